@@ -50,13 +50,14 @@ impl Shared {
         for (peer, pipeline) in self.peers.iter() {
             if *peer != sender {
                 if let Some(pipeline) = pipeline.upgrade() {
-                    let _ = pipeline.write(TaggedString {
+                    pipeline.write(TaggedString {
                         now: msg.now,
                         transport: TransportContext {
                             local_addr: msg.transport.local_addr,
                             peer_addr: *peer,
                             ecn: msg.transport.ecn,
                             protocol: msg.transport.protocol,
+                            interface_index: None,
                         },
                         message: msg.message.clone(),
                     });
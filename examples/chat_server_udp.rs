@@ -50,13 +50,14 @@ impl Shared {
         for (peer, pipeline) in self.peers.iter() {
             if *peer != sender {
                 if let Some(pipeline) = pipeline.upgrade() {
-                    let _ = pipeline.write(TaggedString {
+                    pipeline.write(TaggedString {
                         now: msg.now,
                         transport: TransportContext {
                             local_addr: msg.transport.local_addr,
                             peer_addr: *peer,
                             ecn: msg.transport.ecn,
                             protocol: msg.transport.protocol,
+                            interface_index: None,
                         },
                         message: msg.message.clone(),
                     });
@@ -190,9 +191,11 @@ fn main() -> anyhow::Result<()> {
         bootstrap.pipeline(Box::new(move || {
             let pipeline: Rc<Pipeline<TaggedBytesMut, TaggedString>> = Rc::new(Pipeline::new());
 
-            let line_based_frame_decoder_handler = TaggedByteToMessageCodec::new(Box::new(
-                LineBasedFrameDecoder::new(8192, true, TerminatorType::BOTH),
-            ));
+            // the pipeline serves every peer, so each gets a decoder of its own
+            let line_based_frame_decoder_handler =
+                TaggedByteToMessageCodec::with_factory(Box::new(|| {
+                    Box::new(LineBasedFrameDecoder::new(8192, true, TerminatorType::BOTH))
+                }));
             let string_codec_handler = TaggedStringCodec::new();
             let pipeline_wr = Rc::downgrade(&pipeline);
             let chat_handler = ChatHandler::new(state.clone(), pipeline_wr);
@@ -113,6 +113,7 @@ fn main() -> anyhow::Result<()> {
         peer_addr: SocketAddr::from_str(&format!("{}:{}", host, port))?,
         ecn: None,
         protocol: Protocol::TCP,
+        interface_index: None,
     };
 
     LocalExecutorBuilder::default().run(async move {
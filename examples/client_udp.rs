@@ -101,6 +101,7 @@ fn main() -> anyhow::Result<()> {
         peer_addr: SocketAddr::from_str(&format!("{}:{}", host, port))?,
         ecn: None,
         protocol: Protocol::UDP,
+        interface_index: None,
     };
 
     LocalExecutorBuilder::default().run(async move {
@@ -115,9 +115,11 @@ fn main() -> anyhow::Result<()> {
         bootstrap.pipeline(Box::new(move || {
             let pipeline: Pipeline<TaggedBytesMut, TaggedString> = Pipeline::new();
 
-            let line_based_frame_decoder_handler = TaggedByteToMessageCodec::new(Box::new(
-                LineBasedFrameDecoder::new(8192, true, TerminatorType::BOTH),
-            ));
+            // the pipeline serves every peer, so each gets a decoder of its own
+            let line_based_frame_decoder_handler =
+                TaggedByteToMessageCodec::with_factory(Box::new(|| {
+                    Box::new(LineBasedFrameDecoder::new(8192, true, TerminatorType::BOTH))
+                }));
             let string_codec_handler = TaggedStringCodec::new();
             let echo_handler = EchoHandler::new();
 
@@ -0,0 +1,152 @@
+/// Minimum read size
+pub(crate) const MIN_RECV_SIZE: usize = 64;
+/// Initial read size, unless max payload size is smaller
+pub(crate) const INITIAL_RECV_SIZE: usize = 2048;
+
+const INDEX_INCREMENT: usize = 4;
+const INDEX_DECREMENT: usize = 1;
+
+/// Predicts the size of the next read, like Netty's AdaptiveRecvByteBufAllocator.
+///
+/// It grows quickly when a read fills up the whole guess, and shrinks slowly after
+/// two consecutive reads fit into a smaller size.
+pub(crate) struct AdaptiveRecvSize {
+    size_table: Vec<usize>,
+    min_index: usize,
+    max_index: usize,
+    index: usize,
+    decrease_now: bool,
+}
+
+impl AdaptiveRecvSize {
+    /// Creates a new AdaptiveRecvSize which guesses between `min` and `max` bytes, starting with `initial`
+    pub(crate) fn new(min: usize, initial: usize, max: usize) -> Self {
+        let mut size_table = vec![];
+        let mut size = 16;
+        while size < 512 {
+            size_table.push(size);
+            size += 16;
+        }
+        while size < max {
+            size_table.push(size);
+            size <<= 1;
+        }
+        size_table.push(max);
+        size_table.retain(|&size| size <= max);
+
+        let index_of = |size: usize| {
+            size_table
+                .iter()
+                .position(|&s| s >= size)
+                .unwrap_or(size_table.len() - 1)
+        };
+        let (min_index, max_index) = (index_of(min.min(max)), size_table.len() - 1);
+        let index = index_of(initial).clamp(min_index, max_index);
+
+        Self {
+            size_table,
+            min_index,
+            max_index,
+            index,
+            decrease_now: false,
+        }
+    }
+
+    /// Returns the size of the next read
+    pub(crate) fn guess(&self) -> usize {
+        self.size_table[self.index]
+    }
+
+    /// Records the number of bytes of the last read
+    pub(crate) fn record(&mut self, actual: usize) {
+        let decrease_index = self
+            .index
+            .saturating_sub(INDEX_DECREMENT)
+            .max(self.min_index);
+        if actual <= self.size_table[decrease_index] {
+            if self.decrease_now {
+                self.index = decrease_index;
+                self.decrease_now = false;
+            } else {
+                self.decrease_now = true;
+            }
+        } else if actual >= self.guess() {
+            self.index = (self.index + INDEX_INCREMENT).min(self.max_index);
+            self.decrease_now = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_RECV_SIZE: usize = 65536;
+
+    fn recv_size() -> AdaptiveRecvSize {
+        AdaptiveRecvSize::new(MIN_RECV_SIZE, INITIAL_RECV_SIZE, MAX_RECV_SIZE)
+    }
+
+    #[test]
+    fn test_initial_guess() {
+        assert_eq!(INITIAL_RECV_SIZE, recv_size().guess());
+
+        // initial is clamped to min and max
+        assert_eq!(1000, AdaptiveRecvSize::new(64, 2048, 1000).guess());
+        assert_eq!(512, AdaptiveRecvSize::new(512, 16, 1000).guess());
+    }
+
+    #[test]
+    fn test_grow_on_full_read() {
+        let mut recv_size = recv_size();
+
+        // a read smaller than the guess, but above the next smaller size, keeps it
+        recv_size.record(INITIAL_RECV_SIZE - 1);
+        assert_eq!(INITIAL_RECV_SIZE, recv_size.guess());
+
+        // a read filling the guess grows it by four steps
+        recv_size.record(INITIAL_RECV_SIZE);
+        assert_eq!(32768, recv_size.guess());
+    }
+
+    #[test]
+    fn test_shrink_after_two_small_reads() {
+        let mut recv_size = recv_size();
+
+        // one small read isn't enough
+        recv_size.record(1024);
+        assert_eq!(INITIAL_RECV_SIZE, recv_size.guess());
+
+        // the second one in a row shrinks the guess by one step
+        recv_size.record(1024);
+        assert_eq!(1024, recv_size.guess());
+
+        // a full read in between resets the count
+        recv_size.record(100);
+        recv_size.record(1024);
+        assert_eq!(16384, recv_size.guess());
+        recv_size.record(100);
+        assert_eq!(16384, recv_size.guess());
+        recv_size.record(100);
+        assert_eq!(8192, recv_size.guess());
+    }
+
+    #[test]
+    fn test_clamp_to_min_and_max() {
+        let mut recv_size = recv_size();
+        for _ in 0..100 {
+            recv_size.record(0);
+        }
+        assert_eq!(MIN_RECV_SIZE, recv_size.guess());
+
+        for _ in 0..100 {
+            recv_size.record(usize::MAX);
+        }
+        assert_eq!(MAX_RECV_SIZE, recv_size.guess());
+
+        // max isn't necessarily a size of the table
+        let mut recv_size = AdaptiveRecvSize::new(MIN_RECV_SIZE, INITIAL_RECV_SIZE, 3000);
+        recv_size.record(usize::MAX);
+        assert_eq!(3000, recv_size.guess());
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::SocketAddr,
+    time::Duration,
+};
+
+/// Observes [AcceptEvent]s of the accept loop of [BootstrapTcpServer](crate::bootstrap::BootstrapTcpServer)
+pub type AcceptEventFn = Box<dyn Fn(&AcceptEvent)>;
+
+/// Decides whether a connection from the peer address to the local address, in this order,
+/// is accepted before any pipeline is created for it
+pub type OnAcceptFn = Box<dyn Fn(SocketAddr, SocketAddr) -> AcceptDecision>;
+
+/// Decision of [OnAcceptFn] about an inbound connection
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AcceptDecision {
+    /// Creates a pipeline for the connection
+    Accept,
+    /// Closes the connection right away
+    Reject,
+}
+
+/// Min backoff of accept after a transient error
+pub(crate) const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+/// Max backoff of accept after consecutive transient errors
+pub(crate) const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Events of the accept loop of [BootstrapTcpServer](crate::bootstrap::BootstrapTcpServer)
+#[derive(Debug)]
+pub enum AcceptEvent {
+    /// A connection from the peer address was accepted
+    Accepted(SocketAddr),
+    /// A connection from the peer address was rejected by [OnAcceptFn]
+    Rejected(SocketAddr),
+    /// Accept failed. A transient error, e.g. EMFILE or ECONNABORTED, is retried after
+    /// `retry_in`, any other error stops the listener and `retry_in` is none.
+    Failed {
+        /// Error of accept
+        error: Error,
+        /// Backoff before accept is retried
+        retry_in: Option<Duration>,
+    },
+    /// Accept paused because the number of connections reached `max_connections`
+    Paused,
+    /// Accept resumed after connections were closed
+    Resumed,
+}
+
+/// Returns whether an accept error is worth retrying, e.g. descriptor exhaustion or a
+/// connection aborted before it was accepted
+pub(crate) fn is_transient_accept_error(err: &Error) -> bool {
+    if matches!(
+        err.kind(),
+        ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionReset
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::OutOfMemory
+    ) {
+        return true;
+    }
+
+    #[cfg(unix)]
+    {
+        matches!(
+            err.raw_os_error(),
+            Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM | libc::EPROTO)
+        )
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
@@ -25,6 +25,34 @@ impl<W: 'static> BootstrapTcpClient<W> {
         self
     }
 
+    /// Sets whether a connection stays writable after the peer half-closes it, default is false.
+    /// If false, the connection is closed right after [handle_read_eof](InboundPipeline::handle_read_eof).
+    pub fn allow_half_close(&mut self, allow_half_close: bool) -> &mut Self {
+        self.bootstrap_tcp.allow_half_close(allow_half_close);
+        self
+    }
+
+    /// Sets the timeout of [BootstrapTcpClient::connect] over all attempted addresses, default is none
+    pub fn connect_timeout(&mut self, connect_timeout: Duration) -> &mut Self {
+        self.bootstrap_tcp.connect_timeout(connect_timeout);
+        self
+    }
+
+    /// Reconnects with the given policy when the connection drops, default is no reconnect.
+    /// The pipeline returned by [BootstrapTcpClient::connect] stays the same across reconnects,
+    /// it gets [transport_inactive](InboundPipeline::transport_inactive) when the connection
+    /// drops and [transport_active](InboundPipeline::transport_active) once reconnected. Writes
+    /// while disconnected are buffered up to [ReconnectPolicy::max_buffered_writes] and written
+    /// once reconnected. The client doesn't reconnect after it is stopped or the output of the
+    /// pipeline is shut down. Once it stops reconnecting, e.g. after
+    /// [ReconnectPolicy::max_attempts], buffered writes are dropped and further writes are
+    /// rejected, both reported as `NotConnected` through
+    /// [handle_exception](InboundPipeline::handle_exception).
+    pub fn reconnect(&mut self, reconnect_policy: ReconnectPolicy) -> &mut Self {
+        self.bootstrap_tcp.reconnect(reconnect_policy);
+        self
+    }
+
     /// Creates pipeline instances from when calling [BootstrapTcpClient::connect].
     pub fn pipeline(
         &mut self,
@@ -34,11 +62,20 @@ impl<W: 'static> BootstrapTcpClient<W> {
         self
     }
 
-    /// Connects to the remote peer
+    /// Connects to the remote peer. All resolved addresses are attempted, racing IPv6 and IPv4
+    /// following RFC 8305 (Happy Eyeballs). On failure, the error lists each attempted address
+    /// and why it failed. It can be called many times, [stop](BootstrapTcpClient::stop) closes
+    /// all connections.
     pub async fn connect<A: AsyncToSocketAddrs>(
         &mut self,
         addr: A,
     ) -> Result<Rc<dyn OutboundPipeline<TaggedBytesMut, W>>, Error> {
+        Ok(self.bootstrap_tcp.connect(addr).await?.pipeline())
+    }
+
+    /// Connects to the remote peer like [BootstrapTcpClient::connect], and returns a handle
+    /// to close and wait for this connection only
+    pub async fn open<A: AsyncToSocketAddrs>(&self, addr: A) -> Result<TcpConnection<W>, Error> {
         self.bootstrap_tcp.connect(addr).await
     }
 
@@ -56,4 +93,12 @@ impl<W: 'static> BootstrapTcpClient<W> {
     pub async fn graceful_stop(&self) {
         self.bootstrap_tcp.graceful_stop().await
     }
+
+    /// Gracefully stops the client within the timeout. It delivers a [DrainEvent] to each
+    /// pipeline, which stays open, so that handlers can finish in-flight requests and write e.g. a
+    /// goodbye. A connection is closed once its pipeline is closed and pending writes are flushed,
+    /// and the remaining ones are closed at the timeout.
+    pub async fn graceful_stop_with_timeout(&self, timeout: Duration) {
+        self.bootstrap_tcp.graceful_stop_with_timeout(timeout).await
+    }
 }
@@ -3,6 +3,7 @@ use super::*;
 /// A Bootstrap that makes it easy to bootstrap a pipeline to use for TCP servers.
 pub struct BootstrapTcpServer<W> {
     bootstrap_tcp: BootstrapTcp<W>,
+    listener: RefCell<Option<TcpListener>>,
 }
 
 impl<W: 'static> Default for BootstrapTcpServer<W> {
@@ -16,15 +17,47 @@ impl<W: 'static> BootstrapTcpServer<W> {
     pub fn new() -> Self {
         Self {
             bootstrap_tcp: BootstrapTcp::new(),
+            listener: RefCell::new(None),
         }
     }
 
+    /// Creates a new BootstrapTcpServer which accepts on a listener opened elsewhere, e.g. passed
+    /// with systemd socket activation or inherited from a supervisor, once
+    /// [BootstrapTcpServer::listen] is called
+    pub fn from_std_listener(listener: std::net::TcpListener) -> Result<Self, Error> {
+        let server = Self::new();
+        *server.listener.borrow_mut() = Some(TcpListener::try_from(listener)?);
+        Ok(server)
+    }
+
     /// Sets max payload size, default is 2048 bytes
     pub fn max_payload_size(&mut self, max_payload_size: usize) -> &mut Self {
         self.bootstrap_tcp.max_payload_size(max_payload_size);
         self
     }
 
+    /// Sets whether a connection stays writable after the peer half-closes it, default is false.
+    /// If false, the connection is closed right after [handle_read_eof](InboundPipeline::handle_read_eof).
+    pub fn allow_half_close(&mut self, allow_half_close: bool) -> &mut Self {
+        self.bootstrap_tcp.allow_half_close(allow_half_close);
+        self
+    }
+
+    /// Sets max number of concurrent connections over all listeners, default is unlimited.
+    /// Accepting pauses while the limit is reached, pending connections wait in the listen
+    /// backlog until then.
+    pub fn max_connections(&mut self, max_connections: usize) -> &mut Self {
+        self.bootstrap_tcp.max_connections(max_connections);
+        self
+    }
+
+    /// Observes the accept loop, e.g. accept failures, which are retried with backoff if
+    /// transient, and pauses due to [BootstrapTcpServer::max_connections]
+    pub fn accept_event(&mut self, accept_event_fn: AcceptEventFn) -> &mut Self {
+        self.bootstrap_tcp.accept_event(accept_event_fn);
+        self
+    }
+
     /// Creates pipeline instances from when calling [BootstrapTcpServer::bind].
     pub fn pipeline(
         &mut self,
@@ -34,11 +67,44 @@ impl<W: 'static> BootstrapTcpServer<W> {
         self
     }
 
-    /// Binds local address and port
+    /// Creates pipeline instances with the [TransportContext] of each accepted connection,
+    /// e.g. to add handlers depending on the peer. It takes precedence over [BootstrapTcpServer::pipeline].
+    pub fn pipeline_with_context(
+        &mut self,
+        pipeline_factory_with_context_fn: PipelineFactoryWithContextFn<TaggedBytesMut, W>,
+    ) -> &mut Self {
+        self.bootstrap_tcp
+            .pipeline_with_context(pipeline_factory_with_context_fn);
+        self
+    }
+
+    /// Decides whether to accept each inbound connection before any pipeline is created,
+    /// a rejected connection is closed right away
+    pub fn on_accept(&mut self, on_accept_fn: OnAcceptFn) -> &mut Self {
+        self.bootstrap_tcp.on_accept(on_accept_fn);
+        self
+    }
+
+    /// Binds local address and port. It can be called many times to listen on several addresses,
+    /// all listeners share the pipeline factory and
+    /// [max_connections](BootstrapTcpServer::max_connections), and
+    /// [stop](BootstrapTcpServer::stop) closes all of them.
     pub async fn bind<A: AsyncToSocketAddrs>(&self, addr: A) -> Result<SocketAddr, Error> {
         self.bootstrap_tcp.bind(addr).await
     }
 
+    /// Starts accepting on the listener of [BootstrapTcpServer::from_std_listener], like
+    /// [BootstrapTcpServer::bind] does on a new one
+    pub async fn listen(&self) -> Result<SocketAddr, Error> {
+        let listener = self.listener.borrow_mut().take().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "no listener from from_std_listener to listen on",
+            )
+        })?;
+        self.bootstrap_tcp.serve(listener)
+    }
+
     /// Stops the server
     pub async fn stop(&self) {
         self.bootstrap_tcp.stop().await
@@ -53,4 +119,12 @@ impl<W: 'static> BootstrapTcpServer<W> {
     pub async fn graceful_stop(&self) {
         self.bootstrap_tcp.graceful_stop().await
     }
+
+    /// Gracefully stops the server within the timeout. It stops accepting and delivers a
+    /// [DrainEvent] to each pipeline, which stays open, so that handlers can finish in-flight
+    /// requests and write e.g. a goodbye. A connection is closed once its pipeline is closed and
+    /// pending writes are flushed, and the remaining ones are closed at the timeout.
+    pub async fn graceful_stop_with_timeout(&self, timeout: Duration) {
+        self.bootstrap_tcp.graceful_stop_with_timeout(timeout).await
+    }
 }
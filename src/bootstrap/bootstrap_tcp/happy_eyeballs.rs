@@ -0,0 +1,132 @@
+use crate::executor::spawn_local;
+use futures_lite::future;
+use log::trace;
+use smol::{
+    net::{AsyncToSocketAddrs, TcpStream},
+    Timer,
+};
+use std::{
+    io::{Error, ErrorKind},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+/// Delay before the next connection attempt starts while the previous ones are pending,
+/// the recommended value of RFC 8305
+pub(crate) const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connects to any of the resolved addresses, following RFC 8305 (Happy Eyeballs):
+/// addresses are interleaved by family, and a new attempt starts whenever the previous one
+/// fails or hasn't succeeded within [CONNECTION_ATTEMPT_DELAY]. The first established
+/// connection wins, all other attempts are cancelled.
+pub(crate) async fn connect<A: AsyncToSocketAddrs>(
+    addr: A,
+    timeout: Option<Duration>,
+) -> Result<TcpStream, Error> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let addrs = sort_addrs(addr.to_socket_addrs().await?.collect());
+    if addrs.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "could not resolve to any address",
+        ));
+    }
+
+    let (tx, rx) = smol::channel::unbounded();
+    let mut attempts = vec![];
+    let mut failures: Vec<(SocketAddr, Error)> = vec![];
+    let mut next = 0;
+    loop {
+        if next < addrs.len() {
+            let (addr, tx) = (addrs[next], tx.clone());
+            trace!("connection attempt to {}", addr);
+            attempts.push(spawn_local(async move {
+                let _ = tx.send((addr, TcpStream::connect(addr).await)).await;
+            }));
+            next += 1;
+        } else if failures.len() == addrs.len() {
+            return Err(connect_error(false, failures));
+        }
+
+        let mut timer = match deadline {
+            Some(deadline) if next < addrs.len() => {
+                Timer::at(deadline.min(Instant::now() + CONNECTION_ATTEMPT_DELAY))
+            }
+            Some(deadline) => Timer::at(deadline),
+            None if next < addrs.len() => Timer::after(CONNECTION_ATTEMPT_DELAY),
+            None => Timer::never(),
+        };
+        loop {
+            let result = future::or(async { rx.recv().await.ok() }, async {
+                (&mut timer).await;
+                None
+            })
+            .await;
+
+            match result {
+                Some((addr, Ok(socket))) => {
+                    trace!("connected to {}", addr);
+                    return Ok(socket);
+                }
+                Some((addr, Err(err))) => {
+                    trace!("connection attempt to {} failed: {}", addr, err);
+                    failures.push((addr, err));
+                    // start the next attempt right away, or keep waiting for pending ones
+                    if next < addrs.len() || failures.len() == addrs.len() {
+                        break;
+                    }
+                }
+                None => {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        for addr in &addrs[..next] {
+                            if !failures.iter().any(|(failed, _)| failed == addr) {
+                                failures.push((*addr, Error::from(ErrorKind::TimedOut)));
+                            }
+                        }
+                        return Err(connect_error(true, failures));
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Interleaves IPv6 and IPv4 addresses, starting with the family of the first resolved address
+fn sort_addrs(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+    let first_is_ipv6 = first.is_ipv6();
+    let (preferred, other): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs
+        .iter()
+        .partition(|addr| addr.is_ipv6() == first_is_ipv6);
+
+    let mut sorted = Vec::with_capacity(addrs.len());
+    let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (a, b) => sorted.extend(a.into_iter().chain(b)),
+        }
+    }
+    sorted
+}
+
+fn connect_error(timed_out: bool, failures: Vec<(SocketAddr, Error)>) -> Error {
+    let (kind, mut msg) = if timed_out {
+        (ErrorKind::TimedOut, "connect timed out".to_string())
+    } else {
+        (
+            failures
+                .last()
+                .map_or(ErrorKind::Other, |(_, err)| err.kind()),
+            "could not connect to any address".to_string(),
+        )
+    };
+    for (i, (addr, err)) in failures.iter().enumerate() {
+        msg += if i == 0 { ": " } else { ", " };
+        msg += &format!("{} ({})", addr, err);
+    }
+    Error::new(kind, msg)
+}
@@ -1,15 +1,48 @@
 use super::*;
 use crate::transport::Protocol;
+use accept_event::{
+    is_transient_accept_error, AcceptDecision, AcceptEvent, AcceptEventFn, OnAcceptFn,
+    MAX_ACCEPT_BACKOFF, MIN_ACCEPT_BACKOFF,
+};
+use async_broadcast::TryRecvError;
+use bootstrap_tcp_client::BootstrapTcpClient;
+use bytes::Buf;
+use reconnect_policy::ReconnectPolicy;
 use smol::{
     net::{AsyncToSocketAddrs, TcpListener, TcpStream},
     Timer,
 };
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::io::{ErrorKind, IoSlice};
+use std::net::Shutdown;
+use tcp_connection::TcpConnection;
 
+pub(crate) mod accept_event;
 pub(crate) mod bootstrap_tcp_client;
 pub(crate) mod bootstrap_tcp_server;
+mod happy_eyeballs;
+pub(crate) mod reconnect_policy;
+pub(crate) mod tcp_connection;
+pub(crate) mod tcp_connection_pool;
+
+/// Max number of transmits taken from the pipeline and written at once with a vectored write
+const MAX_IOV_LEN: usize = 64;
 
 struct BootstrapTcp<W> {
     boostrap: Bootstrap<W>,
+
+    allow_half_close: bool,
+    connect_timeout: Option<Duration>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    max_connections: Option<usize>,
+    // shared by all listeners, so that max_connections applies to the server as a whole
+    connections: Rc<Cell<usize>>,
+    conn_closed_tx: async_broadcast::Sender<()>,
+    conn_closed_rx: async_broadcast::InactiveReceiver<()>,
+    accept_event_fn: Option<Rc<AcceptEventFn>>,
+    on_accept_fn: Option<Rc<OnAcceptFn>>,
+    pipeline_factory_with_context_fn: Option<Rc<PipelineFactoryWithContextFn<TaggedBytesMut, W>>>,
 }
 
 impl<W: 'static> Default for BootstrapTcp<W> {
@@ -20,11 +53,63 @@ impl<W: 'static> Default for BootstrapTcp<W> {
 
 impl<W: 'static> BootstrapTcp<W> {
     fn new() -> Self {
+        let (mut conn_closed_tx, conn_closed_rx) = async_broadcast::broadcast(1);
+        // a paused listener only needs to know that some connection was closed since
+        conn_closed_tx.set_overflow(true);
         Self {
             boostrap: Bootstrap::new(),
+
+            allow_half_close: false,
+            connect_timeout: None,
+            reconnect_policy: None,
+            max_connections: None,
+            connections: Rc::new(Cell::new(0)),
+            conn_closed_tx,
+            conn_closed_rx: conn_closed_rx.deactivate(),
+            accept_event_fn: None,
+            on_accept_fn: None,
+            pipeline_factory_with_context_fn: None,
         }
     }
 
+    fn allow_half_close(&mut self, allow_half_close: bool) -> &mut Self {
+        self.allow_half_close = allow_half_close;
+        self
+    }
+
+    fn connect_timeout(&mut self, connect_timeout: Duration) -> &mut Self {
+        self.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    fn reconnect(&mut self, reconnect_policy: ReconnectPolicy) -> &mut Self {
+        self.reconnect_policy = Some(reconnect_policy);
+        self
+    }
+
+    fn max_connections(&mut self, max_connections: usize) -> &mut Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+
+    fn accept_event(&mut self, accept_event_fn: AcceptEventFn) -> &mut Self {
+        self.accept_event_fn = Some(Rc::new(accept_event_fn));
+        self
+    }
+
+    fn on_accept(&mut self, on_accept_fn: OnAcceptFn) -> &mut Self {
+        self.on_accept_fn = Some(Rc::new(on_accept_fn));
+        self
+    }
+
+    fn pipeline_with_context(
+        &mut self,
+        pipeline_factory_with_context_fn: PipelineFactoryWithContextFn<TaggedBytesMut, W>,
+    ) -> &mut Self {
+        self.pipeline_factory_with_context_fn = Some(Rc::new(pipeline_factory_with_context_fn));
+        self
+    }
+
     fn max_payload_size(&mut self, max_payload_size: usize) -> &mut Self {
         self.boostrap.max_payload_size(max_payload_size);
         self
@@ -37,55 +122,126 @@ impl<W: 'static> BootstrapTcp<W> {
 
     async fn bind<A: AsyncToSocketAddrs>(&self, addr: A) -> Result<SocketAddr, Error> {
         let listener = TcpListener::bind(addr).await?;
+        self.serve(listener)
+    }
+
+    /// Accepts connections on the listener in the background
+    fn serve(&self, listener: TcpListener) -> Result<SocketAddr, Error> {
         let local_addr = listener.local_addr()?;
-        let pipeline_factory_fn = Rc::clone(self.boostrap.pipeline_factory_fn.as_ref().unwrap());
+        let pipeline_factory_with_context_fn: Rc<PipelineFactoryWithContextFn<TaggedBytesMut, W>> =
+            match self.pipeline_factory_with_context_fn.as_ref() {
+                Some(pipeline_factory_with_context_fn) => {
+                    Rc::clone(pipeline_factory_with_context_fn)
+                }
+                None => {
+                    let pipeline_factory_fn =
+                        Rc::clone(self.boostrap.pipeline_factory_fn.as_ref().unwrap());
+                    Rc::new(Box::new(move |_: &TransportContext| {
+                        (pipeline_factory_fn)()
+                    }))
+                }
+            };
+        let on_accept_fn = self.on_accept_fn.clone();
 
-        let (close_tx, mut close_rx) = async_broadcast::broadcast(1);
-        {
-            let mut tx = self.boostrap.close_tx.borrow_mut();
-            *tx = Some(close_tx);
-        }
+        let (mut close_rx, mut drain_rx, worker) = self.boostrap.subscribe();
 
-        let worker = {
-            let workgroup = WaitGroup::new();
-            let worker = workgroup.worker();
-            {
-                let mut wg = self.boostrap.wg.borrow_mut();
-                *wg = Some(workgroup);
+        let max_payload_size = self.boostrap.max_payload_size;
+        let allow_half_close = self.allow_half_close;
+        let max_connections = self.max_connections;
+        let connections = Rc::clone(&self.connections);
+        let (conn_closed_tx, mut conn_closed_rx) = (
+            self.conn_closed_tx.clone(),
+            self.conn_closed_rx.activate_cloned(),
+        );
+        let accept_event_fn = self.accept_event_fn.clone();
+        let on_accept_event = move |event: AcceptEvent| {
+            if let Some(accept_event_fn) = accept_event_fn.as_ref() {
+                (accept_event_fn)(&event);
             }
-            worker
         };
 
-        let max_payload_size = self.boostrap.max_payload_size;
-
         spawn_local(async move {
             let _w = worker;
 
             let child_wg = WaitGroup::new();
+            let (mut paused, mut backoff) = (false, MIN_ACCEPT_BACKOFF);
             loop {
+                let at_limit = max_connections.is_some_and(|max| connections.get() >= max);
+                if at_limit != paused {
+                    paused = at_limit;
+                    trace!("listener accept {}", if paused { "paused" } else { "resumed" });
+                    on_accept_event(if paused { AcceptEvent::Paused } else { AcceptEvent::Resumed });
+                }
+
                 tokio::select! {
                     _ = close_rx.recv() => {
                         trace!("listener exit loop");
                         break;
                     }
-                    res = listener.accept() => {
+                    _ = drain_rx.recv() => {
+                        trace!("listener stop accepting");
+                        break;
+                    }
+                    _ = conn_closed_rx.recv() => {}
+                    res = listener.accept(), if !paused => {
                         match res {
-                            Ok((socket, _peer_addr)) => {
+                            Ok((socket, peer_addr)) => {
+                                backoff = MIN_ACCEPT_BACKOFF;
+                                if on_accept_fn.as_ref().is_some_and(|on_accept_fn| {
+                                    (on_accept_fn)(peer_addr, local_addr) == AcceptDecision::Reject
+                                }) {
+                                    trace!("listener reject {}", peer_addr);
+                                    on_accept_event(AcceptEvent::Rejected(peer_addr));
+                                    continue;
+                                }
+                                on_accept_event(AcceptEvent::Accepted(peer_addr));
+
                                 // A new task is spawned for each inbound socket. The socket is
                                 // moved to the new task and processed there.
-                                let pipeline_rd = (pipeline_factory_fn)();
-                                let child_close_rx = close_rx.clone();
+                                let pipeline_rd = (pipeline_factory_with_context_fn)(&TransportContext {
+                                    local_addr,
+                                    peer_addr,
+                                    protocol: Protocol::TCP,
+                                    ..Default::default()
+                                });
+                                let (child_close_rx, child_drain_rx) = (close_rx.clone(), drain_rx.clone());
                                 let child_worker = child_wg.worker();
+                                let (connections, conn_closed_tx) = (Rc::clone(&connections), conn_closed_tx.clone());
+                                connections.set(connections.get() + 1);
                                 spawn_local(async move {
+                                    let _w = child_worker;
                                     let _ = Self::process_pipeline(socket,
                                                                    max_payload_size,
+                                                                   allow_half_close,
                                                                    pipeline_rd,
+                                                                   &mut VecDeque::new(),
                                                                    child_close_rx,
-                                                                   child_worker).await;
+                                                                   child_drain_rx,
+                                                                   None).await;
+                                    connections.set(connections.get() - 1);
+                                    let _ = conn_closed_tx.try_broadcast(());
                                 }).detach();
                             }
+                            Err(err) if is_transient_accept_error(&err) => {
+                                warn!("listener accept error {}, retry in {:?}", err, backoff);
+                                let retry_in = backoff;
+                                backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                                on_accept_event(AcceptEvent::Failed { error: err, retry_in: Some(retry_in) });
+                                tokio::select! {
+                                    _ = close_rx.recv() => {
+                                        trace!("listener exit loop");
+                                        break;
+                                    }
+                                    _ = drain_rx.recv() => {
+                                        trace!("listener stop accepting");
+                                        break;
+                                    }
+                                    _ = Timer::after(retry_in) => {}
+                                }
+                            }
                             Err(err) => {
                                 warn!("listener accept error {}", err);
+                                on_accept_event(AcceptEvent::Failed { error: err, retry_in: None });
                                 break;
                             }
                         }
@@ -99,71 +255,245 @@ impl<W: 'static> BootstrapTcp<W> {
         Ok(local_addr)
     }
 
-    async fn connect<A: AsyncToSocketAddrs>(
-        &self,
-        addr: A,
-    ) -> Result<Rc<dyn OutboundPipeline<TaggedBytesMut, W>>, Error> {
-        let socket = TcpStream::connect(addr).await?;
+    async fn connect<A: AsyncToSocketAddrs>(&self, addr: A) -> Result<TcpConnection<W>, Error> {
+        // resolved once, reconnect attempts go to the same addresses
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs().await?.collect();
+        let connect_timeout = self.connect_timeout;
+        let socket = happy_eyeballs::connect(&addrs[..], connect_timeout).await?;
         let pipeline_factory_fn = Rc::clone(self.boostrap.pipeline_factory_fn.as_ref().unwrap());
 
-        let (close_tx, close_rx) = async_broadcast::broadcast(1);
-        {
-            let mut tx = self.boostrap.close_tx.borrow_mut();
-            *tx = Some(close_tx);
-        }
-
-        let worker = {
-            let workgroup = WaitGroup::new();
-            let worker = workgroup.worker();
-            {
-                let mut wg = self.boostrap.wg.borrow_mut();
-                *wg = Some(workgroup);
-            }
-            worker
-        };
+        let (mut close_rx, mut drain_rx, worker) = self.boostrap.subscribe();
+        let (conn_close_tx, mut conn_close_rx) = async_broadcast::broadcast(1);
+        let conn_wg = WaitGroup::new();
+        let conn_worker = conn_wg.worker();
 
         let pipeline_rd = (pipeline_factory_fn)();
         let pipeline_wr = Rc::clone(&pipeline_rd);
         let max_payload_size = self.boostrap.max_payload_size;
+        let allow_half_close = self.allow_half_close;
+        let reconnect_policy = self.reconnect_policy.clone();
+        // keeps the connection close channel open when the TcpConnection is dropped
+        let task_conn_close_tx = conn_close_tx.clone();
 
         spawn_local(async move {
-            let _ = Self::process_pipeline(socket, max_payload_size, pipeline_rd, close_rx, worker)
+            let (_w, _conn_w, _conn_close_tx) = (worker, conn_worker, task_conn_close_tx);
+            let mut socket = socket;
+            // transmits taken from the pipeline but not written yet, kept across reconnects
+            let mut transmits: VecDeque<BytesMut> = VecDeque::new();
+            loop {
+                let _ = Self::process_pipeline(
+                    socket,
+                    max_payload_size,
+                    allow_half_close,
+                    Rc::clone(&pipeline_rd),
+                    &mut transmits,
+                    close_rx.clone(),
+                    drain_rx.clone(),
+                    Some(conn_close_rx.clone()),
+                )
                 .await;
+
+                // the close signal is delivered to every receiver, including these ones
+                let closed = !matches!(close_rx.try_recv(), Err(TryRecvError::Empty))
+                    || !matches!(drain_rx.try_recv(), Err(TryRecvError::Empty))
+                    || !matches!(conn_close_rx.try_recv(), Err(TryRecvError::Empty));
+                let Some(reconnect_policy) = reconnect_policy.as_ref() else {
+                    break;
+                };
+                if closed || pipeline_rd.is_closed() || pipeline_rd.is_output_shutdown() {
+                    break;
+                }
+
+                match Self::reconnect_with_backoff(
+                    &addrs,
+                    connect_timeout,
+                    reconnect_policy,
+                    &pipeline_rd,
+                    &mut transmits,
+                    &mut close_rx,
+                    &mut drain_rx,
+                    &mut conn_close_rx,
+                )
+                .await
+                {
+                    Some(reconnected) => socket = reconnected,
+                    None => break,
+                }
+            }
+
+            // nothing polls the pipeline anymore, so that further writes are rejected
+            if !transmits.is_empty() {
+                pipeline_rd.handle_exception(Box::new(Error::new(
+                    ErrorKind::NotConnected,
+                    format!("{} writes dropped on disconnect", transmits.len()),
+                )));
+            }
+            pipeline_rd.release();
         })
         .detach();
 
-        Ok(pipeline_wr)
+        Ok(TcpConnection::new(pipeline_wr, conn_close_tx, conn_wg))
+    }
+
+    /// Reconnects with backoff until it succeeds, the policy gives up, or the bootstrap is stopped
+    /// or the connection is closed. Meanwhile, writes to the pipeline are buffered in `transmits`,
+    /// up to [ReconnectPolicy::max_buffered_writes].
+    #[allow(clippy::too_many_arguments)]
+    async fn reconnect_with_backoff(
+        addrs: &[SocketAddr],
+        connect_timeout: Option<Duration>,
+        reconnect_policy: &ReconnectPolicy,
+        pipeline: &Pipeline<TaggedBytesMut, W>,
+        transmits: &mut VecDeque<BytesMut>,
+        close_rx: &mut async_broadcast::Receiver<()>,
+        drain_rx: &mut async_broadcast::Receiver<()>,
+        conn_close_rx: &mut async_broadcast::Receiver<()>,
+    ) -> Option<TcpStream> {
+        let mut write_notify_rx = pipeline.write_notify();
+        let max_buffered_writes = reconnect_policy.max_buffered_writes;
+        Self::buffer_transmits(pipeline, transmits, max_buffered_writes);
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            if reconnect_policy
+                .max_attempts
+                .is_some_and(|max_attempts| attempt > max_attempts)
+            {
+                warn!("giving up reconnecting after {} attempts", attempt - 1);
+                return None;
+            }
+
+            let backoff = reconnect_policy.backoff(attempt);
+            trace!("reconnect attempt {} in {:?}", attempt, backoff);
+            let timer = Timer::after(backoff);
+            futures_lite::pin!(timer);
+            loop {
+                tokio::select! {
+                    _ = close_rx.recv() => return None,
+                    _ = drain_rx.recv() => return None,
+                    _ = conn_close_rx.recv() => return None,
+                    _ = write_notify_rx.recv() => {
+                        Self::buffer_transmits(pipeline, transmits, max_buffered_writes);
+                    }
+                    _ = &mut timer => break,
+                }
+            }
+
+            let connect = happy_eyeballs::connect(addrs, connect_timeout);
+            futures_lite::pin!(connect);
+            loop {
+                tokio::select! {
+                    _ = close_rx.recv() => return None,
+                    _ = drain_rx.recv() => return None,
+                    _ = conn_close_rx.recv() => return None,
+                    _ = write_notify_rx.recv() => {
+                        Self::buffer_transmits(pipeline, transmits, max_buffered_writes);
+                    }
+                    res = &mut connect => {
+                        match res {
+                            Ok(socket) => return Some(socket),
+                            Err(err) => {
+                                warn!("reconnect attempt {} error {}", attempt, err);
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
     }
 
+    /// Takes the writes from the pipeline while disconnected, and drops and reports the ones
+    /// beyond `max_buffered_writes`
+    fn buffer_transmits(
+        pipeline: &Pipeline<TaggedBytesMut, W>,
+        transmits: &mut VecDeque<BytesMut>,
+        max_buffered_writes: usize,
+    ) {
+        let mut dropped = 0;
+        while let Some(transmit) = pipeline.poll_transmit() {
+            if transmits.len() >= max_buffered_writes {
+                dropped += 1;
+            } else if !transmit.message.is_empty() {
+                transmits.push_back(transmit.message);
+            }
+        }
+        if dropped > 0 {
+            trace!("{} writes dropped while reconnecting", dropped);
+            pipeline.handle_exception(Box::new(Error::new(
+                ErrorKind::NotConnected,
+                format!("{} writes dropped while reconnecting", dropped),
+            )));
+        }
+    }
+
+    /// Serves the pipeline on the socket until either is closed. Transmits taken from the pipeline
+    /// but not written yet are left in `transmits`, except a partially written one.
+    #[allow(clippy::too_many_arguments)]
     async fn process_pipeline(
         mut socket: TcpStream,
         max_payload_size: usize,
-        pipeline: Rc<dyn InboundPipeline<TaggedBytesMut>>,
+        allow_half_close: bool,
+        pipeline: Rc<Pipeline<TaggedBytesMut, W>>,
+        transmits: &mut VecDeque<BytesMut>,
         mut close_rx: async_broadcast::Receiver<()>,
-        worker: Worker,
+        mut drain_rx: async_broadcast::Receiver<()>,
+        mut conn_close_rx: Option<async_broadcast::Receiver<()>>,
     ) -> Result<(), Error> {
-        let _w = worker;
+        let mut write_notify_rx = pipeline.write_notify();
 
         let local_addr = socket.local_addr()?;
         let peer_addr = socket.peer_addr()?;
 
-        let mut buf = vec![0u8; max_payload_size];
+        // reads and writes are polled concurrently on the same socket
+        let mut writer = socket.clone();
+        // each read lands in the tail of the buffer and is split off and handed to the pipeline
+        // without copying, its allocation is reclaimed once the pipeline drops all of them. The
+        // tail is zero-filled only where no earlier read has initialized it, `initialized` bytes
+        // from the start of the tail.
+        let mut buf = BytesMut::new();
+        let mut initialized = 0;
+        let mut recv_size = AdaptiveRecvSize::new(
+            MIN_RECV_SIZE,
+            INITIAL_RECV_SIZE.min(max_payload_size),
+            max_payload_size,
+        );
+        // whether the front transmit is partially written, its remainder is useless to a new peer
+        let mut front_written = false;
+        let (mut read_eof, mut output_shutdown, mut draining) = (false, false, false);
 
         pipeline.transport_active();
         loop {
-            // prioritize socket.write than socket.read
-            while let Some(transmit) = pipeline.poll_transmit() {
-                match socket.write(&transmit.message).await {
-                    Ok(n) => {
-                        trace!("socket write {} bytes", n);
-                    }
-                    Err(err) => {
-                        warn!("socket write error {}", err);
-                        break;
-                    }
+            // pull no more than one vectored write, so that the rest stays in the pipeline and
+            // handlers see backpressure while the socket isn't writable
+            while transmits.len() < MAX_IOV_LEN {
+                let Some(transmit) = pipeline.poll_transmit() else {
+                    break;
+                };
+                if !transmit.message.is_empty() {
+                    transmits.push_back(transmit.message);
                 }
             }
 
+            if !output_shutdown && transmits.is_empty() && pipeline.is_output_shutdown() {
+                trace!("socket shutdown output");
+                output_shutdown = true;
+                if let Err(err) = socket.shutdown(Shutdown::Write) {
+                    warn!("socket shutdown error {}", err);
+                    break;
+                }
+            }
+            if read_eof && output_shutdown {
+                trace!("pipeline socket fully closed");
+                break;
+            }
+            // only TCP tears down on a close event, as each connection has a pipeline of its own
+            if transmits.is_empty() && pipeline.is_closed() {
+                trace!("pipeline closed");
+                break;
+            }
+
             let mut eto = Instant::now() + Duration::from_secs(MAX_DURATION_IN_SECS);
             pipeline.poll_timeout(&mut eto);
 
@@ -176,24 +506,98 @@ impl<W: 'static> BootstrapTcp<W> {
             }
 
             let timeout = Timer::after(delay_from_now);
+            // a paused pipeline is polled again once the timeout or a write wakes the loop up
+            let read_paused = pipeline.poll_read_paused();
+            if !read_eof && !read_paused {
+                let guess = recv_size.guess();
+                buf.clear();
+                if buf.capacity() < guess {
+                    buf.reserve(guess);
+                    initialized = 0;
+                }
+                // SAFETY: the first `initialized` bytes of the tail were zero-filled or read into
+                unsafe { buf.set_len(initialized.min(guess)) };
+                buf.resize(guess, 0);
+                initialized = initialized.max(guess);
+            }
+            let iovs: Vec<IoSlice<'_>> = transmits
+                .iter()
+                .map(|transmit| IoSlice::new(transmit))
+                .collect();
 
             tokio::select! {
                 _ = close_rx.recv() => {
                     trace!("pipeline socket exit loop");
                     break;
                 }
+                // only client connections can be closed through their TcpConnection
+                _ = async {
+                    match conn_close_rx.as_mut() {
+                        Some(conn_close_rx) => conn_close_rx.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    trace!("pipeline socket closed");
+                    break;
+                }
+                _ = drain_rx.recv(), if !draining => {
+                    trace!("pipeline socket draining");
+                    draining = true;
+                    pipeline.handle_event(Box::new(DrainEvent));
+                }
                 _ = timeout => {
                     pipeline.handle_timeout(Instant::now());
                 }
-                res = socket.read(&mut buf) => {
+                _ = write_notify_rx.recv() => {
+                    trace!("pipeline written");
+                }
+                // prioritize socket.write than socket.read
+                res = writer.write_vectored(&iovs), if !iovs.is_empty() => {
+                    match res {
+                        Ok(0) => {
+                            let err = Error::new(ErrorKind::WriteZero, "socket write zero bytes");
+                            warn!("socket write error {}", err);
+                            pipeline.handle_exception(Box::new(err));
+                            break;
+                        }
+                        Ok(mut n) => {
+                            trace!("socket write {} bytes", n);
+                            // keep the unwritten remainder for the next write
+                            while n > 0 {
+                                let transmit = transmits.front_mut().unwrap();
+                                if n < transmit.len() {
+                                    transmit.advance(n);
+                                    front_written = true;
+                                    break;
+                                }
+                                n -= transmit.len();
+                                transmits.pop_front();
+                                front_written = false;
+                            }
+                        }
+                        Err(err) => {
+                            warn!("socket write error {}", err);
+                            pipeline.handle_exception(Box::new(err));
+                            break;
+                        }
+                    }
+                }
+                res = socket.read(&mut buf), if !read_eof && !read_paused => {
                     match res {
                         Ok(n) => {
                             if n == 0 {
                                 pipeline.handle_read_eof();
+                                read_eof = true;
+                                if allow_half_close {
+                                    continue;
+                                }
                                 break;
                             }
 
                             trace!("socket read {} bytes", n);
+                            recv_size.record(n);
+                            buf.truncate(n);
+                            initialized -= n;
                             pipeline.read(TaggedBytesMut {
                                     now: Instant::now(),
                                     transport: TransportContext {
@@ -201,8 +605,9 @@ impl<W: 'static> BootstrapTcp<W> {
                                         peer_addr,
                                         ecn: None,
                                         protocol: Protocol::TCP,
+                                        interface_index: None,
                                     },
-                                    message: BytesMut::from(&buf[..n]),
+                                    message: buf.split(),
                                 });
                         }
                         Err(err) => {
@@ -213,6 +618,9 @@ impl<W: 'static> BootstrapTcp<W> {
                 }
             }
         }
+        if front_written {
+            transmits.pop_front();
+        }
         pipeline.transport_inactive();
 
         Ok(())
@@ -229,4 +637,8 @@ impl<W: 'static> BootstrapTcp<W> {
     async fn graceful_stop(&self) {
         self.boostrap.graceful_stop().await
     }
+
+    async fn graceful_stop_with_timeout(&self, timeout: Duration) {
+        self.boostrap.graceful_stop_with_timeout(timeout).await
+    }
 }
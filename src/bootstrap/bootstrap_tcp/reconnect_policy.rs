@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+/// Policy of [BootstrapTcpClient](crate::bootstrap::BootstrapTcpClient) to reconnect after the
+/// connection drops. The backoff before the n-th attempt is `initial_backoff * multiplier^(n-1)`,
+/// randomized by `jitter` and capped at `max_backoff`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Backoff before the first reconnect attempt, default is 100ms
+    pub initial_backoff: Duration,
+    /// Upper bound of the backoff, default is 30s
+    pub max_backoff: Duration,
+    /// Factor the backoff grows by after each failed attempt, default is 2.0
+    pub multiplier: f64,
+    /// Fraction of the backoff it is randomly increased or decreased by, between 0.0 and 1.0,
+    /// default is 0.2
+    pub jitter: f64,
+    /// Max number of consecutive failed attempts before giving up, default is none, i.e. unlimited
+    pub max_attempts: Option<usize>,
+    /// Max number of writes buffered while disconnected, default is 1024. Further writes are
+    /// dropped and reported to the pipeline through
+    /// [handle_exception](crate::channel::InboundPipeline::handle_exception).
+    pub max_buffered_writes: usize,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+            max_buffered_writes: 1024,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Returns the backoff before the given attempt, starting at 1
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as usize) as i32;
+        let backoff = (self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max_backoff.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0) * (2.0 * fastrand::f64() - 1.0);
+        Duration::try_from_secs_f64((backoff * (1.0 + jitter)).max(0.0))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
@@ -0,0 +1,57 @@
+use super::*;
+
+/// A connection opened by [BootstrapTcpClient::open](crate::bootstrap::BootstrapTcpClient::open),
+/// which can be closed and waited for independently of the other connections of the client.
+pub struct TcpConnection<W> {
+    pipeline: Rc<Pipeline<TaggedBytesMut, W>>,
+    close_tx: async_broadcast::Sender<()>,
+    wg: RefCell<Option<WaitGroup>>,
+}
+
+impl<W: 'static> TcpConnection<W> {
+    pub(crate) fn new(
+        pipeline: Rc<Pipeline<TaggedBytesMut, W>>,
+        close_tx: async_broadcast::Sender<()>,
+        wg: WaitGroup,
+    ) -> Self {
+        Self {
+            pipeline,
+            close_tx,
+            wg: RefCell::new(Some(wg)),
+        }
+    }
+
+    /// Returns the pipeline of the connection
+    pub fn pipeline(&self) -> Rc<dyn OutboundPipeline<TaggedBytesMut, W>> {
+        self.pipeline.clone()
+    }
+
+    /// Returns whether the connection is established, i.e. its pipeline hasn't got
+    /// [transport_inactive](InboundPipeline::transport_inactive) since
+    /// [transport_active](InboundPipeline::transport_active)
+    pub fn is_active(&self) -> bool {
+        !self.pipeline.is_inactive()
+    }
+
+    /// Closes the connection, it isn't reconnected afterwards
+    pub fn close(&self) {
+        let _ = self.close_tx.try_broadcast(());
+    }
+
+    /// Waits for the connection to be closed
+    pub async fn wait_for_close(&self) {
+        let wg = {
+            let mut wg = self.wg.borrow_mut();
+            wg.take()
+        };
+        if let Some(wg) = wg {
+            wg.wait().await;
+        }
+    }
+
+    /// Gracefully closes the connection
+    pub async fn graceful_close(&self) {
+        self.close();
+        self.wait_for_close().await;
+    }
+}
@@ -0,0 +1,213 @@
+use super::*;
+use std::collections::HashMap;
+
+/// A pool of outbound TCP connections built on [BootstrapTcpClient], keyed by remote address.
+///
+/// Pipelines are checked out with [TcpConnectionPool::checkout] and handed back with
+/// [TcpConnectionPool::checkin]. A connection is only reused while it is active, i.e. its pipeline
+/// hasn't got [transport_inactive](InboundPipeline::transport_inactive).
+///
+/// The pool doesn't run a timer of its own: the caller must drive it by calling
+/// [TcpConnectionPool::poll_timeout] and [TcpConnectionPool::handle_timeout] at the returned time,
+/// the same way as a [Pipeline]. Only then are idle connections beyond `min_idle` evicted after
+/// `idle_timeout`, connections opened up to `min_idle`, and checked out connections which became
+/// inactive without being checked in released.
+pub struct TcpConnectionPool<W> {
+    client: Rc<BootstrapTcpClient<W>>,
+    min_idle: usize,
+    max_idle: usize,
+    idle_timeout: Duration,
+    idle: Rc<RefCell<HashMap<SocketAddr, VecDeque<IdleConnection<W>>>>>,
+    checked_out: RefCell<Vec<(SocketAddr, TcpConnection<W>)>>,
+    opening: Rc<RefCell<HashMap<SocketAddr, usize>>>,
+}
+
+struct IdleConnection<W> {
+    connection: TcpConnection<W>,
+    idle_since: Instant,
+}
+
+impl<W: 'static> TcpConnectionPool<W> {
+    /// Creates a new TcpConnectionPool which opens connections with the given client
+    pub fn new(client: BootstrapTcpClient<W>) -> Self {
+        Self {
+            client: Rc::new(client),
+            min_idle: 0,
+            max_idle: 8,
+            idle_timeout: Duration::from_secs(60),
+            idle: Rc::new(RefCell::new(HashMap::new())),
+            checked_out: RefCell::new(vec![]),
+            opening: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Sets min number of idle connections per remote address, default is 0
+    pub fn min_idle(&mut self, min_idle: usize) -> &mut Self {
+        self.min_idle = min_idle;
+        self
+    }
+
+    /// Sets max number of idle connections per remote address, default is 8
+    pub fn max_idle(&mut self, max_idle: usize) -> &mut Self {
+        self.max_idle = max_idle;
+        self
+    }
+
+    /// Sets how long a connection can stay idle before it is evicted, default is 60s
+    pub fn idle_timeout(&mut self, idle_timeout: Duration) -> &mut Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Checks out the pipeline of an idle connection to the remote address,
+    /// or of a new connection if none is idle
+    pub async fn checkout(
+        &self,
+        addr: SocketAddr,
+    ) -> Result<Rc<dyn OutboundPipeline<TaggedBytesMut, W>>, Error> {
+        let found = {
+            let mut idle = self.idle.borrow_mut();
+            let connections = idle.entry(addr).or_default();
+            let mut found = None;
+            while let Some(idle_connection) = connections.pop_back() {
+                if idle_connection.connection.is_active() {
+                    found = Some(idle_connection.connection);
+                    break;
+                }
+                idle_connection.connection.close();
+            }
+            found
+        };
+        let connection = match found {
+            Some(connection) => connection,
+            None => self.client.open(addr).await?,
+        };
+
+        let pipeline = connection.pipeline();
+        let mut checked_out = self.checked_out.borrow_mut();
+        checked_out.push((addr, connection));
+        Ok(pipeline)
+    }
+
+    /// Checks in a pipeline from [TcpConnectionPool::checkout]. Its connection is kept idle
+    /// unless it is inactive or there are already `max_idle` idle connections. A pipeline whose
+    /// connection was already released by [TcpConnectionPool::handle_timeout] is ignored.
+    pub fn checkin(&self, pipeline: Rc<dyn OutboundPipeline<TaggedBytesMut, W>>) {
+        let (addr, connection) = {
+            let mut checked_out = self.checked_out.borrow_mut();
+            let Some(index) = checked_out.iter().position(|(_, connection)| {
+                std::ptr::addr_eq(Rc::as_ptr(&connection.pipeline()), Rc::as_ptr(&pipeline))
+            }) else {
+                trace!("checkin of a pipeline which isn't checked out");
+                return;
+            };
+            checked_out.swap_remove(index)
+        };
+
+        let mut idle = self.idle.borrow_mut();
+        let connections = idle.entry(addr).or_default();
+        if !connection.is_active() || connections.len() >= self.max_idle {
+            connection.close();
+        } else {
+            connections.push_back(IdleConnection {
+                connection,
+                idle_since: Instant::now(),
+            });
+        }
+    }
+
+    /// Returns the number of idle connections to the remote address
+    pub fn idle_count(&self, addr: SocketAddr) -> usize {
+        let idle = self.idle.borrow();
+        idle.get(&addr).map_or(0, |connections| connections.len())
+    }
+
+    /// Updates the earliest time the pool needs [TcpConnectionPool::handle_timeout]
+    pub fn poll_timeout(&self, eto: &mut Instant) {
+        let idle = self.idle.borrow();
+        for connections in idle.values() {
+            if connections.len() > self.min_idle {
+                if let Some(oldest) = connections.front() {
+                    *eto = (*eto).min(oldest.idle_since + self.idle_timeout);
+                }
+            }
+        }
+    }
+
+    /// Releases checked out connections which became inactive, evicts inactive connections and
+    /// idle connections beyond `min_idle` older than `idle_timeout`, then opens connections up to
+    /// `min_idle` in the background
+    pub fn handle_timeout(&self, now: Instant) {
+        self.checked_out.borrow_mut().retain(|(addr, connection)| {
+            if !connection.is_active() {
+                trace!("release inactive checked out connection to {}", addr);
+                connection.close();
+            }
+            connection.is_active()
+        });
+
+        let mut idle = self.idle.borrow_mut();
+        for (addr, connections) in idle.iter_mut() {
+            connections.retain(|idle_connection| {
+                if !idle_connection.connection.is_active() {
+                    idle_connection.connection.close();
+                }
+                idle_connection.connection.is_active()
+            });
+            while connections.len() > self.min_idle
+                && connections
+                    .front()
+                    .is_some_and(|oldest| oldest.idle_since + self.idle_timeout <= now)
+            {
+                trace!("evict idle connection to {}", addr);
+                connections.pop_front().unwrap().connection.close();
+            }
+
+            let opening = self.opening.borrow().get(addr).copied().unwrap_or(0);
+            for _ in connections.len() + opening..self.min_idle {
+                self.open_idle(*addr);
+            }
+        }
+    }
+
+    fn open_idle(&self, addr: SocketAddr) {
+        *self.opening.borrow_mut().entry(addr).or_default() += 1;
+        let (client, idle, opening) = (
+            Rc::clone(&self.client),
+            Rc::clone(&self.idle),
+            Rc::clone(&self.opening),
+        );
+        spawn_local(async move {
+            let res = client.open(addr).await;
+            if let Some(count) = opening.borrow_mut().get_mut(&addr) {
+                *count -= 1;
+            }
+            match res {
+                Ok(connection) => {
+                    let mut idle = idle.borrow_mut();
+                    idle.entry(addr).or_default().push_back(IdleConnection {
+                        connection,
+                        idle_since: Instant::now(),
+                    });
+                }
+                Err(err) => warn!("connection pool open {} error {}", addr, err),
+            }
+        })
+        .detach();
+    }
+
+    /// Stops all connections of the pool
+    pub async fn stop(&self) {
+        self.client.stop().await
+    }
+
+    /// Waits for stop of all connections of the pool
+    pub async fn wait_for_stop(&self) {
+        self.client.wait_for_stop().await
+    }
+
+    /// Gracefully stops all connections of the pool
+    pub async fn graceful_stop(&self) {
+        self.client.graceful_stop().await
+    }
+}
@@ -39,7 +39,11 @@ impl<W: 'static> BootstrapUdpClient<W> {
         self.bootstrap_udp.bind(addr).await
     }
 
-    /// Connects to the remote peer
+    /// Connects to the remote peer. Like a connected UDP socket, only datagrams from the peer are
+    /// received, all outbound datagrams are sent to the peer regardless of their
+    /// [TransportContext::peer_addr], and ICMP port unreachable errors are reported to
+    /// the pipeline through [InboundPipeline::handle_exception]. It fails if no socket was bound
+    /// with [BootstrapUdpClient::bind], or if the bound socket is already connected.
     pub async fn connect(
         &mut self,
         addr: SocketAddr,
@@ -47,6 +51,82 @@ impl<W: 'static> BootstrapUdpClient<W> {
         self.bootstrap_udp.connect(Some(addr)).await
     }
 
+    /// Sets the value of the `SO_BROADCAST` option, either before or after bind
+    pub fn set_broadcast(&mut self, broadcast: bool) -> Result<(), Error> {
+        self.bootstrap_udp.set_broadcast(broadcast)
+    }
+
+    /// Sets the value of the `IP_MULTICAST_LOOP` option, either before or after bind
+    pub fn set_multicast_loop_v4(&mut self, multicast_loop_v4: bool) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_loop_v4(multicast_loop_v4)
+    }
+
+    /// Sets the value of the `IP_MULTICAST_TTL` option, either before or after bind
+    pub fn set_multicast_ttl_v4(&mut self, multicast_ttl_v4: u32) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_ttl_v4(multicast_ttl_v4)
+    }
+
+    /// Sets the value of the `IP_MULTICAST_IF` option, i.e. the local interface address
+    /// for outgoing IPv4 multicast datagrams, either before or after bind
+    pub fn set_multicast_if_v4(&mut self, interface: Ipv4Addr) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_if_v4(interface)
+    }
+
+    /// Sets the value of the `IPV6_MULTICAST_LOOP` option, either before or after bind
+    pub fn set_multicast_loop_v6(&mut self, multicast_loop_v6: bool) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_loop_v6(multicast_loop_v6)
+    }
+
+    /// Sets the value of the `IPV6_MULTICAST_HOPS` option, either before or after bind
+    pub fn set_multicast_hops_v6(&mut self, multicast_hops_v6: u32) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_hops_v6(multicast_hops_v6)
+    }
+
+    /// Sets the value of the `IPV6_MULTICAST_IF` option, i.e. the local interface index
+    /// for outgoing IPv6 multicast datagrams, either before or after bind
+    pub fn set_multicast_if_v6(&mut self, interface: u32) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_if_v6(interface)
+    }
+
+    /// Joins an IPv4 multicast group on the interface with address `interface`,
+    /// either before or after bind
+    pub fn join_multicast_v4(
+        &mut self,
+        multiaddr: Ipv4Addr,
+        interface: Ipv4Addr,
+    ) -> Result<(), Error> {
+        self.bootstrap_udp.join_multicast_v4(multiaddr, interface)
+    }
+
+    /// Leaves an IPv4 multicast group on the interface with address `interface`,
+    /// either before or after bind
+    pub fn leave_multicast_v4(
+        &mut self,
+        multiaddr: Ipv4Addr,
+        interface: Ipv4Addr,
+    ) -> Result<(), Error> {
+        self.bootstrap_udp.leave_multicast_v4(multiaddr, interface)
+    }
+
+    /// Joins an IPv6 multicast group on the interface with index `interface`,
+    /// either before or after bind
+    pub fn join_multicast_v6(&mut self, multiaddr: Ipv6Addr, interface: u32) -> Result<(), Error> {
+        self.bootstrap_udp.join_multicast_v6(multiaddr, interface)
+    }
+
+    /// Leaves an IPv6 multicast group on the interface with index `interface`,
+    /// either before or after bind
+    pub fn leave_multicast_v6(&mut self, multiaddr: Ipv6Addr, interface: u32) -> Result<(), Error> {
+        self.bootstrap_udp.leave_multicast_v6(multiaddr, interface)
+    }
+
+    /// Sets the value of the `SO_TIMESTAMPNS` option, either before or after bind. When enabled,
+    /// [Transmit::now](crate::transport::Transmit::now) of inbound datagrams is the kernel receive
+    /// time instead of the time the pipeline is invoked. Only supported on Linux.
+    pub fn set_recv_timestamps(&mut self, recv_timestamps: bool) -> Result<(), Error> {
+        self.bootstrap_udp.set_recv_timestamps(recv_timestamps)
+    }
+
     /// Stops the client
     pub async fn stop(&self) {
         self.bootstrap_udp.stop().await
@@ -61,4 +141,12 @@ impl<W: 'static> BootstrapUdpClient<W> {
     pub async fn graceful_stop(&self) {
         self.bootstrap_udp.graceful_stop().await
     }
+
+    /// Gracefully stops the client within the timeout. It delivers a [DrainEvent] to the pipeline,
+    /// so that handlers can finish in-flight requests and write e.g. a goodbye to the peer. The
+    /// socket is served until the handlers close the pipeline and pending writes are flushed, or
+    /// until the timeout.
+    pub async fn graceful_stop_with_timeout(&self, timeout: Duration) {
+        self.bootstrap_udp.graceful_stop_with_timeout(timeout).await
+    }
 }
@@ -3,6 +3,7 @@ use super::*;
 /// A Bootstrap that makes it easy to bootstrap a pipeline to use for UDP servers.
 pub struct BootstrapUdpServer<W> {
     bootstrap_udp: BootstrapUdp<W>,
+    socket: Option<Rc<UdpSocket>>,
 }
 
 impl<W: 'static> Default for BootstrapUdpServer<W> {
@@ -16,9 +17,19 @@ impl<W: 'static> BootstrapUdpServer<W> {
     pub fn new() -> Self {
         Self {
             bootstrap_udp: BootstrapUdp::new(),
+            socket: None,
         }
     }
 
+    /// Creates a new BootstrapUdpServer which receives on a socket opened elsewhere, e.g. passed
+    /// with systemd socket activation or inherited from a supervisor, once
+    /// [BootstrapUdpServer::listen] is called. Socket options are set on it like on a bound one.
+    pub fn from_std_socket(socket: std::net::UdpSocket) -> Result<Self, Error> {
+        let mut server = Self::new();
+        server.socket = Some(server.bootstrap_udp.adopt(socket)?);
+        Ok(server)
+    }
+
     /// Sets max payload size, default is 2048 bytes
     pub fn max_payload_size(&mut self, max_payload_size: usize) -> &mut Self {
         self.bootstrap_udp.max_payload_size(max_payload_size);
@@ -34,7 +45,9 @@ impl<W: 'static> BootstrapUdpServer<W> {
         self
     }
 
-    /// Binds local address and port
+    /// Binds local address and port. It can be called many times to listen on several addresses,
+    /// each socket gets its own pipeline from the same factory and [stop](BootstrapUdpServer::stop)
+    /// closes all of them.
     pub async fn bind<A: AsyncToSocketAddrs>(&mut self, addr: A) -> Result<SocketAddr, Error> {
         let local_addr = self.bootstrap_udp.bind(addr).await?;
         let peer_addr: Option<SocketAddr> = None;
@@ -42,6 +55,96 @@ impl<W: 'static> BootstrapUdpServer<W> {
         Ok(local_addr)
     }
 
+    /// Starts receiving on the socket of [BootstrapUdpServer::from_std_socket], like
+    /// [BootstrapUdpServer::bind] does on a new one
+    pub async fn listen(&mut self) -> Result<SocketAddr, Error> {
+        let socket = self.socket.take().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "no socket from from_std_socket to listen on",
+            )
+        })?;
+        let local_addr = socket.local_addr()?;
+        self.bootstrap_udp.serve(socket, None).await?;
+        Ok(local_addr)
+    }
+
+    /// Sets the value of the `SO_BROADCAST` option, either before or after bind
+    pub fn set_broadcast(&mut self, broadcast: bool) -> Result<(), Error> {
+        self.bootstrap_udp.set_broadcast(broadcast)
+    }
+
+    /// Sets the value of the `IP_MULTICAST_LOOP` option, either before or after bind
+    pub fn set_multicast_loop_v4(&mut self, multicast_loop_v4: bool) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_loop_v4(multicast_loop_v4)
+    }
+
+    /// Sets the value of the `IP_MULTICAST_TTL` option, either before or after bind
+    pub fn set_multicast_ttl_v4(&mut self, multicast_ttl_v4: u32) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_ttl_v4(multicast_ttl_v4)
+    }
+
+    /// Sets the value of the `IP_MULTICAST_IF` option, i.e. the local interface address
+    /// for outgoing IPv4 multicast datagrams, either before or after bind
+    pub fn set_multicast_if_v4(&mut self, interface: Ipv4Addr) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_if_v4(interface)
+    }
+
+    /// Sets the value of the `IPV6_MULTICAST_LOOP` option, either before or after bind
+    pub fn set_multicast_loop_v6(&mut self, multicast_loop_v6: bool) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_loop_v6(multicast_loop_v6)
+    }
+
+    /// Sets the value of the `IPV6_MULTICAST_HOPS` option, either before or after bind
+    pub fn set_multicast_hops_v6(&mut self, multicast_hops_v6: u32) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_hops_v6(multicast_hops_v6)
+    }
+
+    /// Sets the value of the `IPV6_MULTICAST_IF` option, i.e. the local interface index
+    /// for outgoing IPv6 multicast datagrams, either before or after bind
+    pub fn set_multicast_if_v6(&mut self, interface: u32) -> Result<(), Error> {
+        self.bootstrap_udp.set_multicast_if_v6(interface)
+    }
+
+    /// Joins an IPv4 multicast group on the interface with address `interface`,
+    /// either before or after bind
+    pub fn join_multicast_v4(
+        &mut self,
+        multiaddr: Ipv4Addr,
+        interface: Ipv4Addr,
+    ) -> Result<(), Error> {
+        self.bootstrap_udp.join_multicast_v4(multiaddr, interface)
+    }
+
+    /// Leaves an IPv4 multicast group on the interface with address `interface`,
+    /// either before or after bind
+    pub fn leave_multicast_v4(
+        &mut self,
+        multiaddr: Ipv4Addr,
+        interface: Ipv4Addr,
+    ) -> Result<(), Error> {
+        self.bootstrap_udp.leave_multicast_v4(multiaddr, interface)
+    }
+
+    /// Joins an IPv6 multicast group on the interface with index `interface`,
+    /// either before or after bind
+    pub fn join_multicast_v6(&mut self, multiaddr: Ipv6Addr, interface: u32) -> Result<(), Error> {
+        self.bootstrap_udp.join_multicast_v6(multiaddr, interface)
+    }
+
+    /// Leaves an IPv6 multicast group on the interface with index `interface`,
+    /// either before or after bind
+    pub fn leave_multicast_v6(&mut self, multiaddr: Ipv6Addr, interface: u32) -> Result<(), Error> {
+        self.bootstrap_udp.leave_multicast_v6(multiaddr, interface)
+    }
+
+    /// Sets the value of the `SO_TIMESTAMPNS` option, either before or after bind. When enabled,
+    /// [Transmit::now](crate::transport::Transmit::now) of inbound datagrams is the kernel receive
+    /// time instead of the time the pipeline is invoked. Only supported on Linux.
+    pub fn set_recv_timestamps(&mut self, recv_timestamps: bool) -> Result<(), Error> {
+        self.bootstrap_udp.set_recv_timestamps(recv_timestamps)
+    }
+
     /// Stops the server
     pub async fn stop(&self) {
         self.bootstrap_udp.stop().await
@@ -56,4 +159,12 @@ impl<W: 'static> BootstrapUdpServer<W> {
     pub async fn graceful_stop(&self) {
         self.bootstrap_udp.graceful_stop().await
     }
+
+    /// Gracefully stops the server within the timeout. It delivers a [DrainEvent] to the pipeline,
+    /// so that handlers can finish in-flight requests and write e.g. a goodbye. The socket may be
+    /// shared by many peers, so it is served until the handlers are done with all of them and
+    /// close the pipeline, and pending writes are flushed, or until the timeout.
+    pub async fn graceful_stop_with_timeout(&self, timeout: Duration) {
+        self.bootstrap_udp.graceful_stop_with_timeout(timeout).await
+    }
 }
@@ -1,20 +1,83 @@
 use super::*;
 use crate::transport::Protocol;
-use async_transport::{AsyncUdpSocket, Capabilities, RecvMeta, Transmit, UdpSocket, BATCH_SIZE};
-use std::mem::MaybeUninit;
+use async_transport::BATCH_SIZE;
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddrV6};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::SystemTime;
+use udp_socket::{RecvMeta, Transmit, UdpSocket, UdpSocketOption};
 
 pub(crate) mod bootstrap_udp_client;
 pub(crate) mod bootstrap_udp_server;
+mod udp_socket;
 
-struct BootstrapUdp<W> {
+/// Max UDP payload size of one GSO transmit, i.e. 65535 - 8 (UDP header) - 20 (IPv4 header)
+const MAX_UDP_PAYLOAD_SIZE: usize = 65507;
+
+pub(crate) struct BootstrapUdp<W> {
     boostrap: Bootstrap<W>,
 
-    socket: Option<UdpSocket>,
+    sockets: RefCell<Vec<Rc<UdpSocket>>>,
+    // sockets with a receive loop, each socket is served at most once
+    served_sockets: RefCell<Vec<Rc<UdpSocket>>>,
+    // last value of each option, and joined multicast groups, to be set when binding
+    socket_options: Vec<UdpSocketOption>,
+    multicast_groups_v4: HashSet<(Ipv4Addr, Ipv4Addr)>,
+    multicast_groups_v6: HashSet<(Ipv6Addr, u32)>,
 }
 
-impl<W: 'static> Default for BootstrapUdp<W> {
-    fn default() -> Self {
-        Self::new()
+impl<W: 'static> BootstrapUdp<W> {
+    fn set_broadcast(&mut self, broadcast: bool) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::Broadcast(broadcast))
+    }
+
+    fn set_multicast_loop_v4(&mut self, multicast_loop_v4: bool) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::MulticastLoopV4(multicast_loop_v4))
+    }
+
+    fn set_multicast_ttl_v4(&mut self, multicast_ttl_v4: u32) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::MulticastTtlV4(multicast_ttl_v4))
+    }
+
+    fn set_multicast_if_v4(&mut self, interface: Ipv4Addr) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::MulticastIfV4(interface))
+    }
+
+    fn set_multicast_loop_v6(&mut self, multicast_loop_v6: bool) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::MulticastLoopV6(multicast_loop_v6))
+    }
+
+    fn set_multicast_hops_v6(&mut self, multicast_hops_v6: u32) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::MulticastHopsV6(multicast_hops_v6))
+    }
+
+    fn set_multicast_if_v6(&mut self, interface: u32) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::MulticastIfV6(interface))
+    }
+
+    fn join_multicast_v4(&mut self, multiaddr: Ipv4Addr, interface: Ipv4Addr) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::JoinMulticastV4(multiaddr, interface))
+    }
+
+    fn leave_multicast_v4(
+        &mut self,
+        multiaddr: Ipv4Addr,
+        interface: Ipv4Addr,
+    ) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::LeaveMulticastV4(multiaddr, interface))
+    }
+
+    fn join_multicast_v6(&mut self, multiaddr: Ipv6Addr, interface: u32) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::JoinMulticastV6(multiaddr, interface))
+    }
+
+    fn leave_multicast_v6(&mut self, multiaddr: Ipv6Addr, interface: u32) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::LeaveMulticastV6(multiaddr, interface))
+    }
+
+    fn set_recv_timestamps(&mut self, recv_timestamps: bool) -> Result<(), Error> {
+        self.socket_option(UdpSocketOption::RecvTimestamps(recv_timestamps))
     }
 }
 
@@ -23,7 +86,11 @@ impl<W: 'static> BootstrapUdp<W> {
         Self {
             boostrap: Bootstrap::new(),
 
-            socket: None,
+            sockets: RefCell::new(vec![]),
+            served_sockets: RefCell::new(vec![]),
+            socket_options: vec![],
+            multicast_groups_v4: HashSet::new(),
+            multicast_groups_v6: HashSet::new(),
         }
     }
 
@@ -37,82 +104,145 @@ impl<W: 'static> BootstrapUdp<W> {
         self
     }
 
+    /// Sets a socket option on the bound sockets, and keeps its final state to be set when binding
+    fn socket_option(&mut self, option: UdpSocketOption) -> Result<(), Error> {
+        for socket in self.sockets.borrow().iter() {
+            socket.apply(&option)?;
+        }
+        match option {
+            UdpSocketOption::JoinMulticastV4(multiaddr, interface) => {
+                self.multicast_groups_v4.insert((multiaddr, interface));
+            }
+            UdpSocketOption::LeaveMulticastV4(multiaddr, interface) => {
+                self.multicast_groups_v4.remove(&(multiaddr, interface));
+            }
+            UdpSocketOption::JoinMulticastV6(multiaddr, interface) => {
+                self.multicast_groups_v6.insert((multiaddr, interface));
+            }
+            UdpSocketOption::LeaveMulticastV6(multiaddr, interface) => {
+                self.multicast_groups_v6.remove(&(multiaddr, interface));
+            }
+            _ => {
+                let kind = std::mem::discriminant(&option);
+                match self
+                    .socket_options
+                    .iter_mut()
+                    .find(|socket_option| std::mem::discriminant(*socket_option) == kind)
+                {
+                    Some(socket_option) => *socket_option = option,
+                    None => self.socket_options.push(option),
+                }
+            }
+        }
+        Ok(())
+    }
+
     async fn bind<A: AsyncToSocketAddrs>(&mut self, addr: A) -> Result<SocketAddr, Error> {
         let socket = UdpSocket::bind(addr).await?;
-        let local_addr = socket.local_addr()?;
-        self.socket = Some(socket);
-        Ok(local_addr)
+        self.add_socket(socket)?.local_addr()
     }
 
+    /// Takes a socket opened elsewhere, e.g. inherited from the parent process, like a bound one
+    fn adopt(&mut self, socket: std::net::UdpSocket) -> Result<Rc<UdpSocket>, Error> {
+        let socket = UdpSocket::from_std(socket)?;
+        self.add_socket(socket)
+    }
+
+    fn add_socket(&mut self, socket: UdpSocket) -> Result<Rc<UdpSocket>, Error> {
+        for option in &self.socket_options {
+            socket.apply(option)?;
+        }
+        for &(multiaddr, interface) in &self.multicast_groups_v4 {
+            socket.apply(&UdpSocketOption::JoinMulticastV4(multiaddr, interface))?;
+        }
+        for &(multiaddr, interface) in &self.multicast_groups_v6 {
+            socket.apply(&UdpSocketOption::JoinMulticastV6(multiaddr, interface))?;
+        }
+        let socket = Rc::new(socket);
+        self.sockets.borrow_mut().push(Rc::clone(&socket));
+        Ok(socket)
+    }
+
+    /// Starts a pipeline on the socket bound last
     async fn connect(
         &mut self,
-        _peer_addr: Option<SocketAddr>,
+        peer_addr: Option<SocketAddr>,
+    ) -> Result<Rc<dyn OutboundPipeline<TaggedBytesMut, W>>, Error> {
+        let socket = self.sockets.borrow().last().cloned().ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotConnected,
+                "no bound socket to connect, call bind first",
+            )
+        })?;
+        self.serve(socket, peer_addr).await
+    }
+
+    /// Starts a pipeline on the socket, unless it is already served
+    async fn serve(
+        &mut self,
+        socket: Rc<UdpSocket>,
+        peer_addr: Option<SocketAddr>,
     ) -> Result<Rc<dyn OutboundPipeline<TaggedBytesMut, W>>, Error> {
-        let socket = self.socket.take().unwrap();
+        if self
+            .served_sockets
+            .borrow()
+            .iter()
+            .any(|served_socket| Rc::ptr_eq(served_socket, &socket))
+        {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                "socket is already served by a pipeline, call bind again for another one",
+            ));
+        }
+        if let Some(peer_addr) = peer_addr {
+            socket.connect(peer_addr)?;
+        }
         let local_addr = socket.local_addr()?;
+        self.served_sockets.borrow_mut().push(Rc::clone(&socket));
 
         let pipeline_factory_fn = Rc::clone(self.boostrap.pipeline_factory_fn.as_ref().unwrap());
         let pipeline = (pipeline_factory_fn)();
         let pipeline_wr = Rc::clone(&pipeline);
 
-        let (close_tx, mut close_rx) = async_broadcast::broadcast(1);
-        {
-            let mut tx = self.boostrap.close_tx.borrow_mut();
-            *tx = Some(close_tx);
-        }
-
-        let worker = {
-            let workgroup = WaitGroup::new();
-            let worker = workgroup.worker();
-            {
-                let mut wg = self.boostrap.wg.borrow_mut();
-                *wg = Some(workgroup);
-            }
-            worker
-        };
+        let (mut close_rx, mut drain_rx, worker) = self.boostrap.subscribe();
 
         let max_payload_size = self.boostrap.max_payload_size;
 
         spawn_local(async move {
             let _w = worker;
 
-            let capabilities = Capabilities::new();
-            let buf = vec![0u8; max_payload_size * capabilities.gro_segments() * BATCH_SIZE];
-            let buf_len = buf.len();
-            let mut recv_buf: Box<[u8]> = buf.into();
+            let capabilities = socket.capabilities();
+            // datagrams are read into the spare capacity of their slot, split off and handed to the
+            // pipeline without copying, a slot's allocation is reused once the pipeline drops all
+            // datagrams of it. Every read has room for the largest GRO read, so that the kernel
+            // never cuts off merged datagrams.
+            let recv_size = max_payload_size * capabilities.gro_segments();
+            let mut recv_bufs: Vec<BytesMut> = (0..BATCH_SIZE).map(|_| BytesMut::new()).collect();
             let mut metas = [RecvMeta::default(); BATCH_SIZE];
-            let mut iovs = MaybeUninit::<[std::io::IoSliceMut<'_>; BATCH_SIZE]>::uninit();
-            recv_buf
-                .chunks_mut(buf_len / BATCH_SIZE)
-                .enumerate()
-                .for_each(|(i, buf)| unsafe {
-                    iovs.as_mut_ptr()
-                        .cast::<std::io::IoSliceMut<'_>>()
-                        .add(i)
-                        .write(std::io::IoSliceMut::<'_>::new(buf));
-                });
-            let mut iovs = unsafe { iovs.assume_init() };
+
+            let mut pending: Option<TaggedBytesMut> = None;
+            let mut write_notify_rx = pipeline.write_notify();
+            let mut draining = false;
 
             pipeline.transport_active();
             loop {
                 // prioritize socket.write than socket.read
-                while let Some(msg) = pipeline.poll_transmit() {
-                    let transmit = Transmit {
-                        destination: msg.transport.peer_addr,
-                        ecn: msg.transport.ecn,
-                        contents: msg.message.to_vec(),
-                        segment_size: None,
-                        src_ip: Some(msg.transport.local_addr.ip()),
-                    };
-                    match socket.send(&capabilities, &[transmit]).await {
-                        Ok(_) => {
-                            trace!("socket write {} bytes", msg.message.len());
-                        }
-                        Err(err) => {
-                            warn!("socket write error {}", err);
-                            break;
-                        }
+                loop {
+                    let transmits = Self::poll_transmits(
+                        &*pipeline,
+                        socket.max_gso_segments(),
+                        peer_addr,
+                        &mut pending,
+                    );
+                    if transmits.is_empty() {
+                        break;
                     }
+                    Self::send_transmits(&*pipeline, &socket, transmits).await;
+                }
+                // the socket is shared by every peer, so only a close while draining ends it
+                if draining && pipeline.is_closed() {
+                    trace!("pipeline socket drained");
+                    break;
                 }
 
                 let mut eto = Instant::now() + Duration::from_secs(MAX_DURATION_IN_SECS);
@@ -127,16 +257,26 @@ impl<W: 'static> BootstrapUdp<W> {
                 }
 
                 let timeout = Timer::after(delay_from_now);
+                // a paused pipeline is polled again once the timeout or a write wakes the loop up
+                let read_paused = pipeline.poll_read_paused();
 
                 tokio::select! {
                     _ = close_rx.recv() => {
                         trace!("pipeline socket exit loop");
                         break;
                     }
+                    _ = drain_rx.recv(), if !draining => {
+                        trace!("pipeline socket draining");
+                        draining = true;
+                        pipeline.handle_event(Box::new(DrainEvent));
+                    }
                     _ = timeout => {
                         pipeline.handle_timeout(Instant::now());
                     }
-                    res = socket.recv(&mut iovs, &mut metas) => {
+                    _ = write_notify_rx.recv() => {
+                        trace!("pipeline written");
+                    }
+                    res = socket.recv(&mut recv_bufs, recv_size, &mut metas), if !read_paused => {
                         match res {
                             Ok(n) => {
                                 if n == 0 {
@@ -144,24 +284,55 @@ impl<W: 'static> BootstrapUdp<W> {
                                     break;
                                 }
 
-                                for (meta, buf) in metas.iter().zip(iovs.iter()).take(n) {
-                                    let message: BytesMut = buf[0..meta.len].into();
-                                    if !message.is_empty() {
+                                let (now, system_now) = (Instant::now(), SystemTime::now());
+                                for (meta, recv_buf) in metas.iter().zip(recv_bufs.iter_mut()).take(n) {
+                                    let mut datagrams = recv_buf.split();
+                                    if meta.truncated {
+                                        // the cut-off datagram must not pass for a whole one
+                                        warn!("socket read truncated datagram from {}", meta.addr);
+                                        continue;
+                                    }
+                                    if peer_addr.is_some_and(|peer_addr| peer_addr != meta.addr) {
+                                        trace!("socket read from unconnected peer {}", meta.addr);
+                                        continue;
+                                    }
+
+                                    let transport = TransportContext {
+                                        local_addr: Self::recv_local_addr(
+                                            local_addr,
+                                            meta.dst_ip,
+                                            meta.interface_index,
+                                        ),
+                                        peer_addr: meta.addr,
+                                        ecn: meta.ecn,
+                                        protocol: Protocol::UDP,
+                                        interface_index: meta.interface_index,
+                                    };
+                                    // a kernel timestamp in the past is mapped onto the monotonic clock
+                                    let now = meta
+                                        .timestamp
+                                        .and_then(|timestamp| system_now.duration_since(timestamp).ok())
+                                        .and_then(|elapsed| now.checked_sub(elapsed))
+                                        .unwrap_or(now);
+
+                                    // with GRO, one read may carry several datagrams of stride bytes
+                                    while !datagrams.is_empty() {
+                                        let stride = meta.stride.clamp(1, datagrams.len());
+                                        let message = datagrams.split_to(stride);
                                         trace!("socket read {} bytes", message.len());
-                                        pipeline
-                                            .read(TaggedBytesMut {
-                                                now: Instant::now(),
-                                                transport: TransportContext {
-                                                    local_addr,
-                                                    peer_addr: meta.addr,
-                                                    ecn: meta.ecn,
-                                                    protocol: Protocol::UDP,
-                                                },
-                                                message,
-                                            });
+                                        pipeline.read(TaggedBytesMut {
+                                            now,
+                                            transport,
+                                            message,
+                                        });
                                     }
                                 }
                             }
+                            Err(err) if peer_addr.is_some() && err.kind() == ErrorKind::ConnectionRefused => {
+                                // ICMP port unreachable from the connected peer
+                                trace!("socket read error {}", err);
+                                pipeline.handle_exception(Box::new(err));
+                            }
                             Err(err) => {
                                 warn!("socket read error {}", err);
                                 break;
@@ -177,7 +348,143 @@ impl<W: 'static> BootstrapUdp<W> {
         Ok(pipeline_wr)
     }
 
+    /// Returns the local address an inbound datagram was sent to. When bound to a wildcard address,
+    /// it is the destination IP of the datagram, so that replies leave from the same address on
+    /// multi-homed hosts. Multicast and broadcast destinations can't be used as source address.
+    /// An IPv6 link-local destination carries the index of the interface it arrived on as
+    /// scope id.
+    fn recv_local_addr(
+        local_addr: SocketAddr,
+        dst_ip: Option<IpAddr>,
+        interface_index: Option<u32>,
+    ) -> SocketAddr {
+        match dst_ip {
+            Some(IpAddr::V4(ip)) if ip.is_multicast() || ip.is_broadcast() => local_addr,
+            Some(IpAddr::V6(ip))
+                if ip.is_multicast()
+                    || ip
+                        .to_ipv4_mapped()
+                        .is_some_and(|ip| ip.is_multicast() || ip.is_broadcast()) =>
+            {
+                local_addr
+            }
+            Some(IpAddr::V6(ip)) if ip.is_unicast_link_local() => SocketAddr::V6(
+                SocketAddrV6::new(ip, local_addr.port(), 0, interface_index.unwrap_or(0)),
+            ),
+            Some(ip) => SocketAddr::new(ip, local_addr.port()),
+            None => local_addr,
+        }
+    }
+
+    /// Drains up to [BATCH_SIZE] transmits from the pipeline. Consecutive datagrams of equal size
+    /// to the same destination are coalesced into one GSO transmit of up to `max_gso_segments`
+    /// datagrams. A datagram that doesn't fit into the batch is kept in `pending` for the next
+    /// call. If the socket is connected, all datagrams go to `peer_addr`.
+    fn poll_transmits(
+        pipeline: &dyn InboundPipeline<TaggedBytesMut>,
+        max_gso_segments: usize,
+        peer_addr: Option<SocketAddr>,
+        pending: &mut Option<TaggedBytesMut>,
+    ) -> Vec<Transmit> {
+        let mut transmits: Vec<Transmit> = Vec::with_capacity(BATCH_SIZE);
+        let mut segments: Option<(TransportContext, Vec<BytesMut>, usize)> = None;
+
+        while let Some(mut msg) = pending.take().or_else(|| pipeline.poll_transmit()) {
+            if let Some(peer_addr) = peer_addr {
+                msg.transport.peer_addr = peer_addr;
+            }
+            if let Some((transport, contents, segment_size)) = segments.as_mut() {
+                if *transport == msg.transport
+                    && msg.message.len() == *segment_size
+                    && contents.len() < max_gso_segments
+                    && (contents.len() + 1) * *segment_size <= MAX_UDP_PAYLOAD_SIZE
+                {
+                    contents.push(msg.message);
+                    continue;
+                }
+            }
+            if let Some(segments) = segments.take() {
+                transmits.push(Self::build_transmit(segments));
+                if transmits.len() == BATCH_SIZE {
+                    *pending = Some(msg);
+                    return transmits;
+                }
+            }
+
+            let segment_size = msg.message.len();
+            if segment_size == 0 || max_gso_segments <= 1 {
+                transmits.push(Self::build_transmit((
+                    msg.transport,
+                    vec![msg.message],
+                    segment_size,
+                )));
+                if transmits.len() == BATCH_SIZE {
+                    return transmits;
+                }
+            } else {
+                segments = Some((msg.transport, vec![msg.message], segment_size));
+            }
+        }
+
+        if let Some(segments) = segments {
+            transmits.push(Self::build_transmit(segments));
+        }
+        transmits
+    }
+
+    fn build_transmit(
+        (transport, contents, segment_size): (TransportContext, Vec<BytesMut>, usize),
+    ) -> Transmit {
+        let segment_size = if contents.len() > 1 {
+            Some(segment_size)
+        } else {
+            None
+        };
+        Transmit {
+            destination: transport.peer_addr,
+            ecn: transport.ecn,
+            contents,
+            segment_size,
+            src_ip: Some(transport.local_addr.ip()),
+        }
+    }
+
+    /// Sends all transmits. A GSO transmit which fails as GSO is unsupported is sent again as
+    /// separate datagrams. Any other transmit which can't be sent is dropped, like datagrams lost
+    /// on the way, and its error is reported to the pipeline.
+    async fn send_transmits(
+        pipeline: &dyn InboundPipeline<TaggedBytesMut>,
+        socket: &UdpSocket,
+        mut transmits: Vec<Transmit>,
+    ) {
+        let mut sent = 0;
+        while sent < transmits.len() {
+            match socket.send(&transmits[sent..]).await {
+                Ok(n) => {
+                    for transmit in &transmits[sent..sent + n] {
+                        trace!("socket write {} bytes", transmit.len());
+                    }
+                    sent += n;
+                }
+                Err(err)
+                    if transmits[sent].segment_size.is_some()
+                        && UdpSocket::is_gso_unsupported(&err) =>
+                {
+                    let transmit = transmits.remove(sent);
+                    transmits.splice(sent..sent, transmit.into_datagrams());
+                }
+                Err(err) => {
+                    trace!("socket write error {}", err);
+                    pipeline.handle_exception(Box::new(err));
+                    sent += 1;
+                }
+            }
+        }
+    }
+
     async fn stop(&self) {
+        self.sockets.borrow_mut().clear();
+        self.served_sockets.borrow_mut().clear();
         self.boostrap.stop().await
     }
 
@@ -186,6 +493,13 @@ impl<W: 'static> BootstrapUdp<W> {
     }
 
     async fn graceful_stop(&self) {
-        self.boostrap.graceful_stop().await
+        self.stop().await;
+        self.wait_for_stop().await;
+    }
+
+    async fn graceful_stop_with_timeout(&self, timeout: Duration) {
+        self.boostrap.graceful_stop_with_timeout(timeout).await;
+        self.sockets.borrow_mut().clear();
+        self.served_sockets.borrow_mut().clear();
     }
 }
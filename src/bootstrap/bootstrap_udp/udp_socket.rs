@@ -0,0 +1,763 @@
+use async_transport::{Capabilities, EcnCodepoint, UdpSocketState};
+use bytes::BytesMut;
+use smol::{net::AsyncToSocketAddrs, Async};
+use std::{
+    cell::Cell,
+    io::{Error, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::SystemTime,
+};
+
+/// Metadata of a received datagram, or of several datagrams of `stride` bytes with GRO
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct RecvMeta {
+    /// Source address of the datagram
+    pub(crate) addr: SocketAddr,
+    /// Number of bytes received
+    pub(crate) len: usize,
+    /// Size of each datagram with GRO, otherwise equal to `len`
+    pub(crate) stride: usize,
+    /// ECN bits of the datagram
+    pub(crate) ecn: Option<EcnCodepoint>,
+    /// Destination IP of the datagram, from IP_PKTINFO/IPV6_PKTINFO
+    pub(crate) dst_ip: Option<IpAddr>,
+    /// Index of the interface the datagram arrived on, from IP_PKTINFO/IPV6_PKTINFO
+    pub(crate) interface_index: Option<u32>,
+    /// Kernel receive timestamp, if enabled with SO_TIMESTAMPNS
+    pub(crate) timestamp: Option<SystemTime>,
+    /// Whether the datagram didn't fit into the buffer and was cut off, i.e. MSG_TRUNC
+    pub(crate) truncated: bool,
+}
+
+impl Default for RecvMeta {
+    fn default() -> Self {
+        Self {
+            addr: SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            len: 0,
+            stride: 0,
+            ecn: None,
+            dst_ip: None,
+            interface_index: None,
+            timestamp: None,
+            truncated: false,
+        }
+    }
+}
+
+/// A datagram to send, or several datagrams of `segment_size` bytes with GSO. The payload is kept
+/// in the buffers it was written in, which are sent as one vectored message without copying.
+#[derive(Debug)]
+pub(crate) struct Transmit {
+    /// Destination address of the datagrams
+    pub(crate) destination: SocketAddr,
+    /// ECN bits to set on the datagrams
+    pub(crate) ecn: Option<EcnCodepoint>,
+    /// Payload of the datagrams, in order
+    pub(crate) contents: Vec<BytesMut>,
+    /// Size of each datagram with GSO, `None` for a single datagram
+    pub(crate) segment_size: Option<usize>,
+    /// Source IP of the datagrams, set with IP_PKTINFO/IPV6_PKTINFO
+    pub(crate) src_ip: Option<IpAddr>,
+}
+
+impl Transmit {
+    /// Returns the number of bytes of the payload
+    pub(crate) fn len(&self) -> usize {
+        self.contents.iter().map(|content| content.len()).sum()
+    }
+
+    /// Splits a GSO transmit into a transmit per datagram
+    pub(crate) fn into_datagrams(self) -> impl Iterator<Item = Transmit> {
+        let (destination, ecn, src_ip) = (self.destination, self.ecn, self.src_ip);
+        self.contents.into_iter().map(move |content| Transmit {
+            destination,
+            ecn,
+            contents: vec![content],
+            segment_size: None,
+            src_ip,
+        })
+    }
+}
+
+/// A UDP socket which sends/receives batches of datagrams with ECN information
+/// and gives access to the underlying socket for socket options.
+pub(crate) struct UdpSocket {
+    io: Async<std::net::UdpSocket>,
+    state: UdpSocketState,
+    capabilities: Capabilities,
+    // lowered to 1 once a GSO send fails, as some network adapters don't support it
+    max_gso_segments: Cell<usize>,
+}
+
+impl UdpSocket {
+    pub(crate) async fn bind<A: AsyncToSocketAddrs>(addr: A) -> Result<Self, Error> {
+        let mut last_err = None;
+
+        for addr in addr.to_socket_addrs().await? {
+            match std::net::UdpSocket::bind(addr) {
+                Ok(socket) => return Self::from_std(socket),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "could not bind to any of the addresses",
+            )
+        }))
+    }
+
+    pub(crate) fn from_std(socket: std::net::UdpSocket) -> Result<Self, Error> {
+        UdpSocketState::configure((&socket).into())?;
+        let capabilities = Capabilities::new();
+        let max_gso_segments = Cell::new(capabilities.max_gso_segments());
+        Ok(Self {
+            io: Async::new(socket)?,
+            state: UdpSocketState::new(),
+            capabilities,
+            max_gso_segments,
+        })
+    }
+
+    pub(crate) fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Returns the max number of datagrams of one GSO transmit
+    pub(crate) fn max_gso_segments(&self) -> usize {
+        self.max_gso_segments.get()
+    }
+
+    pub(crate) fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.io.get_ref().local_addr()
+    }
+
+    pub(crate) fn connect(&self, addr: SocketAddr) -> Result<(), Error> {
+        self.io.get_ref().connect(addr)
+    }
+
+    /// Returns whether the error of sending a GSO transmit means that GSO is unsupported, as some
+    /// network adapters don't support it, which only shows as EIO on send
+    pub(crate) fn is_gso_unsupported(err: &Error) -> bool {
+        #[cfg(target_os = "linux")]
+        {
+            err.raw_os_error() == Some(libc::EIO)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = err;
+            false
+        }
+    }
+
+    /// Sends a batch of transmits, and returns how many of them were sent. An error means that
+    /// the first transmit couldn't be sent. Once a GSO transmit fails as
+    /// [unsupported](Self::is_gso_unsupported), GSO is disabled.
+    pub(crate) async fn send(&self, transmits: &[Transmit]) -> Result<usize, Error> {
+        #[cfg(target_os = "linux")]
+        {
+            let res = self.io.write_with(|io| linux::send(io, transmits)).await;
+            if let Err(err) = &res {
+                if transmits[0].segment_size.is_some()
+                    && Self::is_gso_unsupported(err)
+                    && self.max_gso_segments.replace(1) > 1
+                {
+                    warn!("socket send error {}, disabling GSO", err);
+                }
+            }
+            res
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            // without sendmmsg, each payload is copied into one contiguous buffer
+            let transport_transmits: Vec<async_transport::Transmit> = transmits
+                .iter()
+                .map(|transmit| async_transport::Transmit {
+                    destination: transmit.destination,
+                    ecn: transmit.ecn,
+                    contents: transmit.contents.concat(),
+                    segment_size: transmit.segment_size,
+                    src_ip: transmit.src_ip,
+                })
+                .collect();
+            self.io
+                .write_with(|io| {
+                    self.state
+                        .send(io.into(), &self.capabilities, &transport_transmits)
+                })
+                .await
+        }
+    }
+
+    /// Receives a batch of datagrams, each into `recv_size` bytes of room reserved at the end of
+    /// its buffer, and returns how many were received. The buffers of received datagrams hold
+    /// exactly their bytes, the others are left empty.
+    pub(crate) async fn recv(
+        &self,
+        bufs: &mut [BytesMut],
+        recv_size: usize,
+        metas: &mut [RecvMeta],
+    ) -> Result<usize, Error> {
+        for buf in bufs.iter_mut() {
+            buf.clear();
+        }
+        #[cfg(target_os = "linux")]
+        {
+            // the kernel writes into the spare capacity, which is never exposed as a slice
+            self.io
+                .read_with(|io| linux::recv(io, bufs, recv_size, metas))
+                .await
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            for buf in bufs.iter_mut() {
+                buf.resize(recv_size, 0);
+            }
+            let mut iovs: Vec<std::io::IoSliceMut<'_>> = bufs
+                .iter_mut()
+                .map(|buf| std::io::IoSliceMut::new(&mut buf[..]))
+                .collect();
+            let mut transport_metas =
+                [async_transport::RecvMeta::default(); async_transport::BATCH_SIZE];
+            let res = self
+                .io
+                .read_with(|io| self.state.recv(io.into(), &mut iovs, &mut transport_metas))
+                .await;
+            let n = *res.as_ref().unwrap_or(&0);
+            for (i, buf) in bufs.iter_mut().enumerate() {
+                buf.truncate(if i < n { transport_metas[i].len } else { 0 });
+            }
+            for (meta, transport_meta) in metas.iter_mut().zip(transport_metas.iter()).take(n) {
+                *meta = RecvMeta {
+                    addr: transport_meta.addr,
+                    len: transport_meta.len,
+                    stride: transport_meta.stride,
+                    ecn: transport_meta.ecn,
+                    dst_ip: transport_meta.dst_ip,
+                    interface_index: None,
+                    timestamp: None,
+                    truncated: false,
+                };
+            }
+            res
+        }
+    }
+
+    pub(crate) fn apply(&self, option: &UdpSocketOption) -> Result<(), Error> {
+        let socket = self.io.get_ref();
+        let sock_ref = socket2::SockRef::from(socket);
+        match *option {
+            UdpSocketOption::Broadcast(on) => socket.set_broadcast(on),
+            UdpSocketOption::MulticastLoopV4(on) => socket.set_multicast_loop_v4(on),
+            UdpSocketOption::MulticastTtlV4(ttl) => socket.set_multicast_ttl_v4(ttl),
+            UdpSocketOption::MulticastIfV4(interface) => sock_ref.set_multicast_if_v4(&interface),
+            UdpSocketOption::MulticastLoopV6(on) => socket.set_multicast_loop_v6(on),
+            UdpSocketOption::MulticastHopsV6(hops) => sock_ref.set_multicast_hops_v6(hops),
+            UdpSocketOption::MulticastIfV6(interface) => sock_ref.set_multicast_if_v6(interface),
+            UdpSocketOption::JoinMulticastV4(multiaddr, interface) => {
+                socket.join_multicast_v4(&multiaddr, &interface)
+            }
+            UdpSocketOption::LeaveMulticastV4(multiaddr, interface) => {
+                socket.leave_multicast_v4(&multiaddr, &interface)
+            }
+            UdpSocketOption::JoinMulticastV6(multiaddr, interface) => {
+                socket.join_multicast_v6(&multiaddr, interface)
+            }
+            UdpSocketOption::LeaveMulticastV6(multiaddr, interface) => {
+                socket.leave_multicast_v6(&multiaddr, interface)
+            }
+            #[cfg(target_os = "linux")]
+            UdpSocketOption::RecvTimestamps(on) => linux::set_recv_timestamps(socket, on),
+            #[cfg(not(target_os = "linux"))]
+            UdpSocketOption::RecvTimestamps(_) => Err(Error::new(
+                ErrorKind::Unsupported,
+                "receive timestamps are only supported on linux",
+            )),
+        }
+    }
+}
+
+/// Socket options which can be set on a UDP bootstrap before or after bind
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum UdpSocketOption {
+    Broadcast(bool),
+    MulticastLoopV4(bool),
+    MulticastTtlV4(u32),
+    MulticastIfV4(Ipv4Addr),
+    MulticastLoopV6(bool),
+    MulticastHopsV6(u32),
+    MulticastIfV6(u32),
+    JoinMulticastV4(Ipv4Addr, Ipv4Addr),
+    LeaveMulticastV4(Ipv4Addr, Ipv4Addr),
+    JoinMulticastV6(Ipv6Addr, u32),
+    LeaveMulticastV6(Ipv6Addr, u32),
+    RecvTimestamps(bool),
+}
+
+#[cfg(target_os = "linux")]
+use log::warn;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{RecvMeta, Transmit};
+    use async_transport::{EcnCodepoint, BATCH_SIZE};
+    use bytes::BytesMut;
+    use log::warn;
+    use std::{
+        io::{Error, ErrorKind},
+        mem::{self, MaybeUninit},
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+        os::fd::AsRawFd,
+        ptr,
+        time::{Duration, SystemTime},
+    };
+
+    /// Room for IP_TOS/IPV6_TCLASS, IP_PKTINFO/IPV6_PKTINFO, UDP_GRO and SCM_TIMESTAMPNS, which
+    /// take up to 120 bytes, with some headroom
+    const CMSG_LEN: usize = 256;
+
+    #[derive(Copy, Clone)]
+    #[repr(align(8))]
+    struct Aligned([u8; CMSG_LEN]);
+
+    pub(super) fn set_recv_timestamps(socket: &std::net::UdpSocket, on: bool) -> Result<(), Error> {
+        let value = libc::c_int::from(on);
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_TIMESTAMPNS,
+                &value as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret == -1 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn send(
+        socket: &std::net::UdpSocket,
+        transmits: &[Transmit],
+    ) -> Result<usize, Error> {
+        let count = transmits.len().min(BATCH_SIZE);
+        let transmits = &transmits[..count];
+        let names: Vec<socket2::SockAddr> = transmits
+            .iter()
+            .map(|transmit| socket2::SockAddr::from(transmit.destination))
+            .collect();
+        // the iovecs of all messages, each message points to its own range of them
+        let iovs: Vec<libc::iovec> = transmits
+            .iter()
+            .flat_map(|transmit| transmit.contents.iter())
+            .map(|content| libc::iovec {
+                iov_base: content.as_ptr() as *mut libc::c_void,
+                iov_len: content.len(),
+            })
+            .collect();
+        let mut ctrls = [Aligned([0u8; CMSG_LEN]); BATCH_SIZE];
+        let mut hdrs = unsafe { mem::zeroed::<[libc::mmsghdr; BATCH_SIZE]>() };
+        let mut iov_start = 0;
+        for (((hdr, transmit), name), ctrl) in hdrs
+            .iter_mut()
+            .zip(transmits.iter())
+            .zip(names.iter())
+            .zip(ctrls.iter_mut())
+        {
+            // sendmmsg doesn't write through any of these pointers
+            hdr.msg_hdr.msg_name = name.as_ptr() as *mut libc::c_void;
+            hdr.msg_hdr.msg_namelen = name.len();
+            hdr.msg_hdr.msg_iov = iovs[iov_start..].as_ptr() as *mut libc::iovec;
+            hdr.msg_hdr.msg_iovlen = transmit.contents.len() as _;
+            iov_start += transmit.contents.len();
+            hdr.msg_hdr.msg_control = ctrl.0.as_mut_ptr() as *mut libc::c_void;
+            hdr.msg_hdr.msg_controllen = CMSG_LEN as _;
+            encode(&mut hdr.msg_hdr, transmit);
+        }
+
+        loop {
+            let n = unsafe {
+                libc::sendmmsg(
+                    socket.as_raw_fd(),
+                    hdrs.as_mut_ptr(),
+                    count as libc::c_uint,
+                    0,
+                )
+            };
+            if n >= 0 {
+                return Ok(n as usize);
+            }
+            let err = Error::last_os_error();
+            if err.kind() != ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    /// Encodes ECN, GSO segment size and source IP of the transmit as control messages
+    fn encode(hdr: &mut libc::msghdr, transmit: &Transmit) {
+        let mut len = 0;
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(hdr) };
+        let mut push = |level: libc::c_int, ty: libc::c_int, data: &[u8]| {
+            let space = unsafe { libc::CMSG_SPACE(data.len() as _) } as usize;
+            assert!(!cmsg.is_null() && len + space <= CMSG_LEN);
+            unsafe {
+                (*cmsg).cmsg_level = level;
+                (*cmsg).cmsg_type = ty;
+                (*cmsg).cmsg_len = libc::CMSG_LEN(data.len() as _) as _;
+                ptr::copy_nonoverlapping(data.as_ptr(), libc::CMSG_DATA(cmsg), data.len());
+            }
+            len += space;
+            cmsg = unsafe { libc::CMSG_NXTHDR(hdr, cmsg) };
+        };
+
+        let ecn = transmit.ecn.map_or(0, |ecn| ecn as libc::c_int);
+        match transmit.destination {
+            SocketAddr::V4(_) => push(libc::IPPROTO_IP, libc::IP_TOS, &ecn.to_ne_bytes()),
+            SocketAddr::V6(_) => push(libc::IPPROTO_IPV6, libc::IPV6_TCLASS, &ecn.to_ne_bytes()),
+        }
+        if let Some(segment_size) = transmit.segment_size {
+            push(
+                libc::SOL_UDP,
+                libc::UDP_SEGMENT,
+                &(segment_size as u16).to_ne_bytes(),
+            );
+        }
+        match transmit.src_ip {
+            Some(IpAddr::V4(ip)) => {
+                let pktinfo = libc::in_pktinfo {
+                    ipi_ifindex: 0,
+                    ipi_spec_dst: libc::in_addr {
+                        s_addr: u32::from_ne_bytes(ip.octets()),
+                    },
+                    ipi_addr: libc::in_addr { s_addr: 0 },
+                };
+                push(libc::IPPROTO_IP, libc::IP_PKTINFO, as_bytes(&pktinfo));
+            }
+            Some(IpAddr::V6(ip)) => {
+                let pktinfo = libc::in6_pktinfo {
+                    ipi6_ifindex: 0,
+                    ipi6_addr: libc::in6_addr {
+                        s6_addr: ip.octets(),
+                    },
+                };
+                push(libc::IPPROTO_IPV6, libc::IPV6_PKTINFO, as_bytes(&pktinfo));
+            }
+            None => {}
+        }
+        hdr.msg_controllen = len as _;
+    }
+
+    fn as_bytes<T>(value: &T) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) }
+    }
+
+    pub(super) fn recv(
+        socket: &std::net::UdpSocket,
+        bufs: &mut [BytesMut],
+        recv_size: usize,
+        metas: &mut [RecvMeta],
+    ) -> Result<usize, Error> {
+        let count = bufs.len().min(metas.len()).min(BATCH_SIZE);
+        let mut iovs = [libc::iovec {
+            iov_base: ptr::null_mut(),
+            iov_len: 0,
+        }; BATCH_SIZE];
+        let mut names = [MaybeUninit::<libc::sockaddr_storage>::zeroed(); BATCH_SIZE];
+        let mut ctrls = [Aligned([0u8; CMSG_LEN]); BATCH_SIZE];
+        let mut hdrs = unsafe { mem::zeroed::<[libc::mmsghdr; BATCH_SIZE]>() };
+        for ((((hdr, buf), iov), name), ctrl) in hdrs
+            .iter_mut()
+            .zip(bufs.iter_mut())
+            .zip(iovs.iter_mut())
+            .zip(names.iter_mut())
+            .zip(ctrls.iter_mut())
+        {
+            buf.reserve(recv_size);
+            let spare = buf.spare_capacity_mut();
+            iov.iov_base = spare.as_mut_ptr() as *mut libc::c_void;
+            iov.iov_len = recv_size;
+            hdr.msg_hdr.msg_name = name.as_mut_ptr() as *mut libc::c_void;
+            hdr.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            hdr.msg_hdr.msg_iov = iov;
+            hdr.msg_hdr.msg_iovlen = 1;
+            hdr.msg_hdr.msg_control = ctrl.0.as_mut_ptr() as *mut libc::c_void;
+            hdr.msg_hdr.msg_controllen = CMSG_LEN as _;
+        }
+
+        let n = loop {
+            let n = unsafe {
+                libc::recvmmsg(
+                    socket.as_raw_fd(),
+                    hdrs.as_mut_ptr(),
+                    count as libc::c_uint,
+                    0,
+                    ptr::null_mut(),
+                )
+            };
+            if n >= 0 {
+                break n as usize;
+            }
+            let err = Error::last_os_error();
+            if err.kind() != ErrorKind::Interrupted {
+                return Err(err);
+            }
+        };
+
+        for (((meta, hdr), name), buf) in metas
+            .iter_mut()
+            .zip(hdrs.iter())
+            .zip(names.iter())
+            .zip(bufs.iter_mut())
+            .take(n)
+        {
+            // the kernel initialized msg_len bytes of the reserved room
+            let len = hdr.msg_len as usize;
+            unsafe { buf.set_len(len) };
+            *meta = decode(name, &hdr.msg_hdr, len)?;
+        }
+        Ok(n)
+    }
+
+    fn decode(
+        name: &MaybeUninit<libc::sockaddr_storage>,
+        hdr: &libc::msghdr,
+        len: usize,
+    ) -> Result<RecvMeta, Error> {
+        let addr = unsafe { socket2::SockAddr::new(name.assume_init(), hdr.msg_namelen) }
+            .as_socket()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid source address"))?;
+        let mut meta = RecvMeta {
+            addr,
+            len,
+            stride: len,
+            ..Default::default()
+        };
+        meta.truncated = hdr.msg_flags & libc::MSG_TRUNC != 0;
+        if hdr.msg_flags & libc::MSG_CTRUNC != 0 {
+            // the ancillary data which did fit is still decoded
+            warn!(
+                "control messages of datagram from {} truncated to {} bytes",
+                addr, hdr.msg_controllen
+            );
+        }
+
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(hdr) };
+        while !cmsg.is_null() {
+            let (level, ty, data) =
+                unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type, libc::CMSG_DATA(cmsg)) };
+            match (level, ty) {
+                (libc::IPPROTO_IP, libc::IP_TOS) => {
+                    meta.ecn = EcnCodepoint::from_bits(unsafe { *data });
+                }
+                (libc::IPPROTO_IPV6, libc::IPV6_TCLASS) => {
+                    let tclass = unsafe { ptr::read_unaligned(data as *const libc::c_int) };
+                    meta.ecn = EcnCodepoint::from_bits(tclass as u8);
+                }
+                (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                    let pktinfo = unsafe { ptr::read_unaligned(data as *const libc::in_pktinfo) };
+                    meta.dst_ip = Some(IpAddr::V4(Ipv4Addr::from(
+                        pktinfo.ipi_addr.s_addr.to_ne_bytes(),
+                    )));
+                    meta.interface_index = Some(pktinfo.ipi_ifindex as u32);
+                }
+                (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                    let pktinfo = unsafe { ptr::read_unaligned(data as *const libc::in6_pktinfo) };
+                    meta.dst_ip = Some(IpAddr::V6(Ipv6Addr::from(pktinfo.ipi6_addr.s6_addr)));
+                    meta.interface_index = Some(pktinfo.ipi6_ifindex);
+                }
+                (libc::SOL_UDP, libc::UDP_GRO) => {
+                    let stride = unsafe { ptr::read_unaligned(data as *const libc::c_int) };
+                    meta.stride = stride as usize;
+                }
+                (libc::SOL_SOCKET, libc::SCM_TIMESTAMPNS) => {
+                    let ts = unsafe { ptr::read_unaligned(data as *const libc::timespec) };
+                    meta.timestamp = SystemTime::UNIX_EPOCH
+                        .checked_add(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32));
+                }
+                _ => {}
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(hdr, cmsg) };
+        }
+
+        Ok(meta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transmit(contents: &[&str], segment_size: Option<usize>) -> Transmit {
+        Transmit {
+            destination: "127.0.0.1:4000".parse().unwrap(),
+            ecn: EcnCodepoint::from_bits(1),
+            contents: contents
+                .iter()
+                .map(|content| BytesMut::from(*content))
+                .collect(),
+            segment_size,
+            src_ip: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        }
+    }
+
+    #[test]
+    fn test_into_datagrams() {
+        let datagrams: Vec<Transmit> = transmit(&["ab", "cd", "e"], Some(2))
+            .into_datagrams()
+            .collect();
+        assert_eq!(3, datagrams.len());
+        for (datagram, content) in datagrams.iter().zip(["ab", "cd", "e"]) {
+            assert_eq!(vec![BytesMut::from(content)], datagram.contents);
+            assert_eq!(None, datagram.segment_size);
+            assert_eq!(EcnCodepoint::from_bits(1), datagram.ecn);
+            assert_eq!(Some(IpAddr::V4(Ipv4Addr::LOCALHOST)), datagram.src_ip);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    mod linux {
+        use super::*;
+        use async_transport::BATCH_SIZE;
+        use std::time::Duration;
+
+        /// Returns a plain socket receiving each datagram on its own, and a socket sending to it
+        fn pair(ip: IpAddr) -> Option<(std::net::UdpSocket, UdpSocket)> {
+            let receiver = std::net::UdpSocket::bind((ip, 0)).ok()?;
+            receiver
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let sender = UdpSocket::from_std(std::net::UdpSocket::bind((ip, 0)).unwrap()).unwrap();
+            Some((receiver, sender))
+        }
+
+        fn to(receiver: &std::net::UdpSocket, mut transmit: Transmit) -> Transmit {
+            transmit.destination = receiver.local_addr().unwrap();
+            transmit.src_ip = Some(transmit.destination.ip());
+            transmit
+        }
+
+        fn recv_datagram(receiver: &std::net::UdpSocket) -> Vec<u8> {
+            let mut buf = [0u8; 1024];
+            let n = receiver.recv(&mut buf).unwrap();
+            buf[..n].to_vec()
+        }
+
+        #[test]
+        fn test_send_more_than_batch_size() {
+            let (receiver, sender) = pair(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+            let transmits: Vec<Transmit> = (0..BATCH_SIZE + 2)
+                .map(|i| to(&receiver, transmit(&[&i.to_string()], None)))
+                .collect();
+
+            // only the first batch is sent at once
+            let sent = smol::block_on(sender.send(&transmits)).unwrap();
+            assert_eq!(BATCH_SIZE, sent);
+            for i in 0..BATCH_SIZE {
+                assert_eq!(i.to_string().into_bytes(), recv_datagram(&receiver));
+            }
+            assert_eq!(2, smol::block_on(sender.send(&transmits[sent..])).unwrap());
+        }
+
+        #[test]
+        fn test_send_vectored_and_empty() {
+            let (receiver, sender) = pair(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+            let transmits = vec![
+                to(&receiver, transmit(&["ab", "", "cdef"], None)),
+                to(&receiver, transmit(&[""], None)),
+                to(&receiver, transmit(&[], None)),
+            ];
+
+            // the buffers of a transmit form one datagram
+            assert_eq!(3, smol::block_on(sender.send(&transmits)).unwrap());
+            assert_eq!(b"abcdef".to_vec(), recv_datagram(&receiver));
+            assert!(recv_datagram(&receiver).is_empty());
+            assert!(recv_datagram(&receiver).is_empty());
+        }
+
+        #[test]
+        fn test_send_gso_with_short_last_segment() {
+            let (receiver, sender) = pair(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+            if sender.max_gso_segments() <= 1 {
+                return;
+            }
+            let transmits = vec![to(&receiver, transmit(&["aaaa", "bbbb", "cc"], Some(4)))];
+
+            assert_eq!(1, smol::block_on(sender.send(&transmits)).unwrap());
+            for datagram in ["aaaa", "bbbb", "cc"] {
+                assert_eq!(datagram.as_bytes(), recv_datagram(&receiver));
+            }
+        }
+
+        #[test]
+        fn test_send_recv_ipv6_control_messages() {
+            // every control message is encoded, on IPv6 they take the most room
+            let Some((receiver, sender)) = pair(IpAddr::V6(Ipv6Addr::LOCALHOST)) else {
+                return;
+            };
+            let receiver = UdpSocket::from_std(receiver).unwrap();
+            let segment_size = if sender.max_gso_segments() > 1 {
+                Some(2)
+            } else {
+                None
+            };
+            let mut transmit = transmit(&["ab"], segment_size);
+            transmit.destination = receiver.local_addr().unwrap();
+            transmit.src_ip = Some(IpAddr::V6(Ipv6Addr::LOCALHOST));
+            receiver
+                .apply(&UdpSocketOption::RecvTimestamps(true))
+                .unwrap();
+
+            assert_eq!(1, smol::block_on(sender.send(&[transmit])).unwrap());
+            let mut bufs = vec![BytesMut::from("stale"), BytesMut::new()];
+            let mut metas = [RecvMeta::default(); 2];
+            let n = smol::block_on(receiver.recv(&mut bufs, 64, &mut metas)).unwrap();
+
+            assert_eq!(1, n);
+            assert_eq!(&b"ab"[..], &bufs[0][..]);
+            assert!(bufs[1].is_empty());
+            let meta = metas[0];
+            assert_eq!(sender.local_addr().unwrap(), meta.addr);
+            assert_eq!((2, 2), (meta.len, meta.stride));
+            assert_eq!(EcnCodepoint::from_bits(1), meta.ecn);
+            assert_eq!(Some(IpAddr::V6(Ipv6Addr::LOCALHOST)), meta.dst_ip);
+            assert!(meta.interface_index.is_some());
+            assert!(meta.timestamp.is_some());
+            assert!(!meta.truncated);
+        }
+
+        #[test]
+        fn test_recv_truncated() {
+            let (receiver, sender) = pair(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+            let receiver = UdpSocket::from_std(receiver).unwrap();
+            let transmits = vec![
+                Transmit {
+                    destination: receiver.local_addr().unwrap(),
+                    ..transmit(&["too long"], None)
+                },
+                Transmit {
+                    destination: receiver.local_addr().unwrap(),
+                    ..transmit(&["fits"], None)
+                },
+            ];
+            assert_eq!(2, smol::block_on(sender.send(&transmits)).unwrap());
+
+            // only the bytes which fit are kept, and the datagram is marked as cut off
+            let mut bufs = vec![BytesMut::new(), BytesMut::new()];
+            let mut metas = [RecvMeta::default(); 2];
+            let mut received = vec![];
+            while received.len() < 2 {
+                let n = smol::block_on(receiver.recv(&mut bufs, 4, &mut metas)).unwrap();
+                for (buf, meta) in bufs.iter().zip(metas.iter()).take(n) {
+                    received.push((buf.to_vec(), meta.truncated));
+                }
+            }
+            assert_eq!(
+                vec![(b"too ".to_vec(), true), (b"fits".to_vec(), false)],
+                received
+            );
+        }
+    }
+}
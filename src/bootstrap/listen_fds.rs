@@ -0,0 +1,58 @@
+use std::{
+    env,
+    io::{Error, ErrorKind},
+    os::fd::{FromRawFd, OwnedFd, RawFd},
+};
+
+/// First file descriptor passed with socket activation, following stdin, stdout and stderr
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Takes the sockets passed to this process with systemd socket activation, i.e. `LISTEN_FDS`
+/// file descriptors starting at 3, if `LISTEN_PID` is the pid of this process. It returns no
+/// descriptors if none were passed.
+///
+/// The variables are removed from the environment, so that child processes don't take the
+/// descriptors too, and `FD_CLOEXEC` is set on each of them. As changing the environment isn't
+/// thread-safe, it must be called before any threads are spawned, e.g. first thing in `main`. The descriptors can be converted into
+/// [std::net::TcpListener] or [std::net::UdpSocket] for
+/// [BootstrapTcpServer::from_std_listener](crate::bootstrap::BootstrapTcpServer::from_std_listener)
+/// and [BootstrapUdpServer::from_std_socket](crate::bootstrap::BootstrapUdpServer::from_std_socket).
+pub fn listen_fds() -> Result<Vec<OwnedFd>, Error> {
+    let (pid, fds) = (env::var("LISTEN_PID"), env::var("LISTEN_FDS"));
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    let (Ok(pid), Ok(fds)) = (pid, fds) else {
+        return Ok(vec![]);
+    };
+    let pid: u32 = pid
+        .parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "invalid LISTEN_PID"))?;
+    if pid != std::process::id() {
+        return Ok(vec![]);
+    }
+    let end = fds
+        .parse::<RawFd>()
+        .ok()
+        .filter(|fds| *fds >= 0)
+        .and_then(|fds| LISTEN_FDS_START.checked_add(fds))
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid LISTEN_FDS"))?;
+
+    // all descriptors are checked before any is changed or owned, so that a failure leaves them
+    // as they were
+    for fd in LISTEN_FDS_START..end {
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+    for fd in LISTEN_FDS_START..end {
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+    // the descriptors are owned by this process, nothing else takes them
+    Ok((LISTEN_FDS_START..end)
+        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+        .collect())
+}
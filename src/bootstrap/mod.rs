@@ -14,29 +14,52 @@ use std::{
 };
 use waitgroup::{WaitGroup, Worker};
 
+use crate::bootstrap::adaptive_recv_size::{AdaptiveRecvSize, INITIAL_RECV_SIZE, MIN_RECV_SIZE};
 use crate::channel::{InboundPipeline, OutboundPipeline, Pipeline};
 use crate::executor::spawn_local;
 use crate::transport::{TaggedBytesMut, TransportContext};
 
+mod adaptive_recv_size;
 mod bootstrap_tcp;
 mod bootstrap_udp;
+#[cfg(unix)]
+mod listen_fds;
+mod pcap_replay;
 
 pub use bootstrap_tcp::{
-    bootstrap_tcp_client::BootstrapTcpClient, bootstrap_tcp_server::BootstrapTcpServer,
+    accept_event::{AcceptDecision, AcceptEvent, AcceptEventFn, OnAcceptFn},
+    bootstrap_tcp_client::BootstrapTcpClient,
+    bootstrap_tcp_server::BootstrapTcpServer,
+    reconnect_policy::ReconnectPolicy,
+    tcp_connection::TcpConnection,
+    tcp_connection_pool::TcpConnectionPool,
 };
 pub use bootstrap_udp::{
     bootstrap_udp_client::BootstrapUdpClient, bootstrap_udp_server::BootstrapUdpServer,
 };
+#[cfg(unix)]
+pub use listen_fds::listen_fds;
+pub use pcap_replay::PcapReplay;
 
 /// Creates a new [Pipeline]
-pub type PipelineFactoryFn<R, W> = Box<dyn (Fn() -> Rc<Pipeline<R, W>>)>;
+pub type PipelineFactoryFn<R, W> = Box<dyn Fn() -> Rc<Pipeline<R, W>>>;
+
+/// Creates a new [Pipeline] for the connection with the given [TransportContext]
+pub type PipelineFactoryWithContextFn<R, W> = Box<dyn Fn(&TransportContext) -> Rc<Pipeline<R, W>>>;
 
 const MAX_DURATION_IN_SECS: u64 = 86400; // 1 day
 
+/// User-defined event delivered through [handle_event](crate::channel::Handler::handle_event) to
+/// every pipeline when a graceful stop with timeout starts draining. The pipeline stays open, so
+/// that handlers can finish in-flight requests, write e.g. a goodbye, and close when they are done.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DrainEvent;
+
 struct Bootstrap<W> {
     max_payload_size: usize,
     pipeline_factory_fn: Option<Rc<PipelineFactoryFn<TaggedBytesMut, W>>>,
     close_tx: Rc<RefCell<Option<async_broadcast::Sender<()>>>>,
+    drain_tx: Rc<RefCell<Option<async_broadcast::Sender<()>>>>,
     wg: Rc<RefCell<Option<WaitGroup>>>,
 }
 
@@ -52,6 +75,7 @@ impl<W: 'static> Bootstrap<W> {
             max_payload_size: 2048, // Typical internet MTU = 1500, rounded up to a power of 2
             pipeline_factory_fn: None,
             close_tx: Rc::new(RefCell::new(None)),
+            drain_tx: Rc::new(RefCell::new(None)),
             wg: Rc::new(RefCell::new(None)),
         }
     }
@@ -66,6 +90,34 @@ impl<W: 'static> Bootstrap<W> {
         self
     }
 
+    /// Returns receivers of the close and drain channels and a worker of the wait group, all
+    /// shared by the tasks of this bootstrap until it is stopped
+    fn subscribe(
+        &self,
+    ) -> (
+        async_broadcast::Receiver<()>,
+        async_broadcast::Receiver<()>,
+        Worker,
+    ) {
+        let subscribe = |tx: &RefCell<Option<async_broadcast::Sender<()>>>| {
+            let mut tx = tx.borrow_mut();
+            match tx.as_ref() {
+                Some(tx) if !tx.is_closed() => tx.new_receiver(),
+                _ => {
+                    let (new_tx, rx) = async_broadcast::broadcast(1);
+                    *tx = Some(new_tx);
+                    rx
+                }
+            }
+        };
+        let (close_rx, drain_rx) = (subscribe(&self.close_tx), subscribe(&self.drain_tx));
+        let worker = {
+            let mut wg = self.wg.borrow_mut();
+            wg.get_or_insert_with(WaitGroup::new).worker()
+        };
+        (close_rx, drain_rx, worker)
+    }
+
     async fn stop(&self) {
         let mut close_tx = self.close_tx.borrow_mut();
         if let Some(close_tx) = close_tx.take() {
@@ -87,4 +139,42 @@ impl<W: 'static> Bootstrap<W> {
         self.stop().await;
         self.wait_for_stop().await;
     }
+
+    /// Stops accepting and delivers a [DrainEvent] to every pipeline, then waits for the tasks to
+    /// end on their own, e.g. TCP connections whose pipeline is closed and flushed. Whatever
+    /// remains at the timeout is stopped right away.
+    async fn graceful_stop_with_timeout(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        {
+            let mut drain_tx = self.drain_tx.borrow_mut();
+            if let Some(drain_tx) = drain_tx.take() {
+                let _ = drain_tx.try_broadcast(());
+            }
+        }
+
+        let wg = {
+            let mut wg = self.wg.borrow_mut();
+            wg.take()
+        };
+        if let Some(wg) = wg {
+            let mut wait = std::pin::pin!(wg.wait());
+            let drained = futures_lite::future::or(
+                async {
+                    (&mut wait).await;
+                    true
+                },
+                async {
+                    Timer::at(deadline).await;
+                    false
+                },
+            )
+            .await;
+            if !drained {
+                trace!("graceful stop timed out");
+                self.stop().await;
+                wait.await;
+            }
+        }
+        self.stop().await;
+    }
 }
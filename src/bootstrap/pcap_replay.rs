@@ -0,0 +1,539 @@
+use bytes::BytesMut;
+use log::trace;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Error, ErrorKind, Read},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::bootstrap::{PipelineFactoryFn, MAX_DURATION_IN_SECS};
+use crate::channel::{InboundPipeline, Pipeline};
+use crate::transport::{EcnCodepoint, FourTuple, Protocol, TaggedBytesMut, TransportContext};
+
+const PCAP_MAGIC_MICROS: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+const PCAPNG_BLOCK_TYPE_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_BLOCK_TYPE_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const PCAPNG_BLOCK_TYPE_ENHANCED_PACKET: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const PCAPNG_OPTION_IF_TSRESOL: u16 = 9;
+const PCAPNG_OPTION_EPB_FLAGS: u16 = 2;
+
+const LINK_TYPE_NULL: u16 = 0;
+const LINK_TYPE_ETHERNET: u16 = 1;
+const LINK_TYPE_RAW: u16 = 101;
+const LINK_TYPE_LINUX_SLL: u16 = 113;
+const LINK_TYPE_IPV4: u16 = 228;
+const LINK_TYPE_IPV6: u16 = 229;
+const LINK_TYPE_LINUX_SLL2: u16 = 276;
+
+const ETHER_TYPE_IPV4: u16 = 0x0800;
+const ETHER_TYPE_IPV6: u16 = 0x86DD;
+const ETHER_TYPE_VLAN: u16 = 0x8100;
+const ETHER_TYPE_QINQ: u16 = 0x88A8;
+const IP_PROTOCOL_TCP: u8 = 6;
+const IP_PROTOCOL_UDP: u8 = 17;
+
+/// A UDP datagram or TCP segment payload of a capture
+struct Packet {
+    // since the Unix epoch
+    time: Duration,
+    // direction recorded in the capture, if any
+    inbound: Option<bool>,
+    src: SocketAddr,
+    dst: SocketAddr,
+    protocol: Protocol,
+    ecn: Option<EcnCodepoint>,
+    payload: Vec<u8>,
+}
+
+/// A bootstrap-like driver which replays the UDP datagrams and TCP segments of a pcap or pcapng
+/// file into pipelines with virtual time, and returns what they transmit, e.g. to turn captured
+/// traffic into a deterministic regression test of protocol handlers.
+///
+/// Packets are read in [TaggedBytesMut] with the addresses, protocol and ECN of the capture and a
+/// `now` which is as far from the start of the replay as the packet is from the first captured
+/// one. All UDP packets go to one pipeline like with [BootstrapUdpServer](crate::bootstrap::BootstrapUdpServer),
+/// while each TCP connection gets its own pipeline like with
+/// [BootstrapTcpServer](crate::bootstrap::BootstrapTcpServer). Between packets, timeouts the
+/// pipelines poll are handled at their virtual time.
+///
+/// Only packets to a [local_addr](PcapReplay::local_addr) are replayed, or the inbound ones if the
+/// capture records their direction, like one of [PcapCaptureHandler](crate::handler::PcapCaptureHandler),
+/// or else all. Ethernet, Linux cooked, BSD loopback and raw IP captures are supported, IP
+/// fragments, empty TCP segments and other protocols are skipped, and TCP segments are replayed as
+/// they were captured, i.e. without reassembly or removing retransmissions.
+pub struct PcapReplay<W> {
+    packets: Vec<Packet>,
+    local_addrs: Vec<SocketAddr>,
+    linger: Duration,
+    pipeline_factory_fn: Option<PipelineFactoryFn<TaggedBytesMut, W>>,
+}
+
+impl<W: 'static> PcapReplay<W> {
+    /// Reads a pcap or pcapng file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(fs::File::open(path)?)
+    }
+
+    /// Reads a pcap or pcapng capture from the reader
+    pub fn new(mut reader: impl Read) -> Result<Self, Error> {
+        let mut capture = vec![];
+        reader.read_to_end(&mut capture)?;
+        let packets = match capture.get(0..4) {
+            Some(magic)
+                if u32::from_le_bytes(magic.try_into().unwrap())
+                    == PCAPNG_BLOCK_TYPE_SECTION_HEADER =>
+            {
+                parse_pcapng(&capture)?
+            }
+            _ => parse_pcap(&capture)?,
+        };
+        Ok(Self {
+            packets,
+            local_addrs: vec![],
+            linger: Duration::ZERO,
+            pipeline_factory_fn: None,
+        })
+    }
+
+    /// Creates pipeline instances from when calling [PcapReplay::run].
+    pub fn pipeline(
+        &mut self,
+        pipeline_factory_fn: PipelineFactoryFn<TaggedBytesMut, W>,
+    ) -> &mut Self {
+        self.pipeline_factory_fn = Some(pipeline_factory_fn);
+        self
+    }
+
+    /// Adds a local address, packets to which are replayed. An unspecified IP matches any IP, e.g.
+    /// `0.0.0.0:8080` matches packets to port 8080.
+    pub fn local_addr(&mut self, local_addr: SocketAddr) -> &mut Self {
+        self.local_addrs.push(local_addr);
+        self
+    }
+
+    /// Sets how long timeouts keep being handled after the last packet, default is zero
+    pub fn linger(&mut self, linger: Duration) -> &mut Self {
+        self.linger = linger;
+        self
+    }
+
+    /// Replays the capture into new pipelines and returns their transmits in order. Pipelines are
+    /// made transport inactive at the end. Fails if a packet is too far apart from the first one
+    /// to be replayed at its virtual time.
+    pub fn run(&self) -> Result<Vec<TaggedBytesMut>, Error> {
+        let pipeline_factory_fn = self
+            .pipeline_factory_fn
+            .as_ref()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "pipeline factory is not set"))?;
+
+        let start = Instant::now();
+        let first = self.packets.iter().map(|packet| packet.time).min();
+        let mut now = start;
+        let mut transmits = vec![];
+        let mut pipelines: Vec<Rc<Pipeline<TaggedBytesMut, W>>> = vec![];
+        let mut udp_pipeline: Option<usize> = None;
+        let mut tcp_pipelines: HashMap<FourTuple, usize> = HashMap::new();
+
+        for packet in &self.packets {
+            let Some(transport) = self.inbound_transport(packet) else {
+                continue;
+            };
+            let at = start
+                .checked_add(packet.time.saturating_sub(first.unwrap_or_default()))
+                .ok_or_else(|| invalid_data("pcap packet time out of range"))?;
+            let at = at.max(now);
+            Self::advance(&pipelines, now, at, &mut transmits);
+            now = at;
+
+            let index = match transport.protocol {
+                Protocol::UDP => udp_pipeline,
+                Protocol::TCP => tcp_pipelines.get(&FourTuple::from(&transport)).copied(),
+            };
+            let index = index.unwrap_or_else(|| {
+                let pipeline = (pipeline_factory_fn)();
+                pipeline.transport_active();
+                Self::poll_transmits(&pipeline, &mut transmits);
+                pipelines.push(pipeline);
+                let index = pipelines.len() - 1;
+                match transport.protocol {
+                    Protocol::UDP => udp_pipeline = Some(index),
+                    Protocol::TCP => {
+                        tcp_pipelines.insert(FourTuple::from(&transport), index);
+                    }
+                }
+                index
+            });
+
+            let pipeline = &pipelines[index];
+            if pipeline.is_closed() {
+                trace!("pcap replay skips packet to closed pipeline");
+                continue;
+            }
+            pipeline.read(TaggedBytesMut {
+                now,
+                transport,
+                message: BytesMut::from(&packet.payload[..]),
+            });
+            Self::poll_transmits(pipeline, &mut transmits);
+        }
+
+        let until = now
+            .checked_add(self.linger)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "linger out of range"))?;
+        Self::advance(&pipelines, now, until, &mut transmits);
+        for pipeline in &pipelines {
+            pipeline.transport_inactive();
+            Self::poll_transmits(pipeline, &mut transmits);
+        }
+        Ok(transmits)
+    }
+
+    /// Returns the transport context of a packet which is replayed, from the point of view of the
+    /// receiver
+    fn inbound_transport(&self, packet: &Packet) -> Option<TransportContext> {
+        let inbound = if self.local_addrs.is_empty() {
+            packet.inbound.unwrap_or(true)
+        } else {
+            self.local_addrs.iter().any(|local_addr| {
+                local_addr.port() == packet.dst.port()
+                    && (local_addr.ip().is_unspecified() || local_addr.ip() == packet.dst.ip())
+            })
+        };
+        inbound.then_some(TransportContext {
+            local_addr: packet.dst,
+            peer_addr: packet.src,
+            protocol: packet.protocol,
+            ecn: packet.ecn,
+            interface_index: None,
+        })
+    }
+
+    /// Handles timeouts which are due from `now` until `until` at their virtual time
+    fn advance(
+        pipelines: &[Rc<Pipeline<TaggedBytesMut, W>>],
+        mut now: Instant,
+        until: Instant,
+        transmits: &mut Vec<TaggedBytesMut>,
+    ) {
+        let mut handled: Option<Instant> = None;
+        loop {
+            let mut eto = until + Duration::from_secs(MAX_DURATION_IN_SECS);
+            for pipeline in pipelines {
+                pipeline.poll_timeout(&mut eto);
+            }
+            let at = eto.max(now);
+            // a timeout which stays due after it was handled waits for the next packet
+            if at > until || handled.is_some_and(|handled| handled >= at) {
+                break;
+            }
+            now = at;
+            handled = Some(at);
+            for pipeline in pipelines {
+                pipeline.handle_timeout(at);
+                Self::poll_transmits(pipeline, transmits);
+            }
+        }
+    }
+
+    fn poll_transmits(pipeline: &Pipeline<TaggedBytesMut, W>, transmits: &mut Vec<TaggedBytesMut>) {
+        while let Some(transmit) = pipeline.poll_transmit() {
+            transmits.push(transmit);
+        }
+    }
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Reads integers of the byte order of a capture
+#[derive(Copy, Clone)]
+struct ByteOrder {
+    big_endian: bool,
+}
+
+impl ByteOrder {
+    fn u16(&self, bytes: &[u8], offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = bytes.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, bytes: &[u8], offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = bytes.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+fn parse_pcap(capture: &[u8]) -> Result<Vec<Packet>, Error> {
+    let header = capture
+        .get(0..24)
+        .ok_or_else(|| invalid_data("truncated pcap header"))?;
+    let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+    let (order, nanos) = match magic {
+        PCAP_MAGIC_MICROS => (ByteOrder { big_endian: false }, false),
+        PCAP_MAGIC_NANOS => (ByteOrder { big_endian: false }, true),
+        _ if magic.swap_bytes() == PCAP_MAGIC_MICROS => (ByteOrder { big_endian: true }, false),
+        _ if magic.swap_bytes() == PCAP_MAGIC_NANOS => (ByteOrder { big_endian: true }, true),
+        _ => return Err(invalid_data("neither a pcap nor a pcapng file")),
+    };
+    // the upper bits of the link type carry FCS information
+    let link_type = order.u32(header, 20).unwrap() as u16;
+
+    let mut packets = vec![];
+    let mut offset = 24;
+    while offset < capture.len() {
+        let record = &capture[offset..];
+        let (Some(secs), Some(fraction), Some(len)) = (
+            order.u32(record, 0),
+            order.u32(record, 4),
+            order.u32(record, 8),
+        ) else {
+            return Err(invalid_data("truncated pcap record"));
+        };
+        let data = record
+            .get(16..16 + len as usize)
+            .ok_or_else(|| invalid_data("truncated pcap record"))?;
+        let time = Duration::from_secs(secs as u64)
+            + if nanos {
+                Duration::from_nanos(fraction as u64)
+            } else {
+                Duration::from_micros(fraction as u64)
+            };
+        packets.extend(parse_frame(link_type, data, time, None));
+        offset += 16 + len as usize;
+    }
+    Ok(packets)
+}
+
+fn parse_pcapng(capture: &[u8]) -> Result<Vec<Packet>, Error> {
+    let mut order = ByteOrder { big_endian: false };
+    // link type and timestamp units per second of each interface of the current section
+    let mut interfaces: Vec<(u16, u64)> = vec![];
+
+    let mut packets = vec![];
+    let mut offset = 0;
+    while offset < capture.len() {
+        let block = &capture[offset..];
+        if block
+            .get(0..4)
+            .map(|block_type| u32::from_le_bytes(block_type.try_into().unwrap()))
+            == Some(PCAPNG_BLOCK_TYPE_SECTION_HEADER)
+        {
+            let magic = block
+                .get(8..12)
+                .map(|magic| u32::from_le_bytes(magic.try_into().unwrap()))
+                .ok_or_else(|| invalid_data("truncated pcapng block"))?;
+            order = match magic {
+                PCAPNG_BYTE_ORDER_MAGIC => ByteOrder { big_endian: false },
+                _ if magic.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => {
+                    ByteOrder { big_endian: true }
+                }
+                _ => return Err(invalid_data("invalid pcapng byte order magic")),
+            };
+            interfaces.clear();
+        }
+        let (Some(block_type), Some(len)) = (order.u32(block, 0), order.u32(block, 4)) else {
+            return Err(invalid_data("truncated pcapng block"));
+        };
+        let len = len as usize;
+        if len < 12 || !len.is_multiple_of(4) || len > block.len() {
+            return Err(invalid_data("invalid pcapng block length"));
+        }
+        let body = &block[8..len - 4];
+
+        match block_type {
+            PCAPNG_BLOCK_TYPE_INTERFACE_DESCRIPTION => {
+                let link_type = order
+                    .u16(body, 0)
+                    .ok_or_else(|| invalid_data("truncated pcapng interface"))?;
+                let mut units_per_sec = 1_000_000;
+                for (code, value) in options(order, body.get(8..).unwrap_or_default()) {
+                    if code == PCAPNG_OPTION_IF_TSRESOL && !value.is_empty() {
+                        let resolution = value[0] & 0x7F;
+                        units_per_sec = if value[0] & 0x80 == 0 {
+                            10u64.checked_pow(resolution as u32)
+                        } else {
+                            2u64.checked_pow(resolution as u32)
+                        }
+                        .ok_or_else(|| invalid_data("invalid pcapng timestamp resolution"))?;
+                    }
+                }
+                interfaces.push((link_type, units_per_sec));
+            }
+            PCAPNG_BLOCK_TYPE_ENHANCED_PACKET => {
+                let (Some(interface), Some(high), Some(low), Some(captured)) = (
+                    order.u32(body, 0),
+                    order.u32(body, 4),
+                    order.u32(body, 8),
+                    order.u32(body, 12),
+                ) else {
+                    return Err(invalid_data("truncated pcapng packet"));
+                };
+                let &(link_type, units_per_sec) = interfaces
+                    .get(interface as usize)
+                    .ok_or_else(|| invalid_data("pcapng packet of unknown interface"))?;
+                let captured = captured as usize;
+                let data = body
+                    .get(20..20 + captured)
+                    .ok_or_else(|| invalid_data("truncated pcapng packet"))?;
+                let mut inbound = None;
+                let options_offset = 20 + captured.next_multiple_of(4);
+                for (code, value) in options(order, body.get(options_offset..).unwrap_or_default())
+                {
+                    if code == PCAPNG_OPTION_EPB_FLAGS {
+                        inbound = match order.u32(value, 0).map(|flags| flags & 0b11) {
+                            Some(1) => Some(true),
+                            Some(2) => Some(false),
+                            _ => None,
+                        };
+                    }
+                }
+                let timestamp = ((high as u64) << 32) | low as u64;
+                let time = Duration::from_secs(timestamp / units_per_sec)
+                    + Duration::from_nanos(
+                        ((timestamp % units_per_sec) as u128 * 1_000_000_000
+                            / units_per_sec as u128) as u64,
+                    );
+                packets.extend(parse_frame(link_type, data, time, inbound));
+            }
+            _ => {}
+        }
+        offset += len;
+    }
+    Ok(packets)
+}
+
+/// Returns code and value of each pcapng option until the end of options
+fn options(order: ByteOrder, mut options: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut result = vec![];
+    while let (Some(code), Some(len)) = (order.u16(options, 0), order.u16(options, 2)) {
+        let len = len as usize;
+        let Some(value) = options.get(4..4 + len) else {
+            break;
+        };
+        if code == 0 {
+            break;
+        }
+        result.push((code, value));
+        options = options
+            .get(4 + len.next_multiple_of(4)..)
+            .unwrap_or_default();
+    }
+    result
+}
+
+/// Parses a link layer frame into a UDP datagram or TCP segment payload, if it is one
+fn parse_frame(
+    link_type: u16,
+    data: &[u8],
+    time: Duration,
+    inbound: Option<bool>,
+) -> Option<Packet> {
+    let ip = match link_type {
+        LINK_TYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ether_type = u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?);
+            while ether_type == ETHER_TYPE_VLAN || ether_type == ETHER_TYPE_QINQ {
+                offset += 4;
+                ether_type = u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?);
+            }
+            if ether_type != ETHER_TYPE_IPV4 && ether_type != ETHER_TYPE_IPV6 {
+                return None;
+            }
+            data.get(offset + 2..)?
+        }
+        LINK_TYPE_NULL => data.get(4..)?,
+        LINK_TYPE_LINUX_SLL => data.get(16..)?,
+        LINK_TYPE_LINUX_SLL2 => data.get(20..)?,
+        LINK_TYPE_RAW | LINK_TYPE_IPV4 | LINK_TYPE_IPV6 => data,
+        _ => {
+            trace!("pcap replay skips link type {}", link_type);
+            return None;
+        }
+    };
+
+    let (src_ip, dst_ip, ecn, ip_protocol, l4) = match ip.first()? >> 4 {
+        4 => {
+            let header_len = ((ip[0] & 0x0F) as usize) * 4;
+            let total_len = u16::from_be_bytes(ip.get(2..4)?.try_into().ok()?) as usize;
+            let fragment = u16::from_be_bytes(ip.get(6..8)?.try_into().ok()?);
+            // more fragments flag or a fragment offset
+            if fragment & 0x3FFF != 0 {
+                return None;
+            }
+            let src: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            (
+                IpAddr::V4(Ipv4Addr::from(src)),
+                IpAddr::V4(Ipv4Addr::from(dst)),
+                ip[1] & 0b11,
+                ip[9],
+                ip.get(header_len..total_len.min(ip.len()))?,
+            )
+        }
+        6 => {
+            let payload_len = u16::from_be_bytes(ip.get(4..6)?.try_into().ok()?) as usize;
+            let src: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            let traffic_class = ((ip[0] & 0x0F) << 4) | (ip[1] >> 4);
+            let mut next_header = ip[6];
+            let mut payload = ip.get(40..(40 + payload_len).min(ip.len()))?;
+            // hop-by-hop options, routing and destination options extension headers
+            while matches!(next_header, 0 | 43 | 60) {
+                let len = (*payload.get(1)? as usize + 1) * 8;
+                next_header = payload[0];
+                payload = payload.get(len..)?;
+            }
+            (
+                IpAddr::V6(Ipv6Addr::from(src)),
+                IpAddr::V6(Ipv6Addr::from(dst)),
+                traffic_class & 0b11,
+                next_header,
+                payload,
+            )
+        }
+        _ => return None,
+    };
+
+    let src_port = u16::from_be_bytes(l4.get(0..2)?.try_into().ok()?);
+    let dst_port = u16::from_be_bytes(l4.get(2..4)?.try_into().ok()?);
+    let (protocol, payload) = match ip_protocol {
+        IP_PROTOCOL_UDP => {
+            let len = u16::from_be_bytes(l4.get(4..6)?.try_into().ok()?) as usize;
+            (Protocol::UDP, l4.get(8..len.clamp(8, l4.len()))?)
+        }
+        IP_PROTOCOL_TCP => {
+            let data_offset = (*l4.get(12)? >> 4) as usize * 4;
+            let payload = l4.get(data_offset..)?;
+            if payload.is_empty() {
+                return None;
+            }
+            (Protocol::TCP, payload)
+        }
+        _ => return None,
+    };
+
+    Some(Packet {
+        time,
+        inbound,
+        src: SocketAddr::new(src_ip, src_port),
+        dst: SocketAddr::new(dst_ip, dst_port),
+        protocol,
+        ecn: EcnCodepoint::from_bits(ecn),
+        payload: payload.to_vec(),
+    })
+}
@@ -84,6 +84,15 @@ pub trait Handler {
     ) {
         ctx.fire_poll_timeout(eto);
     }
+    /// Polls whether reads from the transport are paused, e.g. while inbound traffic is held
+    /// back. A paused transport reads again once a timeout or a write wakes it up and no handler
+    /// pauses reads anymore.
+    fn poll_read_paused(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> bool {
+        ctx.fire_poll_read_paused()
+    }
 
     /// Reads an EOF event.
     fn handle_read_eof(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
@@ -101,6 +110,22 @@ pub trait Handler {
     fn handle_close(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
         ctx.fire_close();
     }
+    /// Handle a shutdown output event, which half-closes the transport after pending writes.
+    fn handle_shutdown_output(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) {
+        ctx.fire_shutdown_output();
+    }
+    /// Handle a user-defined event, e.g. [IdleStateEvent](crate::handler::IdleStateEvent),
+    /// which can be downcast to its type.
+    fn handle_event(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        evt: Box<dyn Any>,
+    ) {
+        ctx.fire_event(evt);
+    }
 }
 
 impl<Rin: 'static, Rout: 'static, Win: 'static, Wout: 'static> HandlerInternal
@@ -176,6 +201,16 @@ impl<Rin: 'static, Rout: 'static, Win: 'static, Wout: 'static> HandlerInternal
             );
         }
     }
+    fn poll_read_paused_internal(&mut self, ctx: &dyn ContextInternal) -> bool {
+        if let Some(ctx) = ctx.as_any().downcast_ref::<Context<Rin, Rout, Win, Wout>>() {
+            self.poll_read_paused(ctx)
+        } else {
+            panic!(
+                "ctx can't downcast_ref::<Context<Rin, Rout, Win, Wout>> in {} handler",
+                ctx.name()
+            );
+        }
+    }
 
     fn handle_read_eof_internal(&mut self, ctx: &dyn ContextInternal) {
         if let Some(ctx) = ctx.as_any().downcast_ref::<Context<Rin, Rout, Win, Wout>>() {
@@ -207,6 +242,26 @@ impl<Rin: 'static, Rout: 'static, Win: 'static, Wout: 'static> HandlerInternal
             );
         }
     }
+    fn handle_shutdown_output_internal(&mut self, ctx: &dyn ContextInternal) {
+        if let Some(ctx) = ctx.as_any().downcast_ref::<Context<Rin, Rout, Win, Wout>>() {
+            self.handle_shutdown_output(ctx);
+        } else {
+            panic!(
+                "ctx can't downcast_ref::<Context<Rin, Rout, Win, Wout>> in {} handler",
+                ctx.name()
+            );
+        }
+    }
+    fn handle_event_internal(&mut self, ctx: &dyn ContextInternal, evt: Box<dyn Any>) {
+        if let Some(ctx) = ctx.as_any().downcast_ref::<Context<Rin, Rout, Win, Wout>>() {
+            self.handle_event(ctx, evt);
+        } else {
+            panic!(
+                "ctx can't downcast_ref::<Context<Rin, Rout, Win, Wout>> in {} handler",
+                ctx.name()
+            );
+        }
+    }
 }
 
 /// Enables a [Handler] to interact with its Pipeline and other handlers.
@@ -306,6 +361,18 @@ impl<Rin: 'static, Rout: 'static, Win: 'static, Wout: 'static> Context<Rin, Rout
         }
     }
 
+    /// Polls whether reads from the transport are paused.
+    pub fn fire_poll_read_paused(&self) -> bool {
+        if let (Some(next_handler), Some(next_context)) = (&self.next_handler, &self.next_context) {
+            let (mut next_handler, next_context) =
+                (next_handler.borrow_mut(), next_context.borrow());
+            next_handler.poll_read_paused_internal(&*next_context)
+        } else {
+            trace!("poll_read_paused reached end of pipeline");
+            false
+        }
+    }
+
     /// Reads an EOF event.
     pub fn fire_read_eof(&self) {
         if let (Some(next_handler), Some(next_context)) = (&self.next_handler, &self.next_context) {
@@ -338,6 +405,28 @@ impl<Rin: 'static, Rout: 'static, Win: 'static, Wout: 'static> Context<Rin, Rout
             warn!("handle_close reached end of pipeline");
         }
     }
+
+    /// Writes a shutdown output event.
+    pub fn fire_shutdown_output(&self) {
+        if let (Some(next_handler), Some(next_context)) = (&self.next_handler, &self.next_context) {
+            let (mut next_handler, next_context) =
+                (next_handler.borrow_mut(), next_context.borrow());
+            next_handler.handle_shutdown_output_internal(&*next_context);
+        } else {
+            warn!("handle_shutdown_output reached end of pipeline");
+        }
+    }
+
+    /// Fires a user-defined event.
+    pub fn fire_event(&self, evt: Box<dyn Any>) {
+        if let (Some(next_handler), Some(next_context)) = (&self.next_handler, &self.next_context) {
+            let (mut next_handler, next_context) =
+                (next_handler.borrow_mut(), next_context.borrow());
+            next_handler.handle_event_internal(&*next_context, evt);
+        } else {
+            trace!("handle_event reached end of pipeline");
+        }
+    }
 }
 
 impl<Rin: 'static, Rout: 'static, Win: 'static, Wout: 'static> ContextInternal
@@ -370,6 +459,9 @@ impl<Rin: 'static, Rout: 'static, Win: 'static, Wout: 'static> ContextInternal
     fn fire_poll_timeout_internal(&self, eto: &mut Instant) {
         self.fire_poll_timeout(eto);
     }
+    fn fire_poll_read_paused_internal(&self) -> bool {
+        self.fire_poll_read_paused()
+    }
 
     fn fire_read_eof_internal(&self) {
         self.fire_read_eof();
@@ -380,6 +472,12 @@ impl<Rin: 'static, Rout: 'static, Win: 'static, Wout: 'static> ContextInternal
     fn fire_close_internal(&self) {
         self.fire_close();
     }
+    fn fire_shutdown_output_internal(&self) {
+        self.fire_shutdown_output();
+    }
+    fn fire_event_internal(&self, evt: Box<dyn Any>) {
+        self.fire_event(evt);
+    }
 
     fn name(&self) -> &str {
         self.name.as_str()
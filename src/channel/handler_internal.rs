@@ -14,10 +14,13 @@ pub trait HandlerInternal {
 
     fn handle_timeout_internal(&mut self, ctx: &dyn ContextInternal, now: Instant);
     fn poll_timeout_internal(&mut self, ctx: &dyn ContextInternal, eto: &mut Instant);
+    fn poll_read_paused_internal(&mut self, ctx: &dyn ContextInternal) -> bool;
 
     fn handle_read_eof_internal(&mut self, ctx: &dyn ContextInternal);
     fn handle_exception_internal(&mut self, ctx: &dyn ContextInternal, err: Box<dyn Error>);
     fn handle_close_internal(&mut self, ctx: &dyn ContextInternal);
+    fn handle_shutdown_output_internal(&mut self, ctx: &dyn ContextInternal);
+    fn handle_event_internal(&mut self, ctx: &dyn ContextInternal, evt: Box<dyn Any>);
 }
 
 #[doc(hidden)]
@@ -30,10 +33,13 @@ pub trait ContextInternal {
 
     fn fire_timeout_internal(&self, now: Instant);
     fn fire_poll_timeout_internal(&self, eto: &mut Instant);
+    fn fire_poll_read_paused_internal(&self) -> bool;
 
     fn fire_read_eof_internal(&self);
     fn fire_exception_internal(&self, err: Box<dyn Error>);
     fn fire_close_internal(&self);
+    fn fire_shutdown_output_internal(&self);
+    fn fire_event_internal(&self, evt: Box<dyn Any>);
 
     fn name(&self) -> &str;
     fn as_any(&self) -> &dyn Any;
@@ -1,4 +1,11 @@
-use std::{cell::RefCell, error::Error, rc::Rc, time::Instant};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    error::Error,
+    io::ErrorKind,
+    rc::Rc,
+    time::Instant,
+};
 
 use crate::channel::{handler::Handler, pipeline_internal::PipelineInternal};
 
@@ -19,12 +26,22 @@ pub trait InboundPipeline<R> {
     /// Reads an Error exception in one of its inbound operations.
     fn handle_exception(&self, err: Box<dyn Error>);
 
+    /// Reads a user-defined event, e.g. [DrainEvent](crate::bootstrap::DrainEvent).
+    /// Pipelines which don't handle user-defined events drop it.
+    fn handle_event(&self, _evt: Box<dyn Any>) {}
+
     /// Handles a timeout event.
     fn handle_timeout(&self, now: Instant);
 
     /// Polls an event.
     fn poll_timeout(&self, eto: &mut Instant);
 
+    /// Polls whether reads from the transport are paused. Pipelines which don't pause reads
+    /// never are.
+    fn poll_read_paused(&self) -> bool {
+        false
+    }
+
     /// Polls an outgoing message
     fn poll_transmit(&self) -> Option<R>;
 }
@@ -34,14 +51,27 @@ pub trait OutboundPipeline<R, W> {
     /// Writes a message.
     fn write(&self, msg: W);
 
-    /// Writes a close event.
+    /// Writes a close event. A TCP bootstrap closes the connection once the event reaches the end
+    /// of the pipeline and pending writes are flushed, while a UDP bootstrap keeps serving the
+    /// socket, which may be shared by many peers, until it is stopped, or until the event reaches
+    /// the end of the pipeline while draining.
     fn close(&self);
+
+    /// Writes a shutdown output event, which half-closes the transport after pending writes.
+    /// Pipelines which can't half-close write a close event instead.
+    fn shutdown_output(&self) {
+        self.close();
+    }
 }
 
 /// Pipeline implements an advanced form of the Intercepting Filter pattern to give a user full control
 /// over how an event is handled and how the Handlers in a pipeline interact with each other.
 pub struct Pipeline<R, W> {
     internal: RefCell<PipelineInternal<R, W>>,
+    write_notify_tx: async_broadcast::Sender<()>,
+    write_notify_rx: async_broadcast::InactiveReceiver<()>,
+    inactive: Cell<bool>,
+    released: Cell<bool>,
 }
 
 impl<R: 'static, W: 'static> Default for Pipeline<R, W> {
@@ -53,8 +83,13 @@ impl<R: 'static, W: 'static> Default for Pipeline<R, W> {
 impl<R: 'static, W: 'static> Pipeline<R, W> {
     /// Creates a new Pipeline
     pub fn new() -> Self {
+        let (write_notify_tx, write_notify_rx) = async_broadcast::broadcast(1);
         Self {
             internal: RefCell::new(PipelineInternal::new()),
+            write_notify_tx,
+            write_notify_rx: write_notify_rx.deactivate(),
+            inactive: Cell::new(false),
+            released: Cell::new(false),
         }
     }
 
@@ -133,17 +168,56 @@ impl<R: 'static, W: 'static> Pipeline<R, W> {
         let pipeline = Rc::new(self);
         pipeline.update()
     }
+
+    /// Returns a receiver which is notified whenever a message or a close event is written to this
+    /// pipeline from outside, so that the transport can wake up and drain it.
+    pub(crate) fn write_notify(&self) -> async_broadcast::Receiver<()> {
+        self.write_notify_rx.activate_cloned()
+    }
+
+    /// Returns whether a shutdown output event has reached the end of this pipeline.
+    pub(crate) fn is_output_shutdown(&self) -> bool {
+        let internal = self.internal.borrow();
+        internal.is_output_shutdown()
+    }
+
+    /// Returns whether this pipeline got transport_inactive since it last got transport_active.
+    pub(crate) fn is_inactive(&self) -> bool {
+        self.inactive.get()
+    }
+
+    /// Returns whether a close event has reached the end of this pipeline.
+    pub(crate) fn is_closed(&self) -> bool {
+        let internal = self.internal.borrow();
+        internal.is_closed()
+    }
+
+    /// Releases this pipeline from its transport, which won't poll it anymore. Pending writes are
+    /// dropped, and further writes are rejected with a `NotConnected` exception instead of piling
+    /// up.
+    pub(crate) fn release(&self) {
+        self.released.set(true);
+        let internal = self.internal.borrow();
+        internal.clear_transmits();
+    }
+
+    fn notify_write(&self) {
+        // a full channel means a notification is already pending
+        let _ = self.write_notify_tx.try_broadcast(());
+    }
 }
 
 impl<R: 'static, W: 'static> InboundPipeline<R> for Pipeline<R, W> {
     /// Transport is active now, which means it is connected.
     fn transport_active(&self) {
+        self.inactive.set(false);
         let internal = self.internal.borrow();
         internal.transport_active();
     }
 
     /// Transport is inactive now, which means it is disconnected.
     fn transport_inactive(&self) {
+        self.inactive.set(true);
         let internal = self.internal.borrow();
         internal.transport_inactive();
     }
@@ -166,6 +240,12 @@ impl<R: 'static, W: 'static> InboundPipeline<R> for Pipeline<R, W> {
         internal.handle_exception(err);
     }
 
+    /// Reads a user-defined event, e.g. [DrainEvent](crate::bootstrap::DrainEvent).
+    fn handle_event(&self, evt: Box<dyn Any>) {
+        let internal = self.internal.borrow();
+        internal.handle_event(evt);
+    }
+
     /// Handles a timeout event.
     fn handle_timeout(&self, now: Instant) {
         let internal = self.internal.borrow();
@@ -178,6 +258,12 @@ impl<R: 'static, W: 'static> InboundPipeline<R> for Pipeline<R, W> {
         internal.poll_timeout(eto);
     }
 
+    /// Polls whether reads from the transport are paused.
+    fn poll_read_paused(&self) -> bool {
+        let internal = self.internal.borrow();
+        internal.poll_read_paused()
+    }
+
     /// Polls an outgoing message
     fn poll_transmit(&self) -> Option<R> {
         let internal = self.internal.borrow();
@@ -188,13 +274,35 @@ impl<R: 'static, W: 'static> InboundPipeline<R> for Pipeline<R, W> {
 impl<R: 'static, W: 'static> OutboundPipeline<R, W> for Pipeline<R, W> {
     /// Writes a message to pipeline
     fn write(&self, msg: W) {
-        let internal = self.internal.borrow();
-        internal.write(msg);
+        {
+            let internal = self.internal.borrow();
+            if self.released.get() {
+                internal.handle_exception(Box::new(std::io::Error::new(
+                    ErrorKind::NotConnected,
+                    "pipeline has no transport anymore",
+                )));
+                return;
+            }
+            internal.write(msg);
+        }
+        self.notify_write();
     }
 
     /// Writes a close event.
     fn close(&self) {
-        let internal = self.internal.borrow();
-        internal.handle_close();
+        {
+            let internal = self.internal.borrow();
+            internal.handle_close();
+        }
+        self.notify_write();
+    }
+
+    /// Writes a shutdown output event.
+    fn shutdown_output(&self) {
+        {
+            let internal = self.internal.borrow();
+            internal.handle_shutdown_output();
+        }
+        self.notify_write();
     }
 }
@@ -1,5 +1,13 @@
 use std::collections::VecDeque;
-use std::{cell::RefCell, error::Error, io::ErrorKind, marker::PhantomData, rc::Rc, time::Instant};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    error::Error,
+    io::ErrorKind,
+    marker::PhantomData,
+    rc::Rc,
+    time::Instant,
+};
 
 use crate::channel::{
     handler::Handler,
@@ -15,13 +23,18 @@ pub(crate) struct PipelineInternal<R, W> {
     contexts: Vec<Rc<RefCell<dyn ContextInternal>>>,
 
     transmits: Rc<RefCell<VecDeque<W>>>,
+    output_shutdown: Rc<Cell<bool>>,
+    closed: Rc<Cell<bool>>,
     phantom: PhantomData<R>,
 }
 
 impl<R: 'static, W: 'static> PipelineInternal<R, W> {
     pub(crate) fn new() -> Self {
         let transmits = Rc::new(RefCell::new(VecDeque::new()));
-        let last_handler = LastHandler::new(transmits.clone());
+        let output_shutdown = Rc::new(Cell::new(false));
+        let closed = Rc::new(Cell::new(false));
+        let last_handler =
+            LastHandler::new(transmits.clone(), output_shutdown.clone(), closed.clone());
         let (name, handler, context) = last_handler.generate();
         Self {
             names: vec![name],
@@ -29,6 +42,8 @@ impl<R: 'static, W: 'static> PipelineInternal<R, W> {
             contexts: vec![context],
 
             transmits,
+            output_shutdown,
+            closed,
             phantom: PhantomData,
         }
     }
@@ -150,6 +165,11 @@ impl<R: 'static, W: 'static> PipelineInternal<R, W> {
         transmits.push_back(msg);
     }
 
+    pub(crate) fn clear_transmits(&self) {
+        let mut transmits = self.transmits.borrow_mut();
+        transmits.clear();
+    }
+
     pub(crate) fn transport_active(&self) {
         let (mut handler, context) = (
             self.handlers.first().unwrap().borrow_mut(),
@@ -198,6 +218,22 @@ impl<R: 'static, W: 'static> PipelineInternal<R, W> {
         handler.handle_close_internal(&*context);
     }
 
+    pub(crate) fn handle_shutdown_output(&self) {
+        let (mut handler, context) = (
+            self.handlers.first().unwrap().borrow_mut(),
+            self.contexts.first().unwrap().borrow(),
+        );
+        handler.handle_shutdown_output_internal(&*context);
+    }
+
+    pub(crate) fn is_output_shutdown(&self) -> bool {
+        self.output_shutdown.get()
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.get()
+    }
+
     pub(crate) fn handle_timeout(&self, now: Instant) {
         let (mut handler, context) = (
             self.handlers.first().unwrap().borrow_mut(),
@@ -214,6 +250,14 @@ impl<R: 'static, W: 'static> PipelineInternal<R, W> {
         handler.poll_timeout_internal(&*context, eto);
     }
 
+    pub(crate) fn poll_read_paused(&self) -> bool {
+        let (mut handler, context) = (
+            self.handlers.first().unwrap().borrow_mut(),
+            self.contexts.first().unwrap().borrow(),
+        );
+        handler.poll_read_paused_internal(&*context)
+    }
+
     pub(crate) fn handle_read_eof(&self) {
         let (mut handler, context) = (
             self.handlers.first().unwrap().borrow_mut(),
@@ -229,15 +273,33 @@ impl<R: 'static, W: 'static> PipelineInternal<R, W> {
         );
         handler.handle_exception_internal(&*context, err);
     }
+
+    pub(crate) fn handle_event(&self, evt: Box<dyn Any>) {
+        let (mut handler, context) = (
+            self.handlers.first().unwrap().borrow_mut(),
+            self.contexts.first().unwrap().borrow(),
+        );
+        handler.handle_event_internal(&*context, evt);
+    }
 }
 
 pub(crate) struct LastHandler<W> {
     transmits: Rc<RefCell<VecDeque<W>>>,
+    output_shutdown: Rc<Cell<bool>>,
+    closed: Rc<Cell<bool>>,
 }
 
 impl<W> LastHandler<W> {
-    pub(crate) fn new(transmits: Rc<RefCell<VecDeque<W>>>) -> Self {
-        Self { transmits }
+    pub(crate) fn new(
+        transmits: Rc<RefCell<VecDeque<W>>>,
+        output_shutdown: Rc<Cell<bool>>,
+        closed: Rc<Cell<bool>>,
+    ) -> Self {
+        Self {
+            transmits,
+            output_shutdown,
+            closed,
+        }
     }
 }
 
@@ -266,4 +328,15 @@ impl<W: 'static> Handler for LastHandler<W> {
         let mut transmits = self.transmits.borrow_mut();
         transmits.pop_front()
     }
+
+    fn handle_shutdown_output(
+        &mut self,
+        _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) {
+        self.output_shutdown.set(true);
+    }
+
+    fn handle_close(&mut self, _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        self.closed.set(true);
+    }
 }
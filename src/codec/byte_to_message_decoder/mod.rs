@@ -1,8 +1,10 @@
 //! Handlers for converting byte to message
 use crate::channel::{Context, Handler};
-use crate::transport::TaggedBytesMut;
+use crate::transport::{FourTuple, TaggedBytesMut};
 use bytes::BytesMut;
-use std::time::Instant;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
 
 mod line_based_frame_decoder;
 
@@ -14,21 +16,86 @@ pub trait MessageDecoder {
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<BytesMut>, std::io::Error>;
 }
 
+/// Creates a [MessageDecoder] for each connection of [TaggedByteToMessageCodec]
+pub type MessageDecoderFactoryFn =
+    Box<dyn Fn() -> Box<dyn MessageDecoder + Send + Sync> + Send + Sync>;
+
+enum MessageDecoders {
+    Shared(Box<dyn MessageDecoder + Send + Sync>),
+    PerConnection(MessageDecoderFactoryFn),
+}
+
+struct Cumulation {
+    /// Decoder of the connection, none if the decoder is shared
+    message_decoder: Option<Box<dyn MessageDecoder + Send + Sync>>,
+    buf: BytesMut,
+    last_read: Instant,
+}
+
 /// A tagged Byte to Message Codec handler that reads with input of TaggedBytesMut and output of TaggedBytesMut,
 /// or writes with input of TaggedBytesMut and output of TaggedBytesMut
+///
+/// Each [FourTuple], i.e. each connection, gets a cumulation buffer which reads are appended to and
+/// messages are decoded from, so that a frame may be split across reads of a stream transport.
+/// A decoder given to [new](Self::new) is shared by all connections, while
+/// [with_factory](Self::with_factory) gives each connection a decoder of its own, e.g. for a UDP
+/// server whose pipeline serves many peers with a decoder which keeps state between reads. Once more than `max_cumulation_size` bytes are buffered for
+/// a connection, or more than `max_cumulation_total_size` bytes over all connections, or the
+/// decoder fails, the buffer of the connection is discarded with an exception. Connections which
+/// got no read for `cumulation_timeout` are dropped, e.g. UDP peers which went away, driven by
+/// [poll_timeout](Handler::poll_timeout) and [handle_timeout](Handler::handle_timeout).
 pub struct TaggedByteToMessageCodec {
     transport_active: bool,
-    message_decoder: Box<dyn MessageDecoder + Send + Sync>,
+    message_decoders: MessageDecoders,
+    max_cumulation_size: usize,
+    max_cumulation_total_size: usize,
+    cumulation_timeout: Duration,
+    cumulations: HashMap<FourTuple, Cumulation>,
+    cumulation_total_size: usize,
+    next_eviction: Option<Instant>,
 }
 
 impl TaggedByteToMessageCodec {
-    /// Creates a new TaggedByteToMessageCodec handler
+    /// Creates a new TaggedByteToMessageCodec handler with a decoder shared by all connections
     pub fn new(message_decoder: Box<dyn MessageDecoder + Send + Sync>) -> Self {
+        Self::with_decoders(MessageDecoders::Shared(message_decoder))
+    }
+
+    /// Creates a new TaggedByteToMessageCodec handler with a decoder per connection from the factory
+    pub fn with_factory(message_decoder_factory: MessageDecoderFactoryFn) -> Self {
+        Self::with_decoders(MessageDecoders::PerConnection(message_decoder_factory))
+    }
+
+    fn with_decoders(message_decoders: MessageDecoders) -> Self {
         Self {
             transport_active: false,
-            message_decoder,
+            message_decoders,
+            max_cumulation_size: 1 << 20,
+            max_cumulation_total_size: 64 << 20,
+            cumulation_timeout: Duration::from_secs(30),
+            cumulations: HashMap::new(),
+            cumulation_total_size: 0,
+            next_eviction: None,
         }
     }
+
+    /// Sets max number of bytes buffered for decoding per connection, default is 1 MiB
+    pub fn max_cumulation_size(&mut self, max_cumulation_size: usize) -> &mut Self {
+        self.max_cumulation_size = max_cumulation_size;
+        self
+    }
+
+    /// Sets max number of bytes buffered for decoding over all connections, default is 64 MiB
+    pub fn max_cumulation_total_size(&mut self, max_cumulation_total_size: usize) -> &mut Self {
+        self.max_cumulation_total_size = max_cumulation_total_size;
+        self
+    }
+
+    /// Sets how long the decoder and buffer of a connection are kept without a read, default is 30s
+    pub fn cumulation_timeout(&mut self, cumulation_timeout: Duration) -> &mut Self {
+        self.cumulation_timeout = cumulation_timeout;
+        self
+    }
 }
 
 impl Handler for TaggedByteToMessageCodec {
@@ -47,32 +114,138 @@ impl Handler for TaggedByteToMessageCodec {
     }
     fn transport_inactive(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
         self.transport_active = false;
+        self.cumulations.clear();
+        self.cumulation_total_size = 0;
+        self.next_eviction = None;
         ctx.fire_transport_inactive();
     }
     fn handle_read(
         &mut self,
         ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
-        mut msg: Self::Rin,
+        msg: Self::Rin,
     ) {
-        while self.transport_active {
-            match self.message_decoder.decode(&mut msg.message) {
-                Ok(message) => {
-                    if let Some(message) = message {
-                        ctx.fire_read(TaggedBytesMut {
-                            now: Instant::now(),
-                            transport: msg.transport,
-                            message,
-                        });
-                    } else {
-                        return;
-                    }
+        if !self.transport_active {
+            return;
+        }
+
+        let four_tuple = FourTuple::from(&msg.transport);
+        let mut cumulation = match self.cumulations.remove(&four_tuple) {
+            Some(cumulation) => {
+                self.cumulation_total_size -= cumulation.buf.len();
+                cumulation
+            }
+            None => Cumulation {
+                message_decoder: match &self.message_decoders {
+                    MessageDecoders::Shared(_) => None,
+                    MessageDecoders::PerConnection(factory) => Some(factory()),
+                },
+                buf: BytesMut::new(),
+                last_read: msg.now,
+            },
+        };
+        if cumulation.buf.is_empty() {
+            cumulation.buf = msg.message;
+        } else {
+            cumulation.buf.extend_from_slice(&msg.message);
+        }
+
+        let message_decoder = match (&mut cumulation.message_decoder, &mut self.message_decoders) {
+            (Some(message_decoder), _) | (None, MessageDecoders::Shared(message_decoder)) => {
+                message_decoder
+            }
+            (None, MessageDecoders::PerConnection(_)) => {
+                unreachable!("a decoder per connection is created with its cumulation")
+            }
+        };
+        while self.transport_active && !cumulation.buf.is_empty() {
+            let len = cumulation.buf.len();
+            match message_decoder.decode(&mut cumulation.buf) {
+                Ok(Some(message)) => {
+                    ctx.fire_read(TaggedBytesMut {
+                        now: Instant::now(),
+                        transport: msg.transport,
+                        message,
+                    });
                 }
+                // a decoder may consume bytes without producing a message, e.g. when discarding
+                Ok(None) if cumulation.buf.len() < len => {}
+                Ok(None) => break,
                 Err(err) => {
                     ctx.fire_exception(Box::new(err));
-                    return;
+                    cumulation.buf.clear();
+                    break;
                 }
             }
         }
+
+        if !self.transport_active {
+            return;
+        }
+        let len = cumulation.buf.len();
+        if len > self.max_cumulation_size {
+            ctx.fire_exception(Box::new(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "cumulation of {} bytes from {} exceeds max {}",
+                    len, msg.transport.peer_addr, self.max_cumulation_size
+                ),
+            )));
+            cumulation.buf.clear();
+        } else if self.cumulation_total_size + len > self.max_cumulation_total_size {
+            ctx.fire_exception(Box::new(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "cumulation of {} bytes from {} exceeds max total {}",
+                    len, msg.transport.peer_addr, self.max_cumulation_total_size
+                ),
+            )));
+            cumulation.buf.clear();
+        }
+        // the decoder is kept even without buffered bytes, as it may be in the middle of a frame
+        cumulation.last_read = msg.now;
+        self.cumulation_total_size += cumulation.buf.len();
+        self.cumulations.insert(four_tuple, cumulation);
+        if self.next_eviction.is_none() {
+            self.next_eviction = Some(msg.now + self.cumulation_timeout);
+        }
+    }
+
+    fn handle_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        now: Instant,
+    ) {
+        if self
+            .next_eviction
+            .is_some_and(|next_eviction| next_eviction <= now)
+        {
+            let (cumulation_timeout, cumulation_total_size) =
+                (self.cumulation_timeout, &mut self.cumulation_total_size);
+            self.cumulations.retain(|_, cumulation| {
+                let keep = cumulation.last_read + cumulation_timeout > now;
+                if !keep {
+                    *cumulation_total_size -= cumulation.buf.len();
+                }
+                keep
+            });
+            self.next_eviction = self
+                .cumulations
+                .values()
+                .map(|cumulation| cumulation.last_read + cumulation_timeout)
+                .min();
+        }
+        ctx.fire_timeout(now);
+    }
+
+    fn poll_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        eto: &mut Instant,
+    ) {
+        if let Some(next_eviction) = self.next_eviction {
+            *eto = (*eto).min(next_eviction);
+        }
+        ctx.fire_poll_timeout(eto);
     }
 
     fn poll_write(
@@ -0,0 +1,182 @@
+//! Handler for detecting when a pipeline hasn't read or written for a while
+
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use crate::channel::{Context, Handler};
+
+/// User-defined event fired by [IdleStateHandler] through
+/// [handle_event](crate::channel::Handler::handle_event)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IdleStateEvent {
+    /// Nothing was read for `reader_idle_time`
+    ReaderIdle,
+    /// Nothing was written for `writer_idle_time`
+    WriterIdle,
+    /// Nothing was read or written for `all_idle_time`
+    AllIdle,
+}
+
+/// A handler that fires an [IdleStateEvent] to the next handlers when the pipeline hasn't read,
+/// written, or either of them for the configured time, e.g. to close idle connections or to send
+/// heartbeats. A zero duration disables the corresponding event. While the pipeline stays idle,
+/// the event is fired again after each period. Reads and writes of any message type pass through
+/// unchanged, writes count once they are polled towards the transport.
+pub struct IdleStateHandler<R, W> {
+    reader_idle_time: Duration,
+    writer_idle_time: Duration,
+    all_idle_time: Duration,
+
+    active: bool,
+    last_read: Instant,
+    last_write: Instant,
+    // last time each event was fired, so that it is fired again one period later
+    last_reader_idle: Instant,
+    last_writer_idle: Instant,
+    last_all_idle: Instant,
+
+    phantom: PhantomData<(R, W)>,
+}
+
+impl<R, W> IdleStateHandler<R, W> {
+    /// Creates a new IdleStateHandler
+    pub fn new(
+        reader_idle_time: Duration,
+        writer_idle_time: Duration,
+        all_idle_time: Duration,
+    ) -> Self {
+        let now = Instant::now();
+        Self {
+            reader_idle_time,
+            writer_idle_time,
+            all_idle_time,
+
+            active: false,
+            last_read: now,
+            last_write: now,
+            last_reader_idle: now,
+            last_writer_idle: now,
+            last_all_idle: now,
+
+            phantom: PhantomData,
+        }
+    }
+
+    fn reader_idle_at(&self) -> Option<Instant> {
+        Self::idle_at(
+            self.reader_idle_time,
+            self.last_read.max(self.last_reader_idle),
+        )
+    }
+
+    fn writer_idle_at(&self) -> Option<Instant> {
+        Self::idle_at(
+            self.writer_idle_time,
+            self.last_write.max(self.last_writer_idle),
+        )
+    }
+
+    fn all_idle_at(&self) -> Option<Instant> {
+        Self::idle_at(
+            self.all_idle_time,
+            self.last_read.max(self.last_write).max(self.last_all_idle),
+        )
+    }
+
+    fn idle_at(idle_time: Duration, since: Instant) -> Option<Instant> {
+        if idle_time.is_zero() {
+            None
+        } else {
+            Some(since + idle_time)
+        }
+    }
+}
+
+impl<R: 'static, W: 'static> Handler for IdleStateHandler<R, W> {
+    type Rin = R;
+    type Rout = Self::Rin;
+    type Win = W;
+    type Wout = Self::Win;
+
+    fn name(&self) -> &str {
+        "IdleStateHandler"
+    }
+
+    fn transport_active(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        let now = Instant::now();
+        self.active = true;
+        self.last_read = now;
+        self.last_write = now;
+        self.last_reader_idle = now;
+        self.last_writer_idle = now;
+        self.last_all_idle = now;
+        ctx.fire_transport_active();
+    }
+
+    fn transport_inactive(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        self.active = false;
+        ctx.fire_transport_inactive();
+    }
+
+    fn handle_read(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        msg: Self::Rin,
+    ) {
+        self.last_read = Instant::now();
+        ctx.fire_read(msg);
+    }
+
+    fn poll_write(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> Option<Self::Wout> {
+        let msg = ctx.fire_poll_write();
+        if msg.is_some() {
+            self.last_write = Instant::now();
+        }
+        msg
+    }
+
+    fn handle_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        now: Instant,
+    ) {
+        if self.active {
+            if self.reader_idle_at().is_some_and(|at| at <= now) {
+                self.last_reader_idle = now;
+                ctx.fire_event(Box::new(IdleStateEvent::ReaderIdle));
+            }
+            if self.writer_idle_at().is_some_and(|at| at <= now) {
+                self.last_writer_idle = now;
+                ctx.fire_event(Box::new(IdleStateEvent::WriterIdle));
+            }
+            if self.all_idle_at().is_some_and(|at| at <= now) {
+                self.last_all_idle = now;
+                ctx.fire_event(Box::new(IdleStateEvent::AllIdle));
+            }
+        }
+        ctx.fire_timeout(now);
+    }
+
+    fn poll_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        eto: &mut Instant,
+    ) {
+        if self.active {
+            for idle_at in [
+                self.reader_idle_at(),
+                self.writer_idle_at(),
+                self.all_idle_at(),
+            ]
+            .into_iter()
+            .flatten()
+            {
+                *eto = (*eto).min(idle_at);
+            }
+        }
+        ctx.fire_poll_timeout(eto);
+    }
+}
@@ -0,0 +1,223 @@
+//! Handler for emulating an impaired network with loss, delay, duplication, reordering and
+//! corruption
+
+use log::trace;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::channel::{Context, Handler};
+use crate::transport::TaggedBytesMut;
+
+/// Impairment of one direction of [ImpairmentHandler], probabilities are between 0 and 1
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Impairment {
+    /// Probability that a datagram is dropped
+    pub loss: f64,
+    /// Delay of each datagram
+    pub delay: Duration,
+    /// Max random delay which is added to `delay`, uniformly distributed
+    pub jitter: Duration,
+    /// Probability that a datagram is delivered twice
+    pub duplicate: f64,
+    /// Probability that a datagram is delayed by `reorder_delay` in addition, so that following
+    /// ones overtake it
+    pub reorder: f64,
+    /// Additional delay of reordered datagrams
+    pub reorder_delay: Duration,
+    /// Probability that a random bit of a datagram is flipped
+    pub corrupt: f64,
+}
+
+/// Datagrams of one direction waiting for their delivery time, in order of it and of arrival
+#[derive(Default)]
+struct Scheduled {
+    datagrams: BTreeMap<(Instant, u64), TaggedBytesMut>,
+    seq: u64,
+}
+
+impl Scheduled {
+    fn push(&mut self, at: Instant, msg: TaggedBytesMut) {
+        self.datagrams.insert((at, self.seq), msg);
+        self.seq += 1;
+    }
+
+    fn next_at(&self) -> Option<Instant> {
+        self.datagrams.first_key_value().map(|((at, _), _)| *at)
+    }
+
+    fn pop_due(&mut self, now: Instant) -> Option<TaggedBytesMut> {
+        if self.next_at()? <= now {
+            self.datagrams.pop_first().map(|(_, msg)| msg)
+        } else {
+            None
+        }
+    }
+}
+
+/// A handler that emulates an impaired network for the datagrams passing through it, e.g. to test
+/// retransmission or jitter buffers of UDP protocols over the loopback interface without netem or
+/// root access. It is placed directly above the bootstrap, and impairs reads and writes each with
+/// their own [Impairment].
+///
+/// Delays are counted from `now` of the datagrams and scheduled through
+/// [poll_timeout](Handler::poll_timeout) and [handle_timeout](Handler::handle_timeout), and random
+/// decisions come from a seedable generator, so that a run can be reproduced, e.g. with
+/// [PcapReplay](crate::bootstrap::PcapReplay).
+pub struct ImpairmentHandler {
+    inbound: Impairment,
+    outbound: Impairment,
+    rng: fastrand::Rng,
+
+    // latest time seen, of datagrams or timeouts
+    now: Option<Instant>,
+    reads: Scheduled,
+    writes: Scheduled,
+}
+
+impl Default for ImpairmentHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ImpairmentHandler {
+    /// Creates a new ImpairmentHandler without impairments, seeded randomly
+    pub fn new() -> Self {
+        Self {
+            inbound: Impairment::default(),
+            outbound: Impairment::default(),
+            rng: fastrand::Rng::new(),
+
+            now: None,
+            reads: Scheduled::default(),
+            writes: Scheduled::default(),
+        }
+    }
+
+    /// Sets the impairment of reads
+    pub fn inbound(&mut self, impairment: Impairment) -> &mut Self {
+        self.inbound = impairment;
+        self
+    }
+
+    /// Sets the impairment of writes
+    pub fn outbound(&mut self, impairment: Impairment) -> &mut Self {
+        self.outbound = impairment;
+        self
+    }
+
+    /// Seeds the random generator, so that the same datagrams are impaired the same way
+    pub fn seed(&mut self, seed: u64) -> &mut Self {
+        self.rng = fastrand::Rng::with_seed(seed);
+        self
+    }
+
+    fn advance(&mut self, now: Instant) -> Instant {
+        let now = self.now.map_or(now, |last| last.max(now));
+        self.now = Some(now);
+        now
+    }
+
+    /// Schedules the copies of a datagram which aren't lost
+    fn impair(
+        rng: &mut fastrand::Rng,
+        impairment: &Impairment,
+        scheduled: &mut Scheduled,
+        msg: TaggedBytesMut,
+    ) {
+        if rng.f64() < impairment.loss {
+            trace!("impairment drops datagram of {}", msg.transport.peer_addr);
+            return;
+        }
+        let copies = if rng.f64() < impairment.duplicate {
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let mut message = msg.message.clone();
+            if !message.is_empty() && rng.f64() < impairment.corrupt {
+                let i = rng.usize(..message.len());
+                message[i] ^= 1 << rng.u8(..8);
+            }
+            let mut delay = impairment.delay
+                + Duration::from_nanos(rng.u64(..=impairment.jitter.as_nanos() as u64));
+            if rng.f64() < impairment.reorder {
+                delay += impairment.reorder_delay;
+            }
+            scheduled.push(
+                msg.now + delay,
+                TaggedBytesMut {
+                    now: msg.now,
+                    transport: msg.transport,
+                    message,
+                },
+            );
+        }
+    }
+}
+
+impl Handler for ImpairmentHandler {
+    type Rin = TaggedBytesMut;
+    type Rout = Self::Rin;
+    type Win = TaggedBytesMut;
+    type Wout = Self::Win;
+
+    fn name(&self) -> &str {
+        "ImpairmentHandler"
+    }
+
+    fn handle_read(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        msg: Self::Rin,
+    ) {
+        let now = self.advance(msg.now);
+        Self::impair(&mut self.rng, &self.inbound, &mut self.reads, msg);
+        while let Some(mut msg) = self.reads.pop_due(now) {
+            msg.now = now;
+            ctx.fire_read(msg);
+        }
+    }
+
+    fn poll_write(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> Option<Self::Wout> {
+        while let Some(msg) = ctx.fire_poll_write() {
+            self.advance(msg.now);
+            Self::impair(&mut self.rng, &self.outbound, &mut self.writes, msg);
+        }
+        let now = self.now?;
+        let mut msg = self.writes.pop_due(now)?;
+        msg.now = now;
+        Some(msg)
+    }
+
+    fn handle_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        now: Instant,
+    ) {
+        let now = self.advance(now);
+        while let Some(mut msg) = self.reads.pop_due(now) {
+            msg.now = now;
+            ctx.fire_read(msg);
+        }
+        ctx.fire_timeout(now);
+    }
+
+    fn poll_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        eto: &mut Instant,
+    ) {
+        for at in [self.reads.next_at(), self.writes.next_at()]
+            .into_iter()
+            .flatten()
+        {
+            *eto = (*eto).min(at);
+        }
+        ctx.fire_poll_timeout(eto);
+    }
+}
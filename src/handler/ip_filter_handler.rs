@@ -0,0 +1,272 @@
+//! Handler for allowing or denying peers by IP address with CIDR rules
+
+use log::trace;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use crate::channel::{Context, Handler};
+use crate::transport::TaggedBytesMut;
+
+/// An IPv4 or IPv6 network in CIDR notation, e.g. `10.0.0.0/8` or `2001:db8::/32`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    /// Creates a new IpCidr, the host bits of `addr` are cleared
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, Error> {
+        let max_prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_prefix_len {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid prefix length {} of {}", prefix_len, addr),
+            ));
+        }
+        let cidr = Self { addr, prefix_len };
+        Ok(Self {
+            addr: cidr.network(addr),
+            prefix_len,
+        })
+    }
+
+    /// Returns whether the address is in this network. IPv4-mapped IPv6 addresses are matched
+    /// as IPv4 addresses.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.addr.is_ipv4() == ip.is_ipv4() && self.network(ip) == self.addr
+    }
+
+    /// Returns the address with the host bits of this network cleared, `ip` must be of the
+    /// same family
+    fn network(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                IpAddr::V4((u32::from(ip) & mask).into())
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                IpAddr::V6((u128::from(ip) & mask).into())
+            }
+        }
+    }
+}
+
+impl FromStr for IpCidr {
+    type Err = Error;
+
+    /// Parses `addr/prefix_len`, or a single address without prefix length
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::new(ErrorKind::InvalidInput, format!("invalid CIDR {}", s));
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (
+                addr.parse::<IpAddr>().map_err(|_| invalid())?,
+                Some(prefix_len.parse::<u8>().map_err(|_| invalid())?),
+            ),
+            None => (s.parse::<IpAddr>().map_err(|_| invalid())?, None),
+        };
+        let prefix_len = prefix_len.unwrap_or(if addr.is_ipv4() { 32 } else { 128 });
+        Self::new(addr, prefix_len)
+    }
+}
+
+impl fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Whether an [IpFilterRule] allows or denies matching peers
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IpFilterAction {
+    /// Allows matching peers
+    Allow,
+    /// Denies matching peers
+    Deny,
+}
+
+/// A rule of [IpFilter], applying its action to peers in the network
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IpFilterRule {
+    /// Network of the rule
+    pub cidr: IpCidr,
+    /// Action for peers in the network
+    pub action: IpFilterAction,
+}
+
+impl IpFilterRule {
+    /// Creates a rule allowing peers in the network
+    pub fn allow(cidr: IpCidr) -> Self {
+        Self {
+            cidr,
+            action: IpFilterAction::Allow,
+        }
+    }
+
+    /// Creates a rule denying peers in the network
+    pub fn deny(cidr: IpCidr) -> Self {
+        Self {
+            cidr,
+            action: IpFilterAction::Deny,
+        }
+    }
+}
+
+struct IpFilterRules {
+    rules: Vec<IpFilterRule>,
+    default_action: IpFilterAction,
+}
+
+/// Ordered rules of [IpFilterHandler], where the first rule matching a peer decides, or the
+/// default action if none does.
+///
+/// It is a shared handle, clones see the same rules, so that rules can be swapped at runtime,
+/// also from another thread, for all pipelines using it.
+#[derive(Clone)]
+pub struct IpFilter {
+    rules: Arc<RwLock<Arc<IpFilterRules>>>,
+}
+
+impl IpFilter {
+    /// Creates a new IpFilter
+    pub fn new(rules: Vec<IpFilterRule>, default_action: IpFilterAction) -> Self {
+        Self {
+            rules: Arc::new(RwLock::new(Arc::new(IpFilterRules {
+                rules,
+                default_action,
+            }))),
+        }
+    }
+
+    /// Replaces the rules, which applies to subsequent messages and connections
+    pub fn set_rules(&self, rules: Vec<IpFilterRule>, default_action: IpFilterAction) {
+        let mut current = self.rules.write().unwrap_or_else(|err| err.into_inner());
+        *current = Arc::new(IpFilterRules {
+            rules,
+            default_action,
+        });
+    }
+
+    /// Returns whether the rules allow the IP address
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        let rules = {
+            let rules = self.rules.read().unwrap_or_else(|err| err.into_inner());
+            Arc::clone(&rules)
+        };
+        let action = rules
+            .rules
+            .iter()
+            .find(|rule| rule.cidr.contains(ip))
+            .map_or(rules.default_action, |rule| rule.action);
+        action == IpFilterAction::Allow
+    }
+}
+
+/// A handler that drops inbound messages from peers which [IpFilter] denies.
+///
+/// For connection-oriented pipelines, create it with [IpFilterHandler::with_peer_addr], e.g. in
+/// [BootstrapTcpServer::pipeline_with_context](crate::bootstrap::BootstrapTcpServer::pipeline_with_context).
+/// Then a denied connection is closed on [transport_active](Handler::transport_active), which isn't
+/// passed on nor is the following transport_inactive, and an established one is closed on its next
+/// read once the rules deny it.
+pub struct IpFilterHandler {
+    filter: IpFilter,
+    peer_addr: Option<SocketAddr>,
+    denied_on_active: bool,
+    closed: bool,
+}
+
+impl IpFilterHandler {
+    /// Creates a new IpFilterHandler which checks the peer of each message, e.g. for UDP
+    pub fn new(filter: IpFilter) -> Self {
+        Self {
+            filter,
+            peer_addr: None,
+            denied_on_active: false,
+            closed: false,
+        }
+    }
+
+    /// Creates a new IpFilterHandler for the connection with the peer
+    pub fn with_peer_addr(filter: IpFilter, peer_addr: SocketAddr) -> Self {
+        Self {
+            filter,
+            peer_addr: Some(peer_addr),
+            denied_on_active: false,
+            closed: false,
+        }
+    }
+
+    fn close(
+        &mut self,
+        ctx: &Context<TaggedBytesMut, TaggedBytesMut, TaggedBytesMut, TaggedBytesMut>,
+    ) {
+        if !self.closed {
+            self.closed = true;
+            ctx.fire_close();
+        }
+    }
+}
+
+impl Handler for IpFilterHandler {
+    type Rin = TaggedBytesMut;
+    type Rout = Self::Rin;
+    type Win = TaggedBytesMut;
+    type Wout = Self::Win;
+
+    fn name(&self) -> &str {
+        "IpFilterHandler"
+    }
+
+    fn transport_active(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        if let Some(peer_addr) = self.peer_addr {
+            if !self.filter.is_allowed(peer_addr.ip()) {
+                trace!("ip filter closes connection from {}", peer_addr);
+                self.denied_on_active = true;
+                self.close(ctx);
+                return;
+            }
+        }
+        ctx.fire_transport_active();
+    }
+
+    fn transport_inactive(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        if !self.denied_on_active {
+            ctx.fire_transport_inactive();
+        }
+    }
+
+    fn handle_read(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        msg: Self::Rin,
+    ) {
+        if self.closed {
+            return;
+        }
+        if !self.filter.is_allowed(msg.transport.peer_addr.ip()) {
+            trace!("ip filter drops message from {}", msg.transport.peer_addr);
+            if self.peer_addr.is_some() {
+                self.close(ctx);
+            }
+            return;
+        }
+        ctx.fire_read(msg);
+    }
+
+    fn poll_write(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> Option<Self::Wout> {
+        ctx.fire_poll_write()
+    }
+}
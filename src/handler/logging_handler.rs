@@ -0,0 +1,240 @@
+//! Handler for logging the events of a pipeline, with a hex dump of bytes
+
+use bytes::BytesMut;
+use log::{log, log_enabled, Level};
+use std::any::Any;
+use std::error::Error;
+use std::fmt::Write;
+use std::marker::PhantomData;
+use std::time::Instant;
+
+use crate::channel::{Context, Handler};
+use crate::transport::{Transmit, TransportContext};
+
+/// How a message is formatted by [LoggingHandler]
+pub trait LogMessage {
+    /// Formats the message, dumping at most `max_len` bytes or chars of it
+    fn log_message(&self, max_len: usize) -> String;
+
+    /// Returns the transport the message is tagged with, if any, which [LoggingHandler] keeps to
+    /// log with events which carry no message
+    fn transport(&self) -> Option<&TransportContext> {
+        None
+    }
+}
+
+impl LogMessage for BytesMut {
+    fn log_message(&self, max_len: usize) -> String {
+        format!("{}B\n{}", self.len(), hex_dump(self, max_len))
+    }
+}
+
+impl LogMessage for String {
+    fn log_message(&self, max_len: usize) -> String {
+        match self.char_indices().nth(max_len) {
+            Some((end, _)) => format!(
+                "{:?}... {} more chars",
+                &self[..end],
+                self[end..].chars().count()
+            ),
+            None => format!("{:?}", self),
+        }
+    }
+}
+
+impl<T: LogMessage> LogMessage for Transmit<T> {
+    fn log_message(&self, max_len: usize) -> String {
+        format!(
+            "{} {}",
+            log_transport(&self.transport),
+            self.message.log_message(max_len)
+        )
+    }
+
+    fn transport(&self) -> Option<&TransportContext> {
+        Some(&self.transport)
+    }
+}
+
+fn log_transport(transport: &TransportContext) -> String {
+    format!(
+        "[{:?} L:{} - R:{}]",
+        transport.protocol, transport.local_addr, transport.peer_addr
+    )
+}
+
+/// Returns a hex and ASCII dump of at most `max_len` bytes, 16 bytes per row, e.g.
+/// ```text
+///          +-------------------------------------------------+
+///          |  0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f |
+/// +--------+-------------------------------------------------+----------------+
+/// |00000000| 48 65 6c 6c 6f 0d 0a                            |Hello..         |
+/// +--------+-------------------------------------------------+----------------+
+/// ```
+pub fn hex_dump(bytes: &[u8], max_len: usize) -> String {
+    const BORDER: &str =
+        "+--------+-------------------------------------------------+----------------+\n";
+
+    let mut dump = String::new();
+    dump.push_str("         +-------------------------------------------------+\n");
+    dump.push_str("         |  0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f |\n");
+    dump.push_str(BORDER);
+    for (row, chunk) in bytes[..bytes.len().min(max_len)].chunks(16).enumerate() {
+        let _ = write!(dump, "|{:08x}|", row * 16);
+        for i in 0..16 {
+            match chunk.get(i) {
+                Some(byte) => {
+                    let _ = write!(dump, " {:02x}", byte);
+                }
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str(" |");
+        for i in 0..16 {
+            dump.push(match chunk.get(i) {
+                Some(byte) if byte.is_ascii_graphic() || *byte == b' ' => *byte as char,
+                Some(_) => '.',
+                None => ' ',
+            });
+        }
+        dump.push_str("|\n");
+    }
+    dump.push_str(BORDER.trim_end());
+    if bytes.len() > max_len {
+        let _ = write!(dump, "\n... {} more bytes", bytes.len() - max_len);
+    }
+    dump
+}
+
+/// A pass-through handler that logs every event of the pipeline at its position through the `log`
+/// crate with target `retty::handler::logging_handler`, e.g. to debug a pipeline. Messages are
+/// formatted by [LogMessage], i.e. with their [TransportContext](crate::transport::TransportContext)
+/// and a [hex_dump] of bytes. Writes are logged once they are polled towards the transport. Other
+/// events are logged with the transport of the last message read or written, if any.
+pub struct LoggingHandler<T> {
+    level: Level,
+    max_dump_len: usize,
+    transport: Option<TransportContext>,
+    phantom: PhantomData<T>,
+}
+
+impl<T> LoggingHandler<T> {
+    /// Creates a new LoggingHandler logging at `level`
+    pub fn new(level: Level) -> Self {
+        Self {
+            level,
+            max_dump_len: 256,
+            transport: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets max number of bytes or chars of a message which are dumped, default is 256
+    pub fn max_dump_len(&mut self, max_dump_len: usize) -> &mut Self {
+        self.max_dump_len = max_dump_len;
+        self
+    }
+
+    /// Formats the transport of the last message read or written, if any, to follow an event name
+    fn last_transport(&self) -> String {
+        self.transport.as_ref().map_or(String::new(), |transport| {
+            format!(" {}", log_transport(transport))
+        })
+    }
+}
+
+impl<T: LogMessage + 'static> Handler for LoggingHandler<T> {
+    type Rin = T;
+    type Rout = Self::Rin;
+    type Win = T;
+    type Wout = Self::Win;
+
+    fn name(&self) -> &str {
+        "LoggingHandler"
+    }
+
+    fn transport_active(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        log!(self.level, "ACTIVE{}", self.last_transport());
+        ctx.fire_transport_active();
+    }
+
+    fn transport_inactive(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        log!(self.level, "INACTIVE{}", self.last_transport());
+        ctx.fire_transport_inactive();
+    }
+
+    fn handle_read(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        msg: Self::Rin,
+    ) {
+        if let Some(transport) = msg.transport() {
+            self.transport = Some(*transport);
+        }
+        if log_enabled!(self.level) {
+            log!(self.level, "READ: {}", msg.log_message(self.max_dump_len));
+        }
+        ctx.fire_read(msg);
+    }
+
+    fn poll_write(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> Option<Self::Wout> {
+        let msg = ctx.fire_poll_write();
+        if let Some(msg) = msg.as_ref() {
+            if let Some(transport) = msg.transport() {
+                self.transport = Some(*transport);
+            }
+            if log_enabled!(self.level) {
+                log!(self.level, "WRITE: {}", msg.log_message(self.max_dump_len));
+            }
+        }
+        msg
+    }
+
+    fn handle_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        now: Instant,
+    ) {
+        log!(self.level, "TIMEOUT{}: {:?}", self.last_transport(), now);
+        ctx.fire_timeout(now);
+    }
+
+    fn handle_read_eof(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        log!(self.level, "READ EOF{}", self.last_transport());
+        ctx.fire_read_eof();
+    }
+
+    fn handle_exception(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        err: Box<dyn Error>,
+    ) {
+        log!(self.level, "EXCEPTION{}: {}", self.last_transport(), err);
+        ctx.fire_exception(err);
+    }
+
+    fn handle_close(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        log!(self.level, "CLOSE{}", self.last_transport());
+        ctx.fire_close();
+    }
+
+    fn handle_shutdown_output(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) {
+        log!(self.level, "SHUTDOWN OUTPUT{}", self.last_transport());
+        ctx.fire_shutdown_output();
+    }
+
+    fn handle_event(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        evt: Box<dyn Any>,
+    ) {
+        log!(self.level, "EVENT{}", self.last_transport());
+        ctx.fire_event(evt);
+    }
+}
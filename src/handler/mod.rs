@@ -0,0 +1,21 @@
+//! Built-in handlers for common concerns of a pipeline, such as idle detection, rate limiting,
+//! IP filtering, traffic shaping, logging, capturing and network impairment
+
+pub mod idle_state_handler;
+pub mod impairment_handler;
+pub mod ip_filter_handler;
+pub mod logging_handler;
+pub mod pcap_capture_handler;
+pub mod rate_limit_handler;
+mod token_bucket;
+pub mod traffic_shaping_handler;
+
+pub use self::{
+    idle_state_handler::{IdleStateEvent, IdleStateHandler},
+    impairment_handler::{Impairment, ImpairmentHandler},
+    ip_filter_handler::{IpCidr, IpFilter, IpFilterAction, IpFilterHandler, IpFilterRule},
+    logging_handler::{hex_dump, LogMessage, LoggingHandler},
+    pcap_capture_handler::{PcapCaptureHandler, PcapWriter},
+    rate_limit_handler::{RateLimit, RateLimitAction, RateLimitHandler, RateLimitKey},
+    traffic_shaping_handler::{GlobalTrafficShaping, TrafficCounter, TrafficShapingHandler},
+};
@@ -0,0 +1,399 @@
+//! Handler for capturing the traffic of a pipeline into a pcapng file
+
+use log::warn;
+use std::fs::File;
+use std::io::{Result, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::channel::{Context, Handler};
+use crate::transport::{Protocol, TaggedBytesMut};
+
+const BLOCK_TYPE_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_TYPE_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_TYPE_ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const LINK_TYPE_ETHERNET: u16 = 1;
+const OPTION_EPB_FLAGS: u16 = 2;
+
+const ETHER_TYPE_IPV4: u16 = 0x0800;
+const ETHER_TYPE_IPV6: u16 = 0x86DD;
+const IP_PROTOCOL_TCP: u8 = 6;
+const IP_PROTOCOL_UDP: u8 = 17;
+const LOCAL_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const PEER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+
+/// Max payload per synthesized TCP frame, so that it fits into an IP packet
+const MAX_SEGMENT_SIZE: usize = 65000;
+/// Max payload of a synthesized UDP frame, the largest datagram an IPv4 packet can carry
+const MAX_DATAGRAM_SIZE_V4: usize = 65507;
+/// Max payload of a synthesized UDP frame, the largest datagram an IPv6 packet without jumbogram
+/// can carry
+const MAX_DATAGRAM_SIZE_V6: usize = 65527;
+
+struct PcapWriterInner {
+    writer: Box<dyn Write + Send>,
+    // maps `now` of messages to wall clock time
+    instant: Instant,
+    system: SystemTime,
+}
+
+/// Writer of a pcapng file with a single Ethernet interface, shared by all [PcapCaptureHandler]s
+/// created with it, e.g. of all connections of a server. Clones write to the same file.
+#[derive(Clone)]
+pub struct PcapWriter {
+    inner: Arc<Mutex<PcapWriterInner>>,
+}
+
+impl PcapWriter {
+    /// Creates a pcapng file at the path, truncating an existing one
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(File::create(path)?)
+    }
+
+    /// Creates a new PcapWriter and writes the pcapng header to the writer.
+    /// Each packet is written with a single `write_all`, so the writer needs no buffering.
+    pub fn new(writer: impl Write + Send + 'static) -> Result<Self> {
+        let mut writer: Box<dyn Write + Send> = Box::new(writer);
+
+        let mut header = vec![];
+        let mut section_header = vec![];
+        put_u32(&mut section_header, BYTE_ORDER_MAGIC);
+        put_u16(&mut section_header, 1); // major version
+        put_u16(&mut section_header, 0); // minor version
+        section_header.extend_from_slice(&(-1i64).to_le_bytes()); // unspecified section length
+        put_block(&mut header, BLOCK_TYPE_SECTION_HEADER, &section_header);
+        let mut interface_description = vec![];
+        put_u16(&mut interface_description, LINK_TYPE_ETHERNET);
+        put_u16(&mut interface_description, 0); // reserved
+        put_u32(&mut interface_description, 0); // no snap length
+        put_block(
+            &mut header,
+            BLOCK_TYPE_INTERFACE_DESCRIPTION,
+            &interface_description,
+        );
+        writer.write_all(&header)?;
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(PcapWriterInner {
+                writer,
+                instant: Instant::now(),
+                system: SystemTime::now(),
+            })),
+        })
+    }
+
+    /// Flushes the underlying writer
+    pub fn flush(&self) -> Result<()> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .writer
+            .flush()
+    }
+
+    /// Writes the frame, of a packet which was `truncated` bytes longer than the frame
+    fn write_packet(
+        &self,
+        now: Instant,
+        inbound: bool,
+        frame: &[u8],
+        truncated: usize,
+    ) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        let time = if now >= inner.instant {
+            inner.system + now.duration_since(inner.instant)
+        } else {
+            inner.system - inner.instant.duration_since(now)
+        };
+        let micros = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_micros() as u64);
+
+        let mut packet = vec![];
+        put_u32(&mut packet, 0); // interface id
+        put_u32(&mut packet, (micros >> 32) as u32);
+        put_u32(&mut packet, micros as u32);
+        put_u32(&mut packet, frame.len() as u32); // captured length
+        put_u32(&mut packet, (frame.len() + truncated) as u32); // original length
+        packet.extend_from_slice(frame);
+        pad(&mut packet);
+        put_u16(&mut packet, OPTION_EPB_FLAGS);
+        put_u16(&mut packet, 4);
+        put_u32(&mut packet, if inbound { 1 } else { 2 });
+        put_u32(&mut packet, 0); // end of options
+
+        let mut block = vec![];
+        put_block(&mut block, BLOCK_TYPE_ENHANCED_PACKET, &packet);
+        inner.writer.write_all(&block)
+    }
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn pad(buf: &mut Vec<u8>) {
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+fn put_block(buf: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+    let len = 12 + body.len().next_multiple_of(4);
+    put_u32(buf, block_type);
+    put_u32(buf, len as u32);
+    buf.extend_from_slice(body);
+    pad(buf);
+    put_u32(buf, len as u32);
+}
+
+/// Internet checksum of RFC 1071 over the chunks
+fn checksum(chunks: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    for chunk in chunks {
+        for word in chunk.chunks(2) {
+            let word = if word.len() == 2 {
+                u16::from_be_bytes([word[0], word[1]])
+            } else {
+                u16::from_be_bytes([word[0], 0])
+            };
+            sum += word as u32;
+        }
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Synthesizes an Ethernet frame of the segment from `src` to `dst`, with a TCP header of
+/// `seq` and `ack` or a UDP header
+fn frame(
+    src: SocketAddr,
+    dst: SocketAddr,
+    protocol: Protocol,
+    seq: u32,
+    ack: u32,
+    inbound: bool,
+    payload: &[u8],
+) -> Vec<u8> {
+    let (ip_protocol, mut l4) = match protocol {
+        Protocol::TCP => {
+            let mut tcp = vec![];
+            tcp.extend_from_slice(&src.port().to_be_bytes());
+            tcp.extend_from_slice(&dst.port().to_be_bytes());
+            tcp.extend_from_slice(&seq.to_be_bytes());
+            tcp.extend_from_slice(&ack.to_be_bytes());
+            tcp.push(5 << 4); // data offset
+            tcp.push(0x18); // PSH | ACK
+            tcp.extend_from_slice(&u16::MAX.to_be_bytes()); // window
+            tcp.extend_from_slice(&[0, 0, 0, 0]); // checksum, urgent pointer
+            (IP_PROTOCOL_TCP, tcp)
+        }
+        Protocol::UDP => {
+            let mut udp = vec![];
+            udp.extend_from_slice(&src.port().to_be_bytes());
+            udp.extend_from_slice(&dst.port().to_be_bytes());
+            udp.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+            udp.extend_from_slice(&[0, 0]); // checksum
+            (IP_PROTOCOL_UDP, udp)
+        }
+    };
+    let l4_len = l4.len() + payload.len();
+    let checksum_offset = if ip_protocol == IP_PROTOCOL_TCP {
+        16
+    } else {
+        6
+    };
+
+    // a dual-stack socket mixes IPv4 and IPv4-mapped IPv6 addresses
+    let (src_ip, dst_ip) = match (src.ip().to_canonical(), dst.ip().to_canonical()) {
+        (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) => (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)),
+        (src_ip, dst_ip) => (
+            IpAddr::V6(match src_ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            }),
+            IpAddr::V6(match dst_ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            }),
+        ),
+    };
+
+    let (ether_type, ip) = match (src_ip, dst_ip) {
+        (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) => {
+            let mut pseudo = vec![];
+            pseudo.extend_from_slice(&src_ip.octets());
+            pseudo.extend_from_slice(&dst_ip.octets());
+            pseudo.extend_from_slice(&[0, ip_protocol]);
+            pseudo.extend_from_slice(&(l4_len as u16).to_be_bytes());
+            let sum = checksum(&[&pseudo, &l4, payload]);
+            l4[checksum_offset..checksum_offset + 2].copy_from_slice(&sum.to_be_bytes());
+
+            let mut ip = vec![0x45, 0];
+            ip.extend_from_slice(&((20 + l4_len) as u16).to_be_bytes());
+            ip.extend_from_slice(&[0, 0, 0x40, 0]); // identification, don't fragment
+            ip.extend_from_slice(&[64, ip_protocol, 0, 0]); // ttl, protocol, checksum
+            ip.extend_from_slice(&src_ip.octets());
+            ip.extend_from_slice(&dst_ip.octets());
+            let sum = checksum(&[&ip]);
+            ip[10..12].copy_from_slice(&sum.to_be_bytes());
+            (ETHER_TYPE_IPV4, ip)
+        }
+        (IpAddr::V6(src_ip), IpAddr::V6(dst_ip)) => {
+            let mut pseudo = vec![];
+            pseudo.extend_from_slice(&src_ip.octets());
+            pseudo.extend_from_slice(&dst_ip.octets());
+            pseudo.extend_from_slice(&(l4_len as u32).to_be_bytes());
+            pseudo.extend_from_slice(&[0, 0, 0, ip_protocol]);
+            let sum = checksum(&[&pseudo, &l4, payload]);
+            l4[checksum_offset..checksum_offset + 2].copy_from_slice(&sum.to_be_bytes());
+
+            let mut ip = vec![0x60, 0, 0, 0];
+            ip.extend_from_slice(&(l4_len as u16).to_be_bytes());
+            ip.extend_from_slice(&[ip_protocol, 64]); // next header, hop limit
+            ip.extend_from_slice(&src_ip.octets());
+            ip.extend_from_slice(&dst_ip.octets());
+            (ETHER_TYPE_IPV6, ip)
+        }
+        _ => unreachable!("addresses are of the same family"),
+    };
+    // a zero UDP checksum means none
+    if ip_protocol == IP_PROTOCOL_UDP && l4[6..8] == [0, 0] {
+        l4[6..8].copy_from_slice(&[0xFF, 0xFF]);
+    }
+
+    let (src_mac, dst_mac) = if inbound {
+        (PEER_MAC, LOCAL_MAC)
+    } else {
+        (LOCAL_MAC, PEER_MAC)
+    };
+    let mut frame = Vec::with_capacity(14 + ip.len() + l4_len);
+    frame.extend_from_slice(&dst_mac);
+    frame.extend_from_slice(&src_mac);
+    frame.extend_from_slice(&ether_type.to_be_bytes());
+    frame.extend_from_slice(&ip);
+    frame.extend_from_slice(&l4);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// A pass-through handler that records inbound and outbound messages into a pcapng file as
+/// synthesized Ethernet/IP/UDP or TCP frames, with the addresses and protocol of their
+/// [TransportContext](crate::transport::TransportContext) and their `now` as timestamp, e.g. to
+/// inspect the traffic of a pipeline in Wireshark without root access.
+///
+/// Writes are recorded once they are polled towards the transport. TCP sequence numbers are
+/// counted per handler, so for TCP it is created per connection, and messages larger than 65000
+/// bytes are split into several frames. Each UDP datagram is a single frame, truncated to 65507
+/// bytes of payload with the original length kept. Once writing the file fails, capturing stops
+/// with a warning, while messages keep passing through.
+pub struct PcapCaptureHandler {
+    writer: PcapWriter,
+    failed: bool,
+    read_seq: u32,
+    write_seq: u32,
+}
+
+impl PcapCaptureHandler {
+    /// Creates a new PcapCaptureHandler writing to the writer
+    pub fn new(writer: PcapWriter) -> Self {
+        Self {
+            writer,
+            failed: false,
+            read_seq: 0,
+            write_seq: 0,
+        }
+    }
+
+    fn capture(&mut self, msg: &TaggedBytesMut, inbound: bool) {
+        if self.failed {
+            return;
+        }
+        let (src, dst) = if inbound {
+            (msg.transport.peer_addr, msg.transport.local_addr)
+        } else {
+            (msg.transport.local_addr, msg.transport.peer_addr)
+        };
+        let segments: Vec<&[u8]> = match msg.transport.protocol {
+            Protocol::UDP => {
+                // frames are IPv4 only if both addresses are, see frame()
+                let max_datagram_size =
+                    if src.ip().to_canonical().is_ipv4() && dst.ip().to_canonical().is_ipv4() {
+                        MAX_DATAGRAM_SIZE_V4
+                    } else {
+                        MAX_DATAGRAM_SIZE_V6
+                    };
+                vec![&msg.message[..msg.message.len().min(max_datagram_size)]]
+            }
+            Protocol::TCP if msg.message.is_empty() => vec![&[]],
+            Protocol::TCP => msg.message.chunks(MAX_SEGMENT_SIZE).collect(),
+        };
+        for segment in segments {
+            let truncated = match msg.transport.protocol {
+                Protocol::UDP => msg.message.len() - segment.len(),
+                Protocol::TCP => 0,
+            };
+            let (seq, ack) = if inbound {
+                (&mut self.read_seq, self.write_seq)
+            } else {
+                (&mut self.write_seq, self.read_seq)
+            };
+            let frame = frame(
+                src,
+                dst,
+                msg.transport.protocol,
+                *seq,
+                ack,
+                inbound,
+                segment,
+            );
+            *seq = seq.wrapping_add(segment.len() as u32);
+            if let Err(err) = self
+                .writer
+                .write_packet(msg.now, inbound, &frame, truncated)
+            {
+                warn!("pcap capture stops on write error {}", err);
+                self.failed = true;
+                return;
+            }
+        }
+    }
+}
+
+impl Handler for PcapCaptureHandler {
+    type Rin = TaggedBytesMut;
+    type Rout = Self::Rin;
+    type Win = TaggedBytesMut;
+    type Wout = Self::Win;
+
+    fn name(&self) -> &str {
+        "PcapCaptureHandler"
+    }
+
+    fn handle_read(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        msg: Self::Rin,
+    ) {
+        self.capture(&msg, true);
+        ctx.fire_read(msg);
+    }
+
+    fn poll_write(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> Option<Self::Wout> {
+        let msg = ctx.fire_poll_write();
+        if let Some(msg) = msg.as_ref() {
+            self.capture(msg, false);
+        }
+        msg
+    }
+}
@@ -0,0 +1,353 @@
+//! Handler for limiting the rate of inbound messages, globally and per peer
+
+use log::trace;
+use std::collections::{HashMap, VecDeque};
+use std::io::Error;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use crate::channel::{Context, Handler};
+use crate::handler::token_bucket::TokenBucket;
+use crate::transport::{FourTuple, TaggedBytesMut, TransportContext};
+
+/// How often buckets of peers which stopped sending are evicted
+const EVICTION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Limits of [RateLimitHandler], a limit of none or zero is unlimited. Bursts of up to one second
+/// worth of messages or bytes are allowed.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct RateLimit {
+    /// Max number of messages per second
+    pub messages_per_sec: Option<u64>,
+    /// Max number of bytes per second
+    pub bytes_per_sec: Option<u64>,
+}
+
+/// What the per peer limit of [RateLimitHandler] is keyed by
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RateLimitKey {
+    /// Local and peer address, i.e. a UDP association
+    FourTuple,
+    /// Peer IP address, covering all ports of the peer
+    PeerIp,
+}
+
+/// What [RateLimitHandler] does with messages over the limit
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RateLimitAction {
+    /// Drops them
+    Drop,
+    /// Drops them and fires an exception with the peer address
+    Exception,
+    /// Queues them in arrival order per peer, keyed like the per peer limit or else by
+    /// four-tuple, and fires them once the limits allow them. Reads from the transport go on
+    /// meanwhile, so messages beyond `max_delayed` queued ones of a peer, or beyond
+    /// `max_delayed_total` queued ones over all peers, are dropped.
+    Delay,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    FourTuple(FourTuple),
+    PeerIp(IpAddr),
+}
+
+struct Buckets {
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Buckets {
+    fn new(limit: &RateLimit, now: Instant) -> Self {
+        let bucket = |rate: Option<u64>| {
+            rate.filter(|rate| *rate > 0)
+                .map(|rate| TokenBucket::new(rate as f64, now))
+        };
+        Self {
+            messages: bucket(limit.messages_per_sec),
+            bytes: bucket(limit.bytes_per_sec),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        for bucket in [&mut self.messages, &mut self.bytes].into_iter().flatten() {
+            bucket.refill(now);
+        }
+    }
+
+    fn ready_at(&self, len: usize) -> Option<Instant> {
+        [
+            self.messages.as_ref().map(|bucket| bucket.ready_at(1.0)),
+            self.bytes
+                .as_ref()
+                .map(|bucket| bucket.ready_at(len as f64)),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+    }
+
+    fn take(&mut self, len: usize) {
+        if let Some(bucket) = self.messages.as_mut() {
+            bucket.take(1.0);
+        }
+        if let Some(bucket) = self.bytes.as_mut() {
+            bucket.take(len as f64);
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        [&self.messages, &self.bytes]
+            .into_iter()
+            .flatten()
+            .all(|bucket| bucket.is_full())
+    }
+}
+
+/// A handler that limits inbound messages and bytes per second with token buckets, both over all
+/// peers and per peer keyed by [RateLimitKey], e.g. to keep one peer from flooding the pipeline
+/// of a UDP server. Messages over the limit are handled by [RateLimitAction].
+pub struct RateLimitHandler {
+    action: RateLimitAction,
+    max_delayed: usize,
+    max_delayed_total: usize,
+    max_peers: usize,
+    global_limit: Option<RateLimit>,
+    peer_limit: Option<(RateLimitKey, RateLimit)>,
+
+    global: Option<Buckets>,
+    peers: HashMap<Key, Buckets>,
+    delayed: HashMap<Key, VecDeque<TaggedBytesMut>>,
+    delayed_len: usize,
+    next_eviction: Option<Instant>,
+}
+
+impl RateLimitHandler {
+    /// Creates a new RateLimitHandler without any limit
+    pub fn new(action: RateLimitAction) -> Self {
+        Self {
+            action,
+            max_delayed: 1024,
+            max_delayed_total: 65536,
+            max_peers: 65536,
+            global_limit: None,
+            peer_limit: None,
+
+            global: None,
+            peers: HashMap::new(),
+            delayed: HashMap::new(),
+            delayed_len: 0,
+            next_eviction: None,
+        }
+    }
+
+    /// Sets the limit over all peers
+    pub fn global(&mut self, limit: RateLimit) -> &mut Self {
+        self.global_limit = Some(limit);
+        self
+    }
+
+    /// Sets the limit of each peer, keyed by `key`
+    pub fn per_peer(&mut self, key: RateLimitKey, limit: RateLimit) -> &mut Self {
+        self.peer_limit = Some((key, limit));
+        self
+    }
+
+    /// Sets max number of messages queued by [RateLimitAction::Delay] per peer, default is 1024
+    pub fn max_delayed(&mut self, max_delayed: usize) -> &mut Self {
+        self.max_delayed = max_delayed;
+        self
+    }
+
+    /// Sets max number of messages queued by [RateLimitAction::Delay] over all peers, default is
+    /// 65536
+    pub fn max_delayed_total(&mut self, max_delayed_total: usize) -> &mut Self {
+        self.max_delayed_total = max_delayed_total;
+        self
+    }
+
+    /// Sets max number of peers tracked by the per peer limit, default is 65536. While that many
+    /// peers are over or near their limit, messages of new peers are over the limit.
+    pub fn max_peers(&mut self, max_peers: usize) -> &mut Self {
+        self.max_peers = max_peers;
+        self
+    }
+
+    fn key(key: RateLimitKey, transport: &TransportContext) -> Key {
+        match key {
+            RateLimitKey::FourTuple => Key::FourTuple(transport.into()),
+            RateLimitKey::PeerIp => Key::PeerIp(transport.peer_addr.ip()),
+        }
+    }
+
+    /// Returns the key of the queue of messages held back from the peer of the transport
+    fn delayed_key(&self, transport: &TransportContext) -> Key {
+        let key = self
+            .peer_limit
+            .as_ref()
+            .map_or(RateLimitKey::FourTuple, |(key, _)| *key);
+        Self::key(key, transport)
+    }
+
+    /// Returns when the message is allowed, as of the last refill. A peer without a bucket has a
+    /// full one, so only its known bucket is checked.
+    fn ready_at(&self, msg: &TaggedBytesMut) -> Option<Instant> {
+        let len = msg.message.len();
+        let global = self.global.as_ref().and_then(|global| global.ready_at(len));
+        let peer = self.peer_limit.as_ref().and_then(|(key, _)| {
+            self.peers
+                .get(&Self::key(*key, &msg.transport))
+                .and_then(|peer| peer.ready_at(len))
+        });
+        global.into_iter().chain(peer).max()
+    }
+
+    /// Takes tokens for the message if it is allowed now. The bucket of a new peer is only
+    /// created once its message is admitted, and a new peer is over the limit while `max_peers`
+    /// buckets exist.
+    fn try_admit(&mut self, msg: &TaggedBytesMut, now: Instant) -> bool {
+        if let Some(limit) = self.global_limit.as_ref() {
+            self.global
+                .get_or_insert_with(|| Buckets::new(limit, now))
+                .refill(now);
+        }
+        let peer_key = self
+            .peer_limit
+            .as_ref()
+            .map(|(key, _)| Self::key(*key, &msg.transport));
+        if let Some(peer) = peer_key.as_ref().and_then(|key| self.peers.get_mut(key)) {
+            peer.refill(now);
+        } else if peer_key.is_some() && self.peers.len() >= self.max_peers {
+            return false;
+        }
+        if self.ready_at(msg).is_some_and(|ready_at| ready_at > now) {
+            return false;
+        }
+
+        let len = msg.message.len();
+        if let Some(global) = self.global.as_mut() {
+            global.take(len);
+        }
+        if let (Some(key), Some((_, limit))) = (peer_key, self.peer_limit.as_ref()) {
+            self.peers
+                .entry(key)
+                .or_insert_with(|| Buckets::new(limit, now))
+                .take(len);
+            if self.next_eviction.is_none() {
+                self.next_eviction = Some(now + EVICTION_INTERVAL);
+            }
+        }
+        true
+    }
+}
+
+impl Handler for RateLimitHandler {
+    type Rin = TaggedBytesMut;
+    type Rout = Self::Rin;
+    type Win = TaggedBytesMut;
+    type Wout = Self::Win;
+
+    fn name(&self) -> &str {
+        "RateLimitHandler"
+    }
+
+    fn handle_read(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        msg: Self::Rin,
+    ) {
+        let delayed_key = self.delayed_key(&msg.transport);
+        if !self.delayed.contains_key(&delayed_key) && self.try_admit(&msg, msg.now) {
+            ctx.fire_read(msg);
+            return;
+        }
+
+        match self.action {
+            RateLimitAction::Drop => {
+                trace!("rate limit drops message from {}", msg.transport.peer_addr);
+            }
+            RateLimitAction::Exception => {
+                ctx.fire_exception(Box::new(Error::other(format!(
+                    "rate limit exceeded by {}",
+                    msg.transport.peer_addr
+                ))));
+            }
+            RateLimitAction::Delay => {
+                let len = self.delayed.get(&delayed_key).map_or(0, VecDeque::len);
+                if len < self.max_delayed && self.delayed_len < self.max_delayed_total {
+                    self.delayed.entry(delayed_key).or_default().push_back(msg);
+                    self.delayed_len += 1;
+                } else {
+                    trace!("rate limit drops message from {}", msg.transport.peer_addr);
+                }
+            }
+        }
+    }
+
+    fn poll_write(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> Option<Self::Wout> {
+        ctx.fire_poll_write()
+    }
+
+    fn handle_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        now: Instant,
+    ) {
+        // one message per peer at a time, so that peers share the global limit fairly
+        let mut delayed = std::mem::take(&mut self.delayed);
+        let mut admitted = true;
+        while admitted {
+            admitted = false;
+            for messages in delayed.values_mut() {
+                if messages.front().is_some_and(|msg| self.try_admit(msg, now)) {
+                    ctx.fire_read(messages.pop_front().unwrap());
+                    self.delayed_len -= 1;
+                    admitted = true;
+                }
+            }
+        }
+        delayed.retain(|_, messages| !messages.is_empty());
+        self.delayed = delayed;
+
+        if self
+            .next_eviction
+            .is_some_and(|next_eviction| next_eviction <= now)
+        {
+            // a full bucket is the same as a new one
+            self.peers.retain(|_, peer| {
+                peer.refill(now);
+                !peer.is_full()
+            });
+            self.next_eviction = if self.peers.is_empty() {
+                None
+            } else {
+                Some(now + EVICTION_INTERVAL)
+            };
+        }
+
+        ctx.fire_timeout(now);
+    }
+
+    fn poll_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        eto: &mut Instant,
+    ) {
+        if let Some(next_eviction) = self.next_eviction {
+            *eto = (*eto).min(next_eviction);
+        }
+        for front in self
+            .delayed
+            .values()
+            .filter_map(|messages| messages.front())
+        {
+            if let Some(ready_at) = self.ready_at(front) {
+                *eto = (*eto).min(ready_at);
+            }
+        }
+        ctx.fire_poll_timeout(eto);
+    }
+}
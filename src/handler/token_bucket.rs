@@ -0,0 +1,48 @@
+use std::time::{Duration, Instant};
+
+/// Token bucket which refills at `rate` tokens per second up to one second worth of tokens.
+///
+/// A cost larger than the capacity is allowed once the bucket is full, leaving it in debt,
+/// so that oversized messages are delayed rather than blocked forever.
+pub(crate) struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket, `rate` must be positive
+    pub(crate) fn new(rate: f64, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate,
+            last: now,
+        }
+    }
+
+    pub(crate) fn refill(&mut self, now: Instant) {
+        if now > self.last {
+            let elapsed = now.duration_since(self.last).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+            self.last = now;
+        }
+    }
+
+    /// Returns when `cost` tokens are available, as of the last refill
+    pub(crate) fn ready_at(&self, cost: f64) -> Instant {
+        let needed = cost.min(self.rate);
+        if self.tokens >= needed {
+            self.last
+        } else {
+            self.last + Duration::from_secs_f64((needed - self.tokens) / self.rate)
+        }
+    }
+
+    pub(crate) fn take(&mut self, cost: f64) {
+        self.tokens -= cost;
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.tokens >= self.rate
+    }
+}
@@ -0,0 +1,349 @@
+//! Handler for limiting the bandwidth of reads and writes, per connection and globally
+
+use std::collections::VecDeque;
+use std::io::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::channel::{Context, Handler};
+use crate::handler::token_bucket::TokenBucket;
+use crate::transport::TaggedBytesMut;
+
+/// Bandwidth limits shared by all [TrafficShapingHandler]s created with it, e.g. of all
+/// connections of a server. Clones share the same limits.
+#[derive(Clone)]
+pub struct GlobalTrafficShaping {
+    read: Option<Arc<Mutex<TokenBucket>>>,
+    write: Option<Arc<Mutex<TokenBucket>>>,
+}
+
+impl GlobalTrafficShaping {
+    /// Creates new global limits in bytes per second, none or zero is unlimited
+    pub fn new(read_bytes_per_sec: Option<u64>, write_bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            read: bucket(read_bytes_per_sec).map(|bucket| Arc::new(Mutex::new(bucket))),
+            write: bucket(write_bytes_per_sec).map(|bucket| Arc::new(Mutex::new(bucket))),
+        }
+    }
+}
+
+/// Traffic of a [TrafficShapingHandler], counted per `check_interval`. Clones share the same counts.
+#[derive(Clone, Default)]
+pub struct TrafficCounter {
+    counts: Arc<Mutex<TrafficCounts>>,
+}
+
+#[derive(Default)]
+struct TrafficCounts {
+    read_bytes: u64,
+    written_bytes: u64,
+    interval_read_bytes: u64,
+    interval_written_bytes: u64,
+    last_read_throughput: u64,
+    last_write_throughput: u64,
+}
+
+impl TrafficCounter {
+    /// Returns the number of bytes read so far
+    pub fn read_bytes(&self) -> u64 {
+        self.counts().read_bytes
+    }
+
+    /// Returns the number of bytes written so far
+    pub fn written_bytes(&self) -> u64 {
+        self.counts().written_bytes
+    }
+
+    /// Returns bytes per second read in the last complete interval
+    pub fn last_read_throughput(&self) -> u64 {
+        self.counts().last_read_throughput
+    }
+
+    /// Returns bytes per second written in the last complete interval
+    pub fn last_write_throughput(&self) -> u64 {
+        self.counts().last_write_throughput
+    }
+
+    fn counts(&self) -> std::sync::MutexGuard<'_, TrafficCounts> {
+        self.counts.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn record_read(&self, len: usize) {
+        let mut counts = self.counts();
+        counts.read_bytes += len as u64;
+        counts.interval_read_bytes += len as u64;
+    }
+
+    fn record_write(&self, len: usize) {
+        let mut counts = self.counts();
+        counts.written_bytes += len as u64;
+        counts.interval_written_bytes += len as u64;
+    }
+
+    fn end_interval(&self, interval: Duration) {
+        let mut counts = self.counts();
+        let secs = interval.as_secs_f64();
+        counts.last_read_throughput = (counts.interval_read_bytes as f64 / secs) as u64;
+        counts.last_write_throughput = (counts.interval_written_bytes as f64 / secs) as u64;
+        counts.interval_read_bytes = 0;
+        counts.interval_written_bytes = 0;
+    }
+}
+
+fn bucket(bytes_per_sec: Option<u64>) -> Option<TokenBucket> {
+    bytes_per_sec
+        .filter(|bytes_per_sec| *bytes_per_sec > 0)
+        .map(|bytes_per_sec| TokenBucket::new(bytes_per_sec as f64, Instant::now()))
+}
+
+/// Returns when `len` bytes are available in all buckets, or none if right away
+fn ready_at<'a>(
+    buckets: impl IntoIterator<Item = &'a mut TokenBucket>,
+    len: usize,
+    now: Instant,
+) -> Option<Instant> {
+    buckets
+        .into_iter()
+        .map(|bucket| {
+            bucket.refill(now);
+            bucket.ready_at(len as f64)
+        })
+        .max()
+        .filter(|ready_at| *ready_at > now)
+}
+
+/// A handler that limits the bandwidth of reads and writes in bytes per second, per connection,
+/// i.e. per pipeline, and globally with [GlobalTrafficShaping]. Bursts of up to one second worth
+/// of bytes are allowed.
+///
+/// Reads over the limit are held back in arrival order and pause reads from the transport through
+/// [poll_read_paused](Handler::poll_read_paused) until the limits allow them, so that a TCP peer
+/// is slowed down by flow control. As a UDP transport may read a batch of datagrams at once, up
+/// to `max_pending_reads` are held back, beyond which a read closes the pipeline with an
+/// exception, as a stream can't go on without it. Writes over the limit are held back in the pipeline until the limits
+/// allow them. Both are paced through [poll_timeout](Handler::poll_timeout) and
+/// [handle_timeout](Handler::handle_timeout).
+/// Traffic is counted per `check_interval` in a [TrafficCounter].
+pub struct TrafficShapingHandler {
+    read_bucket: Option<TokenBucket>,
+    write_bucket: Option<TokenBucket>,
+    global: Option<GlobalTrafficShaping>,
+    check_interval: Duration,
+    counter: TrafficCounter,
+    max_pending_reads: usize,
+
+    pending_reads: VecDeque<TaggedBytesMut>,
+    pending_write: Option<TaggedBytesMut>,
+    next_check: Option<Instant>,
+}
+
+impl TrafficShapingHandler {
+    /// Creates a new TrafficShapingHandler with per connection limits in bytes per second,
+    /// none or zero is unlimited
+    pub fn new(read_bytes_per_sec: Option<u64>, write_bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            read_bucket: bucket(read_bytes_per_sec),
+            write_bucket: bucket(write_bytes_per_sec),
+            global: None,
+            check_interval: Duration::from_secs(1),
+            counter: TrafficCounter::default(),
+            max_pending_reads: 1024,
+
+            pending_reads: VecDeque::new(),
+            pending_write: None,
+            next_check: None,
+        }
+    }
+
+    /// Applies global limits in addition to the per connection ones
+    pub fn global(&mut self, global: GlobalTrafficShaping) -> &mut Self {
+        self.global = Some(global);
+        self
+    }
+
+    /// Sets the interval traffic is counted per, default is 1s, zero disables per interval counts
+    pub fn check_interval(&mut self, check_interval: Duration) -> &mut Self {
+        self.check_interval = check_interval;
+        self
+    }
+
+    /// Sets max number of reads held back while reads are paused, default is 1024
+    pub fn max_pending_reads(&mut self, max_pending_reads: usize) -> &mut Self {
+        self.max_pending_reads = max_pending_reads;
+        self
+    }
+
+    /// Returns the counter of traffic through this handler
+    pub fn counter(&self) -> TrafficCounter {
+        self.counter.clone()
+    }
+
+    /// Returns when `len` bytes can be read, or none if right away
+    fn read_ready_at(&mut self, len: usize, now: Instant) -> Option<Instant> {
+        let mut global = self
+            .global
+            .as_ref()
+            .and_then(|global| global.read.as_ref())
+            .map(|read| read.lock().unwrap_or_else(|err| err.into_inner()));
+        ready_at(
+            self.read_bucket.iter_mut().chain(global.as_deref_mut()),
+            len,
+            now,
+        )
+    }
+
+    /// Returns when `len` bytes can be written, or none if right away
+    fn write_ready_at(&mut self, len: usize, now: Instant) -> Option<Instant> {
+        let mut global = self
+            .global
+            .as_ref()
+            .and_then(|global| global.write.as_ref())
+            .map(|write| write.lock().unwrap_or_else(|err| err.into_inner()));
+        ready_at(
+            self.write_bucket.iter_mut().chain(global.as_deref_mut()),
+            len,
+            now,
+        )
+    }
+
+    fn take_read(&mut self, len: usize) {
+        if let Some(read) = self.read_bucket.as_mut() {
+            read.take(len as f64);
+        }
+        if let Some(read) = self.global.as_ref().and_then(|global| global.read.as_ref()) {
+            read.lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .take(len as f64);
+        }
+        self.counter.record_read(len);
+    }
+
+    fn take_write(&mut self, len: usize) {
+        if let Some(write) = self.write_bucket.as_mut() {
+            write.take(len as f64);
+        }
+        if let Some(write) = self
+            .global
+            .as_ref()
+            .and_then(|global| global.write.as_ref())
+        {
+            write
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .take(len as f64);
+        }
+        self.counter.record_write(len);
+    }
+}
+
+impl Handler for TrafficShapingHandler {
+    type Rin = TaggedBytesMut;
+    type Rout = Self::Rin;
+    type Win = TaggedBytesMut;
+    type Wout = Self::Win;
+
+    fn name(&self) -> &str {
+        "TrafficShapingHandler"
+    }
+
+    fn transport_active(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        if !self.check_interval.is_zero() {
+            self.next_check = Some(Instant::now() + self.check_interval);
+        }
+        ctx.fire_transport_active();
+    }
+
+    fn transport_inactive(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+        self.next_check = None;
+        ctx.fire_transport_inactive();
+    }
+
+    fn handle_read(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        msg: Self::Rin,
+    ) {
+        if self.pending_reads.is_empty() && self.read_ready_at(msg.message.len(), msg.now).is_none()
+        {
+            self.take_read(msg.message.len());
+            ctx.fire_read(msg);
+        } else if self.pending_reads.len() < self.max_pending_reads {
+            self.pending_reads.push_back(msg);
+        } else {
+            ctx.fire_exception(Box::new(Error::other(format!(
+                "traffic shaping holds back too many reads from {}",
+                msg.transport.peer_addr
+            ))));
+            ctx.fire_close();
+        }
+    }
+
+    fn poll_write(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> Option<Self::Wout> {
+        if self.pending_write.is_none() {
+            self.pending_write = ctx.fire_poll_write();
+        }
+        let len = self.pending_write.as_ref()?.message.len();
+        if self.write_ready_at(len, Instant::now()).is_some() {
+            return None;
+        }
+        self.take_write(len);
+        self.pending_write.take()
+    }
+
+    fn handle_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        now: Instant,
+    ) {
+        while let Some(len) = self.pending_reads.front().map(|msg| msg.message.len()) {
+            if self.read_ready_at(len, now).is_some() {
+                break;
+            }
+            self.take_read(len);
+            ctx.fire_read(self.pending_reads.pop_front().unwrap());
+        }
+
+        if self.next_check.is_some_and(|next_check| next_check <= now) {
+            self.counter.end_interval(self.check_interval);
+            self.next_check = Some(now + self.check_interval);
+        }
+
+        ctx.fire_timeout(now);
+    }
+
+    fn poll_timeout(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        eto: &mut Instant,
+    ) {
+        // held back traffic which is allowed by now is due right away
+        let now = Instant::now();
+        let read_ready_at = self
+            .pending_reads
+            .front()
+            .map(|msg| msg.message.len())
+            .map(|len| self.read_ready_at(len, now).unwrap_or(now));
+        let write_ready_at = self
+            .pending_write
+            .as_ref()
+            .map(|msg| msg.message.len())
+            .map(|len| self.write_ready_at(len, now).unwrap_or(now));
+        for at in [read_ready_at, write_ready_at, self.next_check]
+            .into_iter()
+            .flatten()
+        {
+            *eto = (*eto).min(at);
+        }
+        ctx.fire_poll_timeout(eto);
+    }
+
+    fn poll_read_paused(
+        &mut self,
+        ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+    ) -> bool {
+        !self.pending_reads.is_empty() || ctx.fire_poll_read_paused()
+    }
+}
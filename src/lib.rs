@@ -261,4 +261,5 @@ pub mod bootstrap;
 pub mod channel;
 pub mod codec;
 pub mod executor;
+pub mod handler;
 pub mod transport;
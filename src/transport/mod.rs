@@ -27,6 +27,9 @@ pub struct TransportContext {
     pub protocol: Protocol,
     /// Explicit congestion notification bits to set on the packet
     pub ecn: Option<EcnCodepoint>,
+    /// Index of the network interface an inbound UDP packet arrived on, from
+    /// IP_PKTINFO/IPV6_PKTINFO, if known
+    pub interface_index: Option<u32>,
 }
 
 impl Default for TransportContext {
@@ -36,6 +39,7 @@ impl Default for TransportContext {
             peer_addr: SocketAddr::from_str("0.0.0.0:0").unwrap(),
             protocol: Protocol::UDP,
             ecn: None,
+            interface_index: None,
         }
     }
 }
@@ -0,0 +1,249 @@
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use std::cell::RefCell;
+    use std::error::Error;
+    use std::net::SocketAddr;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use retty::channel::{Context, Handler, InboundPipeline, Pipeline};
+    use retty::codec::byte_to_message_decoder::{
+        LineBasedFrameDecoder, MessageDecoder, TaggedByteToMessageCodec, TerminatorType,
+    };
+    use retty::transport::{TaggedBytesMut, TransportContext};
+
+    type Reads = Rc<RefCell<Vec<String>>>;
+
+    /// Records the peer and message of reads and exceptions which reach the end of the pipeline
+    struct RecordHandler {
+        reads: Reads,
+    }
+
+    impl Handler for RecordHandler {
+        type Rin = TaggedBytesMut;
+        type Rout = Self::Rin;
+        type Win = TaggedBytesMut;
+        type Wout = Self::Win;
+
+        fn name(&self) -> &str {
+            "RecordHandler"
+        }
+
+        fn handle_read(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            msg: Self::Rin,
+        ) {
+            self.reads.borrow_mut().push(format!(
+                "{} {}",
+                msg.transport.peer_addr,
+                String::from_utf8(msg.message.to_vec()).unwrap()
+            ));
+        }
+
+        fn handle_exception(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            _err: Box<dyn Error>,
+        ) {
+            self.reads.borrow_mut().push("error".to_string());
+        }
+
+        fn poll_write(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) -> Option<Self::Wout> {
+            ctx.fire_poll_write()
+        }
+    }
+
+    fn line_pipeline(
+        max_length: usize,
+        max_cumulation_size: Option<usize>,
+        max_cumulation_total_size: Option<usize>,
+    ) -> (Rc<Pipeline<TaggedBytesMut, TaggedBytesMut>>, Reads) {
+        let reads = Rc::new(RefCell::new(vec![]));
+        let mut codec = TaggedByteToMessageCodec::with_factory(Box::new(move || {
+            Box::new(LineBasedFrameDecoder::new(
+                max_length,
+                true,
+                TerminatorType::BOTH,
+            ))
+        }));
+        if let Some(max_cumulation_size) = max_cumulation_size {
+            codec.max_cumulation_size(max_cumulation_size);
+        }
+        if let Some(max_cumulation_total_size) = max_cumulation_total_size {
+            codec.max_cumulation_total_size(max_cumulation_total_size);
+        }
+        let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+        pipeline.add_back(codec);
+        pipeline.add_back(RecordHandler {
+            reads: Rc::clone(&reads),
+        });
+        let pipeline = pipeline.finalize();
+        pipeline.transport_active();
+        (pipeline, reads)
+    }
+
+    fn tagged(peer_addr: &str, message: &str) -> TaggedBytesMut {
+        TaggedBytesMut {
+            now: Instant::now(),
+            transport: TransportContext {
+                local_addr: "127.0.0.1:4000".parse().unwrap(),
+                peer_addr: peer_addr.parse::<SocketAddr>().unwrap(),
+                ..Default::default()
+            },
+            message: BytesMut::from(message),
+        }
+    }
+
+    #[test]
+    fn test_byte_to_message_codec_accumulates_partial_frames() {
+        let (pipeline, reads) = line_pipeline(64, None, None);
+
+        pipeline.read(tagged("10.0.0.1:1000", "hel"));
+        assert!(reads.take().is_empty());
+        pipeline.read(tagged("10.0.0.1:1000", "lo\r"));
+        assert!(reads.take().is_empty());
+        pipeline.read(tagged("10.0.0.1:1000", "\nworld\nagain\npar"));
+        assert_eq!(
+            vec![
+                "10.0.0.1:1000 hello",
+                "10.0.0.1:1000 world",
+                "10.0.0.1:1000 again"
+            ],
+            reads.take()
+        );
+        pipeline.read(tagged("10.0.0.1:1000", "tial\n"));
+        assert_eq!(vec!["10.0.0.1:1000 partial"], reads.take());
+    }
+
+    #[test]
+    fn test_byte_to_message_codec_accumulates_per_connection() {
+        let (pipeline, reads) = line_pipeline(64, None, None);
+
+        pipeline.read(tagged("10.0.0.1:1000", "a1"));
+        pipeline.read(tagged("10.0.0.2:2000", "b1"));
+        pipeline.read(tagged("10.0.0.1:1000", "a2\n"));
+        pipeline.read(tagged("10.0.0.2:2000", "b2\n"));
+        assert_eq!(
+            vec!["10.0.0.1:1000 a1a2", "10.0.0.2:2000 b1b2"],
+            reads.take()
+        );
+
+        // buffers are dropped once the transport is inactive
+        pipeline.read(tagged("10.0.0.1:1000", "stale"));
+        pipeline.transport_inactive();
+        pipeline.transport_active();
+        pipeline.read(tagged("10.0.0.1:1000", "fresh\n"));
+        assert_eq!(vec!["10.0.0.1:1000 fresh"], reads.take());
+    }
+
+    #[test]
+    fn test_byte_to_message_codec_max_cumulation_size() {
+        let (pipeline, reads) = line_pipeline(64, Some(8), None);
+
+        pipeline.read(tagged("10.0.0.1:1000", "0123"));
+        pipeline.read(tagged("10.0.0.2:2000", "4567"));
+        pipeline.read(tagged("10.0.0.1:1000", "89"));
+        assert!(reads.take().is_empty());
+
+        // each connection is capped on its own, the buffer of the read is discarded
+        pipeline.read(tagged("10.0.0.1:1000", "abc"));
+        assert_eq!(vec!["error"], reads.take());
+        pipeline.read(tagged("10.0.0.1:1000", "ab\n"));
+        pipeline.read(tagged("10.0.0.2:2000", "\n"));
+        assert_eq!(vec!["10.0.0.1:1000 ab", "10.0.0.2:2000 4567"], reads.take());
+    }
+
+    #[test]
+    fn test_byte_to_message_codec_max_cumulation_total_size() {
+        let (pipeline, reads) = line_pipeline(64, Some(8), Some(10));
+
+        pipeline.read(tagged("10.0.0.1:1000", "0123"));
+        pipeline.read(tagged("10.0.0.2:2000", "4567"));
+        assert!(reads.take().is_empty());
+
+        // below the cap of each connection, the cap over all connections is exceeded
+        pipeline.read(tagged("10.0.0.3:3000", "89ab"));
+        assert_eq!(vec!["error"], reads.take());
+        pipeline.read(tagged("10.0.0.3:3000", "c\n"));
+        assert_eq!(vec!["10.0.0.3:3000 c"], reads.take());
+
+        // decoded bytes make room again
+        pipeline.read(tagged("10.0.0.1:1000", "\n"));
+        pipeline.read(tagged("10.0.0.3:3000", "89ab"));
+        pipeline.read(tagged("10.0.0.2:2000", "\n"));
+        pipeline.read(tagged("10.0.0.3:3000", "\n"));
+        assert_eq!(
+            vec![
+                "10.0.0.1:1000 0123",
+                "10.0.0.2:2000 4567",
+                "10.0.0.3:3000 89ab"
+            ],
+            reads.take()
+        );
+    }
+
+    #[test]
+    fn test_byte_to_message_codec_decoder_per_connection() {
+        let (pipeline, reads) = line_pipeline(4, None, None);
+
+        // the decoder of a connection discarding a too long line doesn't discard other lines
+        pipeline.read(tagged("10.0.0.1:1000", "toolong"));
+        assert_eq!(vec!["error"], reads.take());
+        pipeline.read(tagged("10.0.0.2:2000", "ok\n"));
+        assert_eq!(vec!["10.0.0.2:2000 ok"], reads.take());
+        pipeline.read(tagged("10.0.0.1:1000", "ng\nok\n"));
+        assert_eq!(vec!["10.0.0.1:1000 ok"], reads.take());
+    }
+
+    /// Fails on a buffer starting with '!' without consuming it, and decodes lines otherwise
+    struct FailingDecoder(LineBasedFrameDecoder);
+
+    impl MessageDecoder for FailingDecoder {
+        fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<BytesMut>, std::io::Error> {
+            if buf.starts_with(b"!") {
+                return Err(std::io::Error::other("bad frame"));
+            }
+            self.0.decode(buf)
+        }
+    }
+
+    #[test]
+    fn test_byte_to_message_codec_discards_on_decode_error() {
+        let reads = Rc::new(RefCell::new(vec![]));
+        let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+        pipeline.add_back(TaggedByteToMessageCodec::new(Box::new(FailingDecoder(
+            LineBasedFrameDecoder::new(64, true, TerminatorType::BOTH),
+        ))));
+        pipeline.add_back(RecordHandler {
+            reads: Rc::clone(&reads),
+        });
+        let pipeline = pipeline.finalize();
+        pipeline.transport_active();
+
+        pipeline.read(tagged("10.0.0.1:1000", "!bad"));
+        assert_eq!(vec!["error"], reads.take());
+        pipeline.read(tagged("10.0.0.1:1000", "ok\n"));
+        assert_eq!(vec!["10.0.0.1:1000 ok"], reads.take());
+    }
+
+    #[test]
+    fn test_byte_to_message_codec_evicts_idle_cumulations() {
+        let (pipeline, reads) = line_pipeline(64, None, None);
+
+        let start = Instant::now();
+        pipeline.read(tagged("10.0.0.1:1000", "stale"));
+        let mut eto = start + Duration::from_secs(60);
+        pipeline.poll_timeout(&mut eto);
+        assert!(eto > start && eto <= Instant::now() + Duration::from_secs(30));
+
+        // the buffer of a peer which went quiet is dropped without transport_inactive
+        pipeline.handle_timeout(eto);
+        pipeline.read(tagged("10.0.0.1:1000", "fresh\n"));
+        assert_eq!(vec!["10.0.0.1:1000 fresh"], reads.take());
+    }
+}
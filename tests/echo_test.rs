@@ -130,9 +130,11 @@ mod tests {
                 server.pipeline(Box::new(move || {
                     let pipeline: Pipeline<TaggedBytesMut, TaggedString> = Pipeline::new();
 
-                    let line_based_frame_decoder_handler = TaggedByteToMessageCodec::new(Box::new(
-                        LineBasedFrameDecoder::new(8192, true, TerminatorType::BOTH),
-                    ));
+                    // the pipeline serves every peer, so each gets a decoder of its own
+                    let line_based_frame_decoder_handler =
+                        TaggedByteToMessageCodec::with_factory(Box::new(|| {
+                            Box::new(LineBasedFrameDecoder::new(8192, true, TerminatorType::BOTH))
+                        }));
                     let string_codec_handler = TaggedStringCodec::new();
                     let echo_handler = EchoHandler::new(
                         true,
@@ -194,6 +196,7 @@ mod tests {
                                 peer_addr: server_addr,
                                 ecn: EcnCodepoint::from_bits(1),
                                 protocol: Protocol::UDP,
+                                interface_index: None,
                             },
                             message: format!("{}\r\n", i),
                         });
@@ -206,8 +209,9 @@ mod tests {
                             peer_addr: server_addr,
                             ecn: EcnCodepoint::from_bits(1),
                             protocol: Protocol::UDP,
+                            interface_index: None,
                         },
-                        message: format!("bye\r\n"),
+                        message: "bye\r\n".to_string(),
                     });
                     yield_local();
 
@@ -222,9 +226,9 @@ mod tests {
                 let client_count = rx.recv().await.unwrap();
                 assert!(server_done_rx.recv().await.is_some());
 
-                let (client_count, server_count) = (client_count.borrow(), server_count.borrow());
-                assert_eq!(*client_count, *server_count);
-                assert_eq!(ITER + 1, *client_count);
+                let (client_count, server_count) = (*client_count.borrow(), *server_count.borrow());
+                assert_eq!(client_count, server_count);
+                assert_eq!(ITER + 1, client_count);
 
                 server.graceful_stop().await;
             })
@@ -309,6 +313,7 @@ mod tests {
                             peer_addr: server_addr,
                             ecn: None,
                             protocol: Protocol::TCP,
+                            interface_index: None,
                         },
                         message: format!("{}\r\n", i),
                     });
@@ -321,8 +326,9 @@ mod tests {
                         peer_addr: server_addr,
                         ecn: None,
                         protocol: Protocol::TCP,
+                        interface_index: None,
                     },
-                    message: format!("bye\r\n"),
+                    message: "bye\r\n".to_string(),
                 });
                 yield_local();
 
@@ -337,9 +343,9 @@ mod tests {
             let client_count = rx.recv().await.unwrap();
             assert!(server_done_rx.recv().await.is_some());
 
-            let (client_count, server_count) = (client_count.borrow(), server_count.borrow());
-            assert_eq!(*client_count, *server_count);
-            assert_eq!(ITER + 1, *client_count);
+            let (client_count, server_count) = (*client_count.borrow(), *server_count.borrow());
+            assert_eq!(client_count, server_count);
+            assert_eq!(ITER + 1, client_count);
 
             server.graceful_stop().await;
         });
@@ -0,0 +1,777 @@
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use std::any::Any;
+    use std::cell::RefCell;
+    use std::error::Error;
+    use std::net::SocketAddr;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use retty::channel::{Context, Handler, InboundPipeline, OutboundPipeline, Pipeline};
+    use retty::handler::{
+        hex_dump, IdleStateEvent, IdleStateHandler, Impairment, ImpairmentHandler, IpCidr,
+        IpFilter, IpFilterAction, IpFilterHandler, IpFilterRule, LogMessage, LoggingHandler,
+        PcapCaptureHandler, PcapWriter, RateLimit, RateLimitAction, RateLimitHandler, RateLimitKey,
+        TrafficShapingHandler,
+    };
+    use retty::transport::{TaggedBytesMut, TransportContext};
+
+    type IdleEvents = Rc<RefCell<Vec<IdleStateEvent>>>;
+    type Capture = Arc<Mutex<Vec<u8>>>;
+    type Reads = Rc<RefCell<Vec<String>>>;
+
+    /// Records the messages and exceptions which reach the end of the pipeline
+    struct RecordHandler {
+        reads: Reads,
+    }
+
+    impl Handler for RecordHandler {
+        type Rin = TaggedBytesMut;
+        type Rout = Self::Rin;
+        type Win = TaggedBytesMut;
+        type Wout = Self::Win;
+
+        fn name(&self) -> &str {
+            "RecordHandler"
+        }
+
+        fn handle_read(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            msg: Self::Rin,
+        ) {
+            let message = String::from_utf8(msg.message.to_vec()).unwrap();
+            self.reads.borrow_mut().push(message);
+        }
+
+        fn handle_exception(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            err: Box<dyn Error>,
+        ) {
+            self.reads.borrow_mut().push(format!("error: {}", err));
+        }
+
+        fn poll_write(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) -> Option<Self::Wout> {
+            ctx.fire_poll_write()
+        }
+    }
+
+    fn record_pipeline(
+        handler: impl Handler<
+                Rin = TaggedBytesMut,
+                Rout = TaggedBytesMut,
+                Win = TaggedBytesMut,
+                Wout = TaggedBytesMut,
+            > + 'static,
+    ) -> (Rc<Pipeline<TaggedBytesMut, TaggedBytesMut>>, Reads) {
+        let reads = Rc::new(RefCell::new(vec![]));
+        let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+        pipeline.add_back(handler);
+        pipeline.add_back(RecordHandler {
+            reads: Rc::clone(&reads),
+        });
+        let pipeline = pipeline.finalize();
+        pipeline.transport_active();
+        (pipeline, reads)
+    }
+
+    fn tagged(now: Instant, peer_addr: &str, message: &str) -> TaggedBytesMut {
+        TaggedBytesMut {
+            now,
+            transport: TransportContext {
+                local_addr: "127.0.0.1:4000".parse().unwrap(),
+                peer_addr: peer_addr.parse::<SocketAddr>().unwrap(),
+                ..Default::default()
+            },
+            message: BytesMut::from(message),
+        }
+    }
+
+    /// Records the idle events which reach the end of the pipeline
+    struct IdleEventHandler {
+        events: IdleEvents,
+    }
+
+    impl Handler for IdleEventHandler {
+        type Rin = String;
+        type Rout = Self::Rin;
+        type Win = String;
+        type Wout = Self::Win;
+
+        fn name(&self) -> &str {
+            "IdleEventHandler"
+        }
+
+        fn handle_read(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            _msg: Self::Rin,
+        ) {
+        }
+
+        fn poll_write(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) -> Option<Self::Wout> {
+            ctx.fire_poll_write()
+        }
+
+        fn handle_event(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            evt: Box<dyn Any>,
+        ) {
+            if let Some(evt) = evt.downcast_ref::<IdleStateEvent>() {
+                self.events.borrow_mut().push(*evt);
+            }
+        }
+    }
+
+    fn idle_pipeline(
+        reader_idle_time: Duration,
+        writer_idle_time: Duration,
+        all_idle_time: Duration,
+    ) -> (Rc<Pipeline<String, String>>, IdleEvents) {
+        let events = Rc::new(RefCell::new(vec![]));
+        let pipeline: Pipeline<String, String> = Pipeline::new();
+        pipeline.add_back(IdleStateHandler::<String, String>::new(
+            reader_idle_time,
+            writer_idle_time,
+            all_idle_time,
+        ));
+        pipeline.add_back(IdleEventHandler {
+            events: Rc::clone(&events),
+        });
+        (pipeline.finalize(), events)
+    }
+
+    #[test]
+    fn test_idle_state_handler_fires_periodically() {
+        let (pipeline, events) = idle_pipeline(
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+            Duration::from_secs(3),
+        );
+        pipeline.transport_active();
+        let start = Instant::now();
+
+        let mut eto = start + Duration::from_secs(60);
+        pipeline.poll_timeout(&mut eto);
+        assert!(eto <= start + Duration::from_secs(1));
+
+        pipeline.handle_timeout(start + Duration::from_millis(1500));
+        assert_eq!(vec![IdleStateEvent::ReaderIdle], events.take());
+
+        // reader idle fires again one period after the previous event
+        pipeline.handle_timeout(start + Duration::from_millis(2500));
+        assert_eq!(
+            vec![IdleStateEvent::ReaderIdle, IdleStateEvent::WriterIdle],
+            events.take()
+        );
+
+        pipeline.handle_timeout(start + Duration::from_millis(3500));
+        assert_eq!(
+            vec![IdleStateEvent::ReaderIdle, IdleStateEvent::AllIdle],
+            events.take()
+        );
+
+        // no events once the transport is inactive
+        pipeline.transport_inactive();
+        pipeline.handle_timeout(start + Duration::from_secs(60));
+        assert!(events.take().is_empty());
+    }
+
+    #[test]
+    fn test_idle_state_handler_resets_on_activity() {
+        let (pipeline, events) = idle_pipeline(
+            Duration::from_millis(50),
+            Duration::from_millis(50),
+            Duration::ZERO,
+        );
+        pipeline.transport_active();
+        std::thread::sleep(Duration::from_millis(30));
+
+        pipeline.read("request".to_string());
+        pipeline.handle_timeout(Instant::now() + Duration::from_millis(30));
+        assert_eq!(vec![IdleStateEvent::WriterIdle], events.take());
+
+        std::thread::sleep(Duration::from_millis(30));
+        pipeline.write("response".to_string());
+        assert_eq!(Some("response".to_string()), pipeline.poll_transmit());
+        pipeline.handle_timeout(Instant::now() + Duration::from_millis(30));
+        assert_eq!(vec![IdleStateEvent::ReaderIdle], events.take());
+    }
+
+    #[test]
+    fn test_rate_limit_handler_per_peer_drop() {
+        let mut handler = RateLimitHandler::new(RateLimitAction::Drop);
+        handler.per_peer(
+            RateLimitKey::PeerIp,
+            RateLimit {
+                messages_per_sec: Some(2),
+                bytes_per_sec: None,
+            },
+        );
+        let (pipeline, reads) = record_pipeline(handler);
+
+        let start = Instant::now();
+        pipeline.read(tagged(start, "10.0.0.1:1000", "a1"));
+        pipeline.read(tagged(start, "10.0.0.1:1001", "a2"));
+        pipeline.read(tagged(start, "10.0.0.1:1000", "a3"));
+        pipeline.read(tagged(start, "10.0.0.2:1000", "b1"));
+        assert_eq!(vec!["a1", "a2", "b1"], reads.take());
+
+        // tokens refill over time
+        pipeline.read(tagged(
+            start + Duration::from_millis(500),
+            "10.0.0.1:1000",
+            "a4",
+        ));
+        pipeline.read(tagged(
+            start + Duration::from_millis(500),
+            "10.0.0.1:1000",
+            "a5",
+        ));
+        assert_eq!(vec!["a4"], reads.take());
+    }
+
+    #[test]
+    fn test_rate_limit_handler_max_peers() {
+        let mut handler = RateLimitHandler::new(RateLimitAction::Drop);
+        handler
+            .max_peers(2)
+            .global(RateLimit {
+                messages_per_sec: Some(1),
+                bytes_per_sec: None,
+            })
+            .per_peer(
+                RateLimitKey::PeerIp,
+                RateLimit {
+                    messages_per_sec: Some(1),
+                    bytes_per_sec: None,
+                },
+            );
+        let (pipeline, reads) = record_pipeline(handler);
+
+        // a peer over the global limit gets no bucket, leaving room for another one
+        let start = Instant::now();
+        pipeline.read(tagged(start, "10.0.0.1:1000", "a1"));
+        pipeline.read(tagged(start, "10.0.0.2:1000", "b1"));
+        let later = start + Duration::from_secs(1);
+        pipeline.read(tagged(later, "10.0.0.3:1000", "c1"));
+        assert_eq!(vec!["a1", "c1"], reads.take());
+
+        // new peers are over the limit while the buckets of max_peers peers exist
+        let later = later + Duration::from_secs(1);
+        pipeline.read(tagged(later, "10.0.0.4:1000", "d1"));
+        assert!(reads.take().is_empty());
+
+        // until the full buckets are evicted
+        pipeline.handle_timeout(later);
+        pipeline.read(tagged(later, "10.0.0.4:1000", "d2"));
+        assert_eq!(vec!["d2"], reads.take());
+    }
+
+    #[test]
+    fn test_rate_limit_handler_global_exception() {
+        let mut handler = RateLimitHandler::new(RateLimitAction::Exception);
+        handler.global(RateLimit {
+            messages_per_sec: None,
+            bytes_per_sec: Some(10),
+        });
+        let (pipeline, reads) = record_pipeline(handler);
+
+        let start = Instant::now();
+        pipeline.read(tagged(start, "10.0.0.1:1000", "12345678"));
+        pipeline.read(tagged(start, "10.0.0.2:1000", "12345678"));
+        assert_eq!(
+            vec!["12345678", "error: rate limit exceeded by 10.0.0.2:1000"],
+            reads.take()
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_handler_delay() {
+        let mut handler = RateLimitHandler::new(RateLimitAction::Delay);
+        handler.max_delayed(2).global(RateLimit {
+            messages_per_sec: Some(1),
+            bytes_per_sec: None,
+        });
+        let (pipeline, reads) = record_pipeline(handler);
+
+        let start = Instant::now();
+        for message in ["m1", "m2", "m3", "m4"] {
+            pipeline.read(tagged(start, "10.0.0.1:1000", message));
+        }
+        assert_eq!(vec!["m1"], reads.take());
+
+        // held back messages are released in order once tokens are available
+        let mut eto = start + Duration::from_secs(60);
+        pipeline.poll_timeout(&mut eto);
+        assert_eq!(start + Duration::from_secs(1), eto);
+        pipeline.handle_timeout(start + Duration::from_millis(500));
+        assert!(reads.take().is_empty());
+        pipeline.handle_timeout(start + Duration::from_secs(1));
+        assert_eq!(vec!["m2"], reads.take());
+        pipeline.handle_timeout(start + Duration::from_secs(2));
+        assert_eq!(vec!["m3"], reads.take());
+        pipeline.handle_timeout(start + Duration::from_secs(3));
+        assert!(reads.take().is_empty());
+    }
+
+    #[test]
+    fn test_rate_limit_handler_delay_total() {
+        let mut handler = RateLimitHandler::new(RateLimitAction::Delay);
+        handler
+            .max_delayed(2)
+            .max_delayed_total(3)
+            .global(RateLimit {
+                messages_per_sec: Some(1),
+                bytes_per_sec: None,
+            });
+        let (pipeline, reads) = record_pipeline(handler);
+
+        // the total cap holds over all peers, even below the cap of each peer
+        let start = Instant::now();
+        for (peer_addr, message) in [
+            ("10.0.0.1:1000", "a1"),
+            ("10.0.0.1:1000", "a2"),
+            ("10.0.0.2:1000", "b1"),
+            ("10.0.0.3:1000", "c1"),
+            ("10.0.0.4:1000", "d1"),
+        ] {
+            pipeline.read(tagged(start, peer_addr, message));
+        }
+        assert_eq!(vec!["a1"], reads.take());
+
+        // a released message makes room for another one
+        pipeline.handle_timeout(start + Duration::from_secs(1));
+        let mut released = reads.take();
+        let now = start + Duration::from_secs(1);
+        pipeline.read(tagged(now, "10.0.0.3:1000", "c2"));
+        pipeline.read(tagged(now, "10.0.0.3:1000", "c3"));
+        for secs in 2..=5 {
+            pipeline.handle_timeout(start + Duration::from_secs(secs));
+            released.extend(reads.take());
+        }
+        released.sort();
+        assert_eq!(vec!["a2", "b1", "c1", "c2"], released);
+    }
+
+    #[test]
+    fn test_rate_limit_handler_delay_per_peer() {
+        let mut handler = RateLimitHandler::new(RateLimitAction::Delay);
+        handler.per_peer(
+            RateLimitKey::PeerIp,
+            RateLimit {
+                messages_per_sec: Some(1),
+                bytes_per_sec: None,
+            },
+        );
+        let (pipeline, reads) = record_pipeline(handler);
+
+        // messages held back from a flooding peer don't hold back other peers
+        let start = Instant::now();
+        for message in ["a1", "a2", "a3"] {
+            pipeline.read(tagged(start, "10.0.0.1:1000", message));
+        }
+        pipeline.read(tagged(start, "10.0.0.2:1000", "b1"));
+        pipeline.read(tagged(start, "10.0.0.2:1000", "b2"));
+        assert_eq!(vec!["a1", "b1"], reads.take());
+
+        pipeline.handle_timeout(start + Duration::from_secs(1));
+        let mut released = reads.take();
+        released.sort();
+        assert_eq!(vec!["a2", "b2"], released);
+        pipeline.handle_timeout(start + Duration::from_secs(2));
+        assert_eq!(vec!["a3"], reads.take());
+    }
+
+    #[test]
+    fn test_ip_cidr() {
+        let cidr: IpCidr = "10.1.0.0/16".parse().unwrap();
+        assert!(cidr.contains("10.1.2.3".parse().unwrap()));
+        assert!(!cidr.contains("10.2.0.1".parse().unwrap()));
+        assert!(cidr.contains("::ffff:10.1.0.1".parse().unwrap()));
+        assert!(!cidr.contains("2001:db8::1".parse().unwrap()));
+
+        let cidr: IpCidr = "2001:db8::/32".parse().unwrap();
+        assert!(cidr.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!cidr.contains("2001:db9::1".parse().unwrap()));
+
+        let cidr: IpCidr = "0.0.0.0/0".parse().unwrap();
+        assert!(cidr.contains("192.0.2.1".parse().unwrap()));
+        let cidr: IpCidr = "192.0.2.1".parse().unwrap();
+        assert_eq!("192.0.2.1/32", cidr.to_string());
+
+        // host bits are cleared
+        let cidr: IpCidr = "10.1.2.3/16".parse().unwrap();
+        assert_eq!("10.1.0.0/16", cidr.to_string());
+        assert_eq!("10.1.0.0/16".parse::<IpCidr>().unwrap(), cidr);
+        assert!(cidr.contains("10.1.0.1".parse().unwrap()));
+        let cidr: IpCidr = "2001:db8::1/32".parse().unwrap();
+        assert_eq!("2001:db8::/32", cidr.to_string());
+
+        assert!("10.0.0.0/33".parse::<IpCidr>().is_err());
+        assert!("10.0.0.0/".parse::<IpCidr>().is_err());
+        assert!("example.com/8".parse::<IpCidr>().is_err());
+    }
+
+    #[test]
+    fn test_ip_filter_handler() {
+        // the first matching rule decides
+        let filter = IpFilter::new(
+            vec![
+                IpFilterRule::allow("10.0.0.1".parse().unwrap()),
+                IpFilterRule::deny("10.0.0.0/8".parse().unwrap()),
+            ],
+            IpFilterAction::Allow,
+        );
+        let (pipeline, reads) = record_pipeline(IpFilterHandler::new(filter.clone()));
+
+        let now = Instant::now();
+        pipeline.read(tagged(now, "10.0.0.1:1000", "allowed"));
+        pipeline.read(tagged(now, "10.0.0.2:1000", "denied"));
+        pipeline.read(tagged(now, "192.0.2.1:1000", "default"));
+        assert_eq!(vec!["allowed", "default"], reads.take());
+
+        // rules are swapped through the shared handle
+        filter.set_rules(
+            vec![IpFilterRule::allow("10.0.0.0/8".parse().unwrap())],
+            IpFilterAction::Deny,
+        );
+        pipeline.read(tagged(now, "10.0.0.2:1000", "allowed"));
+        pipeline.read(tagged(now, "192.0.2.1:1000", "denied"));
+        assert_eq!(vec!["allowed"], reads.take());
+    }
+
+    #[test]
+    fn test_traffic_shaping_handler_read() {
+        let mut handler = TrafficShapingHandler::new(Some(10), None);
+        handler
+            .check_interval(Duration::from_secs(1))
+            .max_pending_reads(2);
+        let counter = handler.counter();
+        let (pipeline, reads) = record_pipeline(handler);
+
+        // held back reads pause reads, and a read beyond max_pending_reads closes the pipeline
+        let start = Instant::now();
+        pipeline.read(tagged(start, "10.0.0.1:1000", "0123456789"));
+        assert!(!pipeline.poll_read_paused());
+        for message in ["abcdefghij", "x", "closes"] {
+            pipeline.read(tagged(start, "10.0.0.1:1000", message));
+        }
+        assert!(pipeline.poll_read_paused());
+        assert_eq!(
+            vec![
+                "0123456789",
+                "error: traffic shaping holds back too many reads from 10.0.0.1:1000"
+            ],
+            reads.take()
+        );
+        assert_eq!(10, counter.read_bytes());
+
+        // held back reads are released in order once the limit allows them
+        let mut eto = start + Duration::from_secs(60);
+        pipeline.poll_timeout(&mut eto);
+        assert!(eto <= start + Duration::from_secs(1));
+        pipeline.handle_timeout(start + Duration::from_secs(1));
+        assert_eq!(vec!["abcdefghij"], reads.take());
+        pipeline.handle_timeout(start + Duration::from_secs(2));
+        assert_eq!(vec!["x"], reads.take());
+        assert!(!pipeline.poll_read_paused());
+        assert_eq!(21, counter.read_bytes());
+        assert_eq!(0, counter.written_bytes());
+        assert_eq!(1, counter.last_read_throughput());
+    }
+
+    #[test]
+    fn test_traffic_shaping_handler_write() {
+        let handler = TrafficShapingHandler::new(None, Some(10));
+        let counter = handler.counter();
+        let (pipeline, _) = record_pipeline(handler);
+
+        let now = Instant::now();
+        pipeline.write(tagged(now, "10.0.0.1:1000", "0123456789"));
+        pipeline.write(tagged(now, "10.0.0.1:1000", "abcdefghij"));
+        assert_eq!(
+            "0123456789",
+            String::from_utf8(pipeline.poll_transmit().unwrap().message.to_vec()).unwrap()
+        );
+
+        // the second write is held back until the limit allows it
+        assert!(pipeline.poll_transmit().is_none());
+        let mut eto = now + Duration::from_secs(60);
+        pipeline.poll_timeout(&mut eto);
+        assert!(eto > now && eto <= Instant::now() + Duration::from_secs(1));
+        assert_eq!(10, counter.written_bytes());
+        assert_eq!(0, counter.read_bytes());
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        assert_eq!(
+            "         +-------------------------------------------------+\n\
+             \x20        |  0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f |\n\
+             +--------+-------------------------------------------------+----------------+\n\
+             |00000000| 48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 0d 0a 00 |Hello, world!...|\n\
+             |00000010| 41 42                                           |AB              |\n\
+             +--------+-------------------------------------------------+----------------+",
+            hex_dump(b"Hello, world!\r\n\0AB", 64)
+        );
+        assert!(hex_dump(b"Hello, world!\r\n\0AB", 4).ends_with(
+            "|00000000| 48 65 6c 6c                                     |Hell            |\n\
+             +--------+-------------------------------------------------+----------------+\n\
+             ... 14 more bytes"
+        ));
+
+        let msg = tagged(Instant::now(), "10.0.0.1:1000", "Hello");
+        assert!(msg
+            .log_message(64)
+            .starts_with("[UDP L:127.0.0.1:4000 - R:10.0.0.1:1000] 5B\n"));
+        assert_eq!(
+            "\"Hel\"... 2 more chars",
+            "Hello".to_string().log_message(3)
+        );
+    }
+
+    #[test]
+    fn test_logging_handler_passes_through() {
+        let mut handler = LoggingHandler::new(log::Level::Info);
+        handler.max_dump_len(16);
+        let (pipeline, reads) = record_pipeline(handler);
+
+        let now = Instant::now();
+        pipeline.read(tagged(now, "10.0.0.1:1000", "hello"));
+        assert_eq!(vec!["hello"], reads.take());
+        pipeline.write(tagged(now, "10.0.0.1:1000", "world"));
+        assert_eq!(
+            "world",
+            String::from_utf8(pipeline.poll_transmit().unwrap().message.to_vec()).unwrap()
+        );
+        pipeline.handle_exception(Box::new(std::io::Error::other("oops")));
+        assert_eq!(vec!["error: oops"], reads.take());
+    }
+
+    thread_local! {
+        static LOG_LINES: RefCell<Vec<String>> = const { RefCell::new(vec![]) };
+    }
+
+    /// Collects log lines of LoggingHandler on the current thread
+    struct CaptureLogger;
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == "retty::handler::logging_handler"
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                LOG_LINES.with(|lines| lines.borrow_mut().push(record.args().to_string()));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_logging_handler_logs_last_transport() {
+        static LOGGER: CaptureLogger = CaptureLogger;
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Trace);
+
+        // active is logged before any transport is known
+        let (pipeline, _) = record_pipeline(LoggingHandler::new(log::Level::Info));
+        pipeline.read(tagged(Instant::now(), "10.0.0.1:1000", "hello"));
+        pipeline.handle_read_eof();
+
+        let lines = LOG_LINES.with(|lines| lines.take());
+        assert_eq!("ACTIVE", lines[0]);
+        assert!(lines[1].starts_with("READ: [UDP L:127.0.0.1:4000 - R:10.0.0.1:1000] 5B"));
+        assert_eq!(
+            "READ EOF [UDP L:127.0.0.1:4000 - R:10.0.0.1:1000]",
+            lines[2]
+        );
+    }
+
+    /// Collects what is written into a shared buffer
+    struct CaptureWriter(Capture);
+
+    impl std::io::Write for CaptureWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Returns type and body of each pcapng block
+    fn pcapng_blocks(capture: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        let mut blocks = vec![];
+        let mut rest = capture;
+        while !rest.is_empty() {
+            let block_type = u32::from_le_bytes(rest[0..4].try_into().unwrap());
+            let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            assert_eq!(rest[4..8], rest[len - 4..len]);
+            blocks.push((block_type, rest[8..len - 4].to_vec()));
+            rest = &rest[len..];
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_pcap_capture_handler() {
+        let capture = Arc::new(Mutex::new(vec![]));
+        let writer = PcapWriter::new(CaptureWriter(Arc::clone(&capture))).unwrap();
+        let (pipeline, reads) = record_pipeline(PcapCaptureHandler::new(writer));
+
+        let now = Instant::now();
+        pipeline.read(tagged(now, "10.0.0.1:1000", "ping"));
+        assert_eq!(vec!["ping"], reads.take());
+        pipeline.write(tagged(now, "10.0.0.1:1000", "pong!"));
+        assert!(pipeline.poll_transmit().is_some());
+
+        let blocks = pcapng_blocks(&capture.lock().unwrap());
+        let block_types: Vec<u32> = blocks.iter().map(|(block_type, _)| *block_type).collect();
+        assert_eq!(vec![0x0A0D0D0A, 1, 6, 6], block_types);
+
+        // Ethernet, IPv4 and UDP headers with peer to local inbound and local to peer outbound
+        for ((_, packet), (src, dst, payload)) in blocks[2..].iter().zip([
+            (
+                [10, 0, 0, 1, 0x03, 0xe8],
+                [127, 0, 0, 1, 0x0f, 0xa0],
+                "ping",
+            ),
+            (
+                [127, 0, 0, 1, 0x0f, 0xa0],
+                [10, 0, 0, 1, 0x03, 0xe8],
+                "pong!",
+            ),
+        ]) {
+            let len = u32::from_le_bytes(packet[12..16].try_into().unwrap()) as usize;
+            assert_eq!(14 + 20 + 8 + payload.len(), len);
+            let frame = &packet[20..20 + len];
+            assert_eq!([0x08, 0x00], frame[12..14]);
+            assert_eq!(17, frame[23]);
+            assert_eq!(src[..4], frame[26..30]);
+            assert_eq!(dst[..4], frame[30..34]);
+            assert_eq!(src[4..], frame[34..36]);
+            assert_eq!(dst[4..], frame[36..38]);
+            assert_eq!(payload.as_bytes(), &frame[42..]);
+        }
+    }
+
+    #[test]
+    fn test_impairment_handler_inbound() {
+        let start = Instant::now();
+        let read = |impairment: Impairment| {
+            let mut handler = ImpairmentHandler::new();
+            handler.seed(1).inbound(impairment);
+            let (pipeline, reads) = record_pipeline(handler);
+            pipeline.read(tagged(start, "10.0.0.1:1000", "hello"));
+            (pipeline, reads)
+        };
+
+        let (_, reads) = read(Impairment {
+            loss: 1.0,
+            ..Default::default()
+        });
+        assert!(reads.take().is_empty());
+
+        let (_, reads) = read(Impairment {
+            duplicate: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(vec!["hello", "hello"], reads.take());
+
+        let (_, reads) = read(Impairment {
+            corrupt: 1.0,
+            ..Default::default()
+        });
+        let corrupted = reads.take().pop().unwrap();
+        let flipped: u32 = corrupted
+            .bytes()
+            .zip("hello".bytes())
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum();
+        assert_eq!(1, flipped);
+
+        // delayed reads are delivered on timeout
+        let (pipeline, reads) = read(Impairment {
+            delay: Duration::from_millis(100),
+            ..Default::default()
+        });
+        assert!(reads.take().is_empty());
+        let mut eto = start + Duration::from_secs(60);
+        pipeline.poll_timeout(&mut eto);
+        assert_eq!(start + Duration::from_millis(100), eto);
+        pipeline.handle_timeout(start + Duration::from_millis(50));
+        assert!(reads.take().is_empty());
+        pipeline.handle_timeout(start + Duration::from_millis(100));
+        assert_eq!(vec!["hello"], reads.take());
+    }
+
+    #[test]
+    fn test_impairment_handler_outbound() {
+        let mut handler = ImpairmentHandler::new();
+        handler.outbound(Impairment {
+            delay: Duration::from_millis(100),
+            reorder: 1.0,
+            reorder_delay: Duration::from_millis(100),
+            ..Default::default()
+        });
+        let (pipeline, _) = record_pipeline(handler);
+
+        let start = Instant::now();
+        pipeline.write(tagged(start, "10.0.0.1:1000", "hello"));
+        assert!(pipeline.poll_transmit().is_none());
+        let mut eto = start + Duration::from_secs(60);
+        pipeline.poll_timeout(&mut eto);
+        assert_eq!(start + Duration::from_millis(200), eto);
+        pipeline.handle_timeout(start + Duration::from_millis(200));
+        let transmit = pipeline.poll_transmit().unwrap();
+        assert_eq!(start + Duration::from_millis(200), transmit.now);
+        assert_eq!(b"hello", &transmit.message[..]);
+    }
+
+    #[test]
+    fn test_impairment_handler_is_reproducible() {
+        let run = |seed: u64| {
+            let mut handler = ImpairmentHandler::new();
+            handler.seed(seed).inbound(Impairment {
+                loss: 0.2,
+                jitter: Duration::from_millis(100),
+                duplicate: 0.2,
+                ..Default::default()
+            });
+            let (pipeline, reads) = record_pipeline(handler);
+            let start = Instant::now();
+            for i in 0..50 {
+                pipeline.read(tagged(
+                    start + Duration::from_millis(i * 10),
+                    "10.0.0.1:1000",
+                    &i.to_string(),
+                ));
+            }
+            pipeline.handle_timeout(start + Duration::from_secs(1));
+            reads.take()
+        };
+
+        let reads = run(7);
+        assert_eq!(reads, run(7));
+        assert_ne!(reads, run(8));
+        // jitter reorders datagrams
+        let mut sorted = reads.clone();
+        sorted.sort_by_key(|read| read.parse::<u32>().unwrap());
+        assert_ne!(reads, sorted);
+    }
+}
@@ -0,0 +1,430 @@
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+    use std::io::ErrorKind;
+    use std::net::SocketAddr;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use retty::bootstrap::PcapReplay;
+    use retty::channel::{Context, Handler, InboundPipeline, Pipeline};
+    use retty::handler::{PcapCaptureHandler, PcapWriter};
+    use retty::transport::{Protocol, TaggedBytesMut, TransportContext};
+
+    type Capture = Arc<Mutex<Vec<u8>>>;
+
+    /// Echoes reads and writes a tick `tick` after each read
+    struct EchoHandler {
+        tick: Option<Duration>,
+        next_tick: Option<(Instant, TransportContext)>,
+        transmits: VecDeque<TaggedBytesMut>,
+    }
+
+    impl EchoHandler {
+        fn new(tick: Option<Duration>) -> Self {
+            Self {
+                tick,
+                next_tick: None,
+                transmits: VecDeque::new(),
+            }
+        }
+    }
+
+    impl Handler for EchoHandler {
+        type Rin = TaggedBytesMut;
+        type Rout = Self::Rin;
+        type Win = TaggedBytesMut;
+        type Wout = Self::Win;
+
+        fn name(&self) -> &str {
+            "EchoHandler"
+        }
+
+        fn handle_read(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            msg: Self::Rin,
+        ) {
+            if let Some(tick) = self.tick {
+                self.next_tick = Some((msg.now + tick, msg.transport));
+            }
+            self.transmits.push_back(msg);
+        }
+
+        fn poll_write(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) -> Option<Self::Wout> {
+            if let Some(msg) = ctx.fire_poll_write() {
+                self.transmits.push_back(msg);
+            }
+            self.transmits.pop_front()
+        }
+
+        fn handle_timeout(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            now: Instant,
+        ) {
+            if let Some((at, transport)) = self.next_tick {
+                if at <= now {
+                    self.next_tick = None;
+                    self.transmits.push_back(TaggedBytesMut {
+                        now,
+                        transport,
+                        message: BytesMut::from("tick"),
+                    });
+                }
+            }
+        }
+
+        fn poll_timeout(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            eto: &mut Instant,
+        ) {
+            if let Some((at, _)) = self.next_tick {
+                *eto = (*eto).min(at);
+            }
+        }
+    }
+
+    /// Collects what is written into a shared buffer
+    struct CaptureWriter(Capture);
+
+    impl std::io::Write for CaptureWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn tagged(now: Instant, protocol: Protocol, peer_addr: &str, message: &str) -> TaggedBytesMut {
+        TaggedBytesMut {
+            now,
+            transport: TransportContext {
+                local_addr: "127.0.0.1:4000".parse().unwrap(),
+                peer_addr: peer_addr.parse::<SocketAddr>().unwrap(),
+                protocol,
+                ..Default::default()
+            },
+            message: BytesMut::from(message),
+        }
+    }
+
+    fn echo_pipeline(
+        writer: Option<&PcapWriter>,
+        tick: Option<Duration>,
+    ) -> Rc<Pipeline<TaggedBytesMut, TaggedBytesMut>> {
+        let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+        if let Some(writer) = writer {
+            pipeline.add_back(PcapCaptureHandler::new(writer.clone()));
+        }
+        pipeline.add_back(EchoHandler::new(tick));
+        pipeline.finalize()
+    }
+
+    fn summary(transmits: &[TaggedBytesMut]) -> Vec<(Protocol, String, String)> {
+        transmits
+            .iter()
+            .map(|transmit| {
+                (
+                    transmit.transport.protocol,
+                    transmit.transport.peer_addr.to_string(),
+                    String::from_utf8(transmit.message.to_vec()).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pcap_capture_replay() {
+        let capture = Arc::new(Mutex::new(vec![]));
+        let writer = PcapWriter::new(CaptureWriter(Arc::clone(&capture))).unwrap();
+
+        // the UDP server pipeline and a TCP connection pipeline share the capture
+        let udp_pipeline = echo_pipeline(Some(&writer), None);
+        let tcp_pipeline = echo_pipeline(Some(&writer), None);
+        let start = Instant::now();
+        for (pipeline, msg) in [
+            (
+                &udp_pipeline,
+                tagged(start, Protocol::UDP, "10.0.0.1:1000", "a"),
+            ),
+            (
+                &tcp_pipeline,
+                tagged(start, Protocol::TCP, "10.0.0.3:3000", "b"),
+            ),
+            (
+                &udp_pipeline,
+                tagged(
+                    start + Duration::from_secs(1),
+                    Protocol::UDP,
+                    "[2001:db8::1]:2000",
+                    "c",
+                ),
+            ),
+        ] {
+            pipeline.read(msg);
+            while pipeline.poll_transmit().is_some() {}
+        }
+
+        let pipelines = Rc::new(Cell::new(0));
+        let mut replay = PcapReplay::new(&capture.lock().unwrap()[..]).unwrap();
+        replay.pipeline(Box::new({
+            let pipelines = Rc::clone(&pipelines);
+            move || {
+                pipelines.set(pipelines.get() + 1);
+                echo_pipeline(None, None)
+            }
+        }));
+        let transmits = replay.run().unwrap();
+
+        // only inbound packets are replayed, the echoes are transmitted again
+        assert_eq!(
+            vec![
+                (Protocol::UDP, "10.0.0.1:1000".to_string(), "a".to_string()),
+                (Protocol::TCP, "10.0.0.3:3000".to_string(), "b".to_string()),
+                (
+                    Protocol::UDP,
+                    "[2001:db8::1]:2000".to_string(),
+                    "c".to_string()
+                ),
+            ],
+            summary(&transmits)
+        );
+        assert_eq!(
+            "127.0.0.1:4000".parse::<SocketAddr>().unwrap(),
+            transmits[1].transport.local_addr
+        );
+        assert_eq!(
+            Duration::from_secs(1),
+            transmits[2].now.duration_since(transmits[0].now)
+        );
+        assert_eq!(2, pipelines.get());
+    }
+
+    #[test]
+    fn test_pcap_capture_udp_whole() {
+        // Ethernet, IPv4 and UDP headers are 42 bytes, a mixed IPv4 and IPv6 pair is captured as
+        // IPv6 with headers of 62 bytes
+        for (peer_addr, header_len, max_datagram_size) in [
+            ("10.0.0.1:1000", 42, 65507),
+            ("[2001:db8::1]:1000", 62, 65527),
+        ] {
+            let capture = Arc::new(Mutex::new(vec![]));
+            let writer = PcapWriter::new(CaptureWriter(Arc::clone(&capture))).unwrap();
+            let pipeline = echo_pipeline(Some(&writer), None);
+            let start = Instant::now();
+            for len in [65100, 70000] {
+                let mut msg = tagged(start, Protocol::UDP, peer_addr, "");
+                msg.message = BytesMut::from(&vec![b'x'; len][..]);
+                pipeline.read(msg);
+            }
+
+            // each datagram is a single frame, one too large for an IP packet is truncated
+            let capture = capture.lock().unwrap();
+            let mut lengths = vec![];
+            let mut offset = 0;
+            while offset < capture.len() {
+                let u32_at =
+                    |at: usize| u32::from_le_bytes(capture[at..at + 4].try_into().unwrap());
+                if u32_at(offset) == 6 {
+                    lengths.push((u32_at(offset + 20), u32_at(offset + 24)));
+                }
+                offset += u32_at(offset + 4) as usize;
+            }
+            assert_eq!(
+                vec![
+                    (65100 + header_len, 65100 + header_len),
+                    (max_datagram_size + header_len, 70000 + header_len)
+                ],
+                lengths
+            );
+
+            let mut replay = PcapReplay::new(&capture[..]).unwrap();
+            replay.pipeline(Box::new(|| echo_pipeline(None, None)));
+            let lengths: Vec<u32> = replay
+                .run()
+                .unwrap()
+                .iter()
+                .map(|transmit| transmit.message.len() as u32)
+                .collect();
+            assert_eq!(vec![65100, max_datagram_size], lengths);
+        }
+    }
+
+    fn udp_frame(src: ([u8; 4], u16), dst: ([u8; 4], u16), payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&[0x08, 0x00]);
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&((28 + payload.len()) as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0x40, 0, 64, 17, 0, 0]);
+        frame.extend_from_slice(&src.0);
+        frame.extend_from_slice(&dst.0);
+        frame.extend_from_slice(&src.1.to_be_bytes());
+        frame.extend_from_slice(&dst.1.to_be_bytes());
+        frame.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(payload);
+        // Ethernet padding to the minimum frame size
+        frame.resize(frame.len().max(60), 0);
+        frame
+    }
+
+    #[test]
+    fn test_pcap_replay_local_addr() {
+        let mut capture = vec![];
+        capture.extend_from_slice(&0xA1B2C3D4u32.to_le_bytes());
+        capture.extend_from_slice(&[2, 0, 4, 0]); // version
+        capture.extend_from_slice(&[0; 8]); // time zone, accuracy
+        capture.extend_from_slice(&65535u32.to_le_bytes());
+        capture.extend_from_slice(&1u32.to_le_bytes()); // Ethernet
+        let client = ([198, 51, 100, 1], 6000);
+        let server = ([192, 0, 2, 1], 5000);
+        for (secs, micros, frame) in [
+            (100u32, 0u32, udp_frame(client, server, b"request")),
+            (100, 250_000, udp_frame(server, client, b"response")),
+            (101, 500_000, udp_frame(client, server, b"again")),
+        ] {
+            capture.extend_from_slice(&secs.to_le_bytes());
+            capture.extend_from_slice(&micros.to_le_bytes());
+            capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&frame);
+        }
+
+        let mut replay = PcapReplay::new(&capture[..]).unwrap();
+        replay
+            .local_addr("0.0.0.0:5000".parse().unwrap())
+            .pipeline(Box::new(|| echo_pipeline(None, None)));
+        let transmits = replay.run().unwrap();
+
+        assert_eq!(
+            vec![
+                (
+                    Protocol::UDP,
+                    "198.51.100.1:6000".to_string(),
+                    "request".to_string()
+                ),
+                (
+                    Protocol::UDP,
+                    "198.51.100.1:6000".to_string(),
+                    "again".to_string()
+                ),
+            ],
+            summary(&transmits)
+        );
+        assert_eq!(
+            "192.0.2.1:5000".parse::<SocketAddr>().unwrap(),
+            transmits[0].transport.local_addr
+        );
+        assert_eq!(
+            Duration::from_millis(1500),
+            transmits[1].now.duration_since(transmits[0].now)
+        );
+
+        assert!(PcapReplay::<TaggedBytesMut>::new(&b"not a capture"[..]).is_err());
+        assert!(PcapReplay::<TaggedBytesMut>::new(&capture[..capture.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_pcap_replay_time_out_of_range() {
+        let block = |capture: &mut Vec<u8>, block_type: u32, body: &[u8]| {
+            let len = (12 + body.len()) as u32;
+            capture.extend_from_slice(&block_type.to_le_bytes());
+            capture.extend_from_slice(&len.to_le_bytes());
+            capture.extend_from_slice(body);
+            capture.extend_from_slice(&len.to_le_bytes());
+        };
+        let mut capture = vec![];
+        let mut section = 0x1A2B3C4Du32.to_le_bytes().to_vec();
+        section.extend_from_slice(&[1, 0, 0, 0]); // version
+        section.extend_from_slice(&(-1i64).to_le_bytes()); // section length
+        block(&mut capture, 0x0A0D0D0A, &section);
+        let mut interface = vec![1, 0, 0, 0]; // Ethernet
+        interface.extend_from_slice(&65535u32.to_le_bytes());
+        interface.extend_from_slice(&[9, 0, 1, 0, 0, 0, 0, 0]); // if_tsresol of seconds
+        block(&mut capture, 1, &interface);
+        let client = ([198, 51, 100, 1], 6000);
+        let server = ([192, 0, 2, 1], 5000);
+        let mut first_packet_end = 0;
+        for timestamp in [0, u64::MAX] {
+            let mut frame = udp_frame(client, server, b"request");
+            let mut packet = 0u32.to_le_bytes().to_vec();
+            packet.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+            packet.extend_from_slice(&(timestamp as u32).to_le_bytes());
+            packet.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            packet.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            frame.resize(frame.len().next_multiple_of(4), 0);
+            packet.extend_from_slice(&frame);
+            block(&mut capture, 6, &packet);
+            if timestamp == 0 {
+                first_packet_end = capture.len();
+            }
+        }
+
+        // the packet is too far from the first one for an Instant
+        let mut replay = PcapReplay::new(&capture[..]).unwrap();
+        replay.pipeline(Box::new(|| echo_pipeline(None, None)));
+        let err = replay.run().err().unwrap();
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+
+        // as is a linger too long after the last packet
+        let mut replay = PcapReplay::new(&capture[..first_packet_end]).unwrap();
+        replay
+            .linger(Duration::MAX)
+            .pipeline(Box::new(|| echo_pipeline(None, None)));
+        let err = replay.run().err().unwrap();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn test_pcap_replay_virtual_time() {
+        let capture = Arc::new(Mutex::new(vec![]));
+        let writer = PcapWriter::new(CaptureWriter(Arc::clone(&capture))).unwrap();
+        let pipeline = echo_pipeline(Some(&writer), None);
+        let start = Instant::now();
+        for (at, message) in [(0, "a"), (2, "b")] {
+            pipeline.read(tagged(
+                start + Duration::from_secs(at),
+                Protocol::UDP,
+                "10.0.0.1:1000",
+                message,
+            ));
+        }
+
+        // timeouts are handled at their virtual time between packets and while lingering
+        let mut replay = PcapReplay::new(&capture.lock().unwrap()[..]).unwrap();
+        replay.linger(Duration::from_secs(1)).pipeline(Box::new(|| {
+            echo_pipeline(None, Some(Duration::from_millis(500)))
+        }));
+        let transmits = replay.run().unwrap();
+
+        let messages: Vec<(String, Duration)> = transmits
+            .iter()
+            .map(|transmit| {
+                (
+                    String::from_utf8(transmit.message.to_vec()).unwrap(),
+                    transmit.now.duration_since(transmits[0].now),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("a".to_string(), Duration::ZERO),
+                ("tick".to_string(), Duration::from_millis(500)),
+                ("b".to_string(), Duration::from_secs(2)),
+                ("tick".to_string(), Duration::from_millis(2500)),
+            ],
+            messages
+        );
+    }
+}
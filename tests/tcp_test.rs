@@ -0,0 +1,1053 @@
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use futures_lite::{AsyncReadExt, AsyncWriteExt};
+    use smol::net::{TcpListener, TcpStream};
+    use std::any::Any;
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+    use std::io::ErrorKind;
+    use std::net::Shutdown;
+    #[cfg(unix)]
+    use std::os::{fd::AsRawFd, unix::process::CommandExt};
+    #[cfg(unix)]
+    use std::process::Command;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use local_sync::mpsc::{unbounded::channel, unbounded::Tx as LocalSender};
+    #[cfg(unix)]
+    use retty::bootstrap::listen_fds;
+    use retty::bootstrap::{
+        AcceptDecision, AcceptEvent, BootstrapTcpClient, BootstrapTcpServer, DrainEvent,
+        ReconnectPolicy, TcpConnectionPool,
+    };
+    use retty::channel::{Context, Handler, Pipeline};
+    use retty::executor::LocalExecutorBuilder;
+    use retty::handler::{
+        IpFilter, IpFilterAction, IpFilterHandler, IpFilterRule, TrafficShapingHandler,
+    };
+    use retty::transport::{Protocol, TaggedBytesMut, TransportContext};
+
+    const LARGE_PAYLOAD_SIZE: usize = 8 * 1024 * 1024;
+
+    /// Collects the request until the peer half-closes, then answers and half-closes too
+    struct RequestResponseHandler {
+        request: Option<TaggedBytesMut>,
+        transmits: VecDeque<TaggedBytesMut>,
+    }
+
+    impl RequestResponseHandler {
+        fn new() -> Self {
+            Self {
+                request: None,
+                transmits: VecDeque::new(),
+            }
+        }
+    }
+
+    impl Handler for RequestResponseHandler {
+        type Rin = TaggedBytesMut;
+        type Rout = Self::Rin;
+        type Win = TaggedBytesMut;
+        type Wout = Self::Win;
+
+        fn name(&self) -> &str {
+            "RequestResponseHandler"
+        }
+
+        fn handle_read(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            msg: Self::Rin,
+        ) {
+            if let Some(request) = self.request.as_mut() {
+                request.message.extend_from_slice(&msg.message);
+            } else {
+                self.request = Some(msg);
+            }
+        }
+
+        fn handle_read_eof(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+            if let Some(request) = self.request.take() {
+                let mut message = BytesMut::from("response to ");
+                message.extend_from_slice(&request.message);
+                self.transmits.push_back(TaggedBytesMut {
+                    now: Instant::now(),
+                    transport: request.transport,
+                    message,
+                });
+            }
+            ctx.fire_shutdown_output();
+        }
+
+        fn poll_write(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) -> Option<Self::Wout> {
+            if let Some(msg) = ctx.fire_poll_write() {
+                self.transmits.push_back(msg);
+            }
+            self.transmits.pop_front()
+        }
+    }
+
+    /// Reports transport_active and transport_inactive events
+    struct ActiveHandler {
+        tx: LocalSender<bool>,
+    }
+
+    impl Handler for ActiveHandler {
+        type Rin = TaggedBytesMut;
+        type Rout = Self::Rin;
+        type Win = TaggedBytesMut;
+        type Wout = Self::Win;
+
+        fn name(&self) -> &str {
+            "ActiveHandler"
+        }
+
+        fn transport_active(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) {
+            let _ = self.tx.send(true);
+            ctx.fire_transport_active();
+        }
+
+        fn transport_inactive(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) {
+            let _ = self.tx.send(false);
+            ctx.fire_transport_inactive();
+        }
+
+        fn handle_read(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            msg: Self::Rin,
+        ) {
+            ctx.fire_read(msg);
+        }
+
+        fn poll_write(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) -> Option<Self::Wout> {
+            ctx.fire_poll_write()
+        }
+    }
+
+    /// Writes a goodbye on drain and closes if `close_on_drain`, and reports a close event from the
+    /// pipeline
+    struct GoodbyeHandler {
+        tx: LocalSender<()>,
+        close_on_drain: bool,
+        transport: Option<TransportContext>,
+        transmits: VecDeque<TaggedBytesMut>,
+    }
+
+    impl Handler for GoodbyeHandler {
+        type Rin = TaggedBytesMut;
+        type Rout = Self::Rin;
+        type Win = TaggedBytesMut;
+        type Wout = Self::Win;
+
+        fn name(&self) -> &str {
+            "GoodbyeHandler"
+        }
+
+        fn handle_read(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            msg: Self::Rin,
+        ) {
+            self.transport = Some(msg.transport);
+            let _ = self.tx.send(());
+        }
+
+        fn handle_event(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            evt: Box<dyn Any>,
+        ) {
+            if evt.downcast_ref::<DrainEvent>().is_none() {
+                ctx.fire_event(evt);
+                return;
+            }
+            if let Some(transport) = self.transport {
+                self.transmits.push_back(TaggedBytesMut {
+                    now: Instant::now(),
+                    transport,
+                    message: BytesMut::from("bye"),
+                });
+            }
+            if self.close_on_drain {
+                ctx.fire_close();
+            }
+        }
+
+        fn handle_close(&mut self, ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>) {
+            let _ = self.tx.send(());
+            ctx.fire_close();
+        }
+
+        fn poll_write(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) -> Option<Self::Wout> {
+            if let Some(msg) = ctx.fire_poll_write() {
+                self.transmits.push_back(msg);
+            }
+            self.transmits.pop_front()
+        }
+    }
+
+    #[test]
+    fn test_half_close_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let mut server = BootstrapTcpServer::new();
+            server.allow_half_close(true).pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(RequestResponseHandler::new());
+                pipeline.finalize()
+            }));
+            let server_addr = server.bind("127.0.0.1:0").await.unwrap();
+
+            let mut client = TcpStream::connect(server_addr).await.unwrap();
+            client.write_all(b"request").await.unwrap();
+            client.shutdown(Shutdown::Write).unwrap();
+
+            let mut response = vec![];
+            client.read_to_end(&mut response).await.unwrap();
+            assert_eq!(b"response to request".to_vec(), response);
+
+            server.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_large_payload_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = listener.local_addr().unwrap();
+
+            let mut client = BootstrapTcpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.finalize()
+            }));
+            let pipeline = client.connect(server_addr).await.unwrap();
+            let (mut server, _) = listener.accept().await.unwrap();
+
+            // several transmits, each much larger than the socket send buffer
+            let payload: Vec<u8> = (0..LARGE_PAYLOAD_SIZE).map(|i| i as u8).collect();
+            for chunk in payload.chunks(LARGE_PAYLOAD_SIZE / 4) {
+                pipeline.write(TaggedBytesMut {
+                    now: Instant::now(),
+                    transport: TransportContext {
+                        protocol: Protocol::TCP,
+                        ..Default::default()
+                    },
+                    message: BytesMut::from(chunk),
+                });
+            }
+            pipeline.shutdown_output();
+
+            let mut received = vec![];
+            server.read_to_end(&mut received).await.unwrap();
+            assert_eq!(payload.len(), received.len());
+            assert!(payload == received);
+
+            client.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_many_transmits_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = listener.local_addr().unwrap();
+
+            let mut client = BootstrapTcpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.finalize()
+            }));
+            let pipeline = client.connect(server_addr).await.unwrap();
+            let (mut server, _) = listener.accept().await.unwrap();
+
+            // more transmits than one vectored write takes, they are pulled in order
+            let mut payload = vec![];
+            for i in 0..1000u32 {
+                payload.extend_from_slice(&i.to_be_bytes());
+                pipeline.write(TaggedBytesMut {
+                    now: Instant::now(),
+                    transport: TransportContext {
+                        protocol: Protocol::TCP,
+                        ..Default::default()
+                    },
+                    message: BytesMut::from(&i.to_be_bytes()[..]),
+                });
+            }
+            pipeline.shutdown_output();
+
+            let mut received = vec![];
+            server.read_to_end(&mut received).await.unwrap();
+            assert_eq!(payload, received);
+
+            client.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_connect_address_fallback_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = listener.local_addr().unwrap();
+            let closed_addr = {
+                let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+                listener.local_addr().unwrap()
+            };
+
+            let mut client = BootstrapTcpClient::new();
+            client
+                .connect_timeout(Duration::from_secs(5))
+                .pipeline(Box::new(move || {
+                    let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                    pipeline.finalize()
+                }));
+
+            // the refused address is skipped
+            let addrs = [closed_addr, server_addr];
+            assert!(client.connect(&addrs[..]).await.is_ok());
+            listener.accept().await.unwrap();
+
+            // the error lists every failed address
+            let addrs = [closed_addr, closed_addr];
+            let err = match client.connect(&addrs[..]).await {
+                Ok(_) => panic!("connect to closed ports should fail"),
+                Err(err) => err,
+            };
+            assert_eq!(ErrorKind::ConnectionRefused, err.kind());
+            assert_eq!(2, err.to_string().matches(&closed_addr.to_string()).count());
+
+            client.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_reconnect_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, mut rx) = channel();
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = listener.local_addr().unwrap();
+
+            let mut client = BootstrapTcpClient::new();
+            client
+                .reconnect(ReconnectPolicy {
+                    initial_backoff: Duration::from_millis(10),
+                    ..Default::default()
+                })
+                .pipeline(Box::new(move || {
+                    let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                    pipeline.add_back(ActiveHandler { tx: tx.clone() });
+                    pipeline.finalize()
+                }));
+            let pipeline = client.connect(server_addr).await.unwrap();
+
+            // the server drops the first connection
+            let (server, _) = listener.accept().await.unwrap();
+            assert_eq!(Some(true), rx.recv().await);
+            drop(server);
+            assert_eq!(Some(false), rx.recv().await);
+
+            // the same pipeline is active again on the new connection
+            let (mut server, _) = listener.accept().await.unwrap();
+            assert_eq!(Some(true), rx.recv().await);
+            pipeline.write(TaggedBytesMut {
+                now: Instant::now(),
+                transport: TransportContext {
+                    protocol: Protocol::TCP,
+                    ..Default::default()
+                },
+                message: BytesMut::from("hello"),
+            });
+            let mut buf = [0u8; 5];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(b"hello", &buf);
+
+            client.graceful_stop().await;
+            assert_eq!(Some(false), rx.recv().await);
+        });
+    }
+
+    /// Reports the kind of each I/O exception
+    struct ExceptionHandler {
+        tx: LocalSender<ErrorKind>,
+    }
+
+    impl Handler for ExceptionHandler {
+        type Rin = TaggedBytesMut;
+        type Rout = Self::Rin;
+        type Win = TaggedBytesMut;
+        type Wout = Self::Win;
+
+        fn name(&self) -> &str {
+            "ExceptionHandler"
+        }
+
+        fn handle_read(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            msg: Self::Rin,
+        ) {
+            ctx.fire_read(msg);
+        }
+
+        fn handle_exception(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            err: Box<dyn std::error::Error>,
+        ) {
+            if let Some(err) = err.downcast_ref::<std::io::Error>() {
+                let _ = self.tx.send(err.kind());
+            }
+        }
+
+        fn poll_write(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) -> Option<Self::Wout> {
+            ctx.fire_poll_write()
+        }
+    }
+
+    #[test]
+    fn test_reconnect_buffered_writes_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, mut rx) = channel();
+            let (exception_tx, mut exception_rx) = channel();
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = listener.local_addr().unwrap();
+
+            let mut client = BootstrapTcpClient::new();
+            client
+                .reconnect(ReconnectPolicy {
+                    initial_backoff: Duration::from_millis(100),
+                    jitter: 0.0,
+                    max_attempts: Some(1),
+                    max_buffered_writes: 1,
+                    ..Default::default()
+                })
+                .pipeline(Box::new(move || {
+                    let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                    pipeline.add_back(ActiveHandler { tx: tx.clone() });
+                    pipeline.add_back(ExceptionHandler {
+                        tx: exception_tx.clone(),
+                    });
+                    pipeline.finalize()
+                }));
+            let pipeline = client.connect(server_addr).await.unwrap();
+            let write = |message: &str| {
+                pipeline.write(TaggedBytesMut {
+                    now: Instant::now(),
+                    transport: TransportContext {
+                        protocol: Protocol::TCP,
+                        ..Default::default()
+                    },
+                    message: BytesMut::from(message),
+                });
+            };
+
+            let (server, _) = listener.accept().await.unwrap();
+            assert_eq!(Some(true), rx.recv().await);
+            drop(server);
+            assert_eq!(Some(false), rx.recv().await);
+
+            // one write is buffered until reconnected, the one beyond the limit is dropped
+            write("hello");
+            write("dropped");
+            assert_eq!(Some(ErrorKind::NotConnected), exception_rx.recv().await);
+            let (mut server, _) = listener.accept().await.unwrap();
+            assert_eq!(Some(true), rx.recv().await);
+            let mut buf = [0u8; 5];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(b"hello", &buf);
+
+            // once the client gives up, writes are rejected
+            drop(listener);
+            drop(server);
+            assert_eq!(Some(false), rx.recv().await);
+            smol::Timer::after(Duration::from_millis(300)).await;
+            write("rejected");
+            assert_eq!(Some(ErrorKind::NotConnected), exception_rx.recv().await);
+
+            client.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_reconnect_policy_backoff() {
+        let policy = ReconnectPolicy::default();
+        for attempt in [1, 10, 100, usize::MAX] {
+            assert!(policy.backoff(attempt) <= policy.max_backoff);
+        }
+
+        // a backoff beyond the range of Duration is capped instead of panicking
+        let policy = ReconnectPolicy {
+            max_backoff: Duration::MAX,
+            multiplier: f64::MAX,
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(Duration::MAX, policy.backoff(3));
+    }
+
+    #[test]
+    fn test_multiple_connections_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = listener.local_addr().unwrap();
+
+            let mut client = BootstrapTcpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.finalize()
+            }));
+            let first = client.open(server_addr).await.unwrap();
+            let (mut first_server, _) = listener.accept().await.unwrap();
+            let second = client.open(server_addr).await.unwrap();
+            let (mut second_server, _) = listener.accept().await.unwrap();
+
+            // closing one connection leaves the other one open
+            first.graceful_close().await;
+            let mut received = vec![];
+            first_server.read_to_end(&mut received).await.unwrap();
+            second.pipeline().write(TaggedBytesMut {
+                now: Instant::now(),
+                transport: TransportContext {
+                    protocol: Protocol::TCP,
+                    ..Default::default()
+                },
+                message: BytesMut::from("hello"),
+            });
+            let mut buf = [0u8; 5];
+            second_server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(b"hello", &buf);
+
+            // stopping the client closes all connections
+            client.graceful_stop().await;
+            second_server.read_to_end(&mut received).await.unwrap();
+            assert!(received.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_connection_pool_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, mut rx) = channel();
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = listener.local_addr().unwrap();
+
+            let mut client = BootstrapTcpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(ActiveHandler { tx: tx.clone() });
+                pipeline.finalize()
+            }));
+            let mut pool = TcpConnectionPool::new(client);
+            pool.idle_timeout(Duration::from_secs(1));
+
+            // a checked in connection is reused
+            let first = pool.checkout(server_addr).await.unwrap();
+            let (server, _) = listener.accept().await.unwrap();
+            assert_eq!(Some(true), rx.recv().await);
+            pool.checkin(Rc::clone(&first));
+            assert_eq!(1, pool.idle_count(server_addr));
+            let second = pool.checkout(server_addr).await.unwrap();
+            assert!(Rc::ptr_eq(&first, &second));
+
+            // an inactive connection isn't reused
+            drop(server);
+            assert_eq!(Some(false), rx.recv().await);
+            pool.checkin(second);
+            assert_eq!(0, pool.idle_count(server_addr));
+            let third = pool.checkout(server_addr).await.unwrap();
+            assert!(!Rc::ptr_eq(&first, &third));
+            let (_server, _) = listener.accept().await.unwrap();
+            assert_eq!(Some(true), rx.recv().await);
+
+            // idle connections are evicted after idle timeout
+            pool.checkin(third);
+            let mut eto = Instant::now() + Duration::from_secs(60);
+            pool.poll_timeout(&mut eto);
+            assert!(eto <= Instant::now() + Duration::from_secs(1));
+            pool.handle_timeout(eto);
+            assert_eq!(0, pool.idle_count(server_addr));
+            assert_eq!(Some(false), rx.recv().await);
+
+            pool.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_connection_pool_min_idle_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, mut rx) = channel();
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server_addr = listener.local_addr().unwrap();
+
+            let mut client = BootstrapTcpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(ActiveHandler { tx: tx.clone() });
+                pipeline.finalize()
+            }));
+            let mut pool = TcpConnectionPool::new(client);
+            pool.min_idle(1);
+
+            // a connection checked out and never checked in is released once inactive
+            let leaked = pool.checkout(server_addr).await.unwrap();
+            let (server, _) = listener.accept().await.unwrap();
+            assert_eq!(Some(true), rx.recv().await);
+            drop(server);
+            assert_eq!(Some(false), rx.recv().await);
+
+            // handle_timeout opens connections up to min_idle
+            pool.handle_timeout(Instant::now());
+            let (_server, _) = listener.accept().await.unwrap();
+            assert_eq!(Some(true), rx.recv().await);
+            while pool.idle_count(server_addr) == 0 {
+                smol::Timer::after(Duration::from_millis(10)).await;
+            }
+            assert_eq!(1, pool.idle_count(server_addr));
+
+            // idle connections up to min_idle aren't evicted
+            let mut eto = Instant::now() + Duration::from_secs(60);
+            pool.poll_timeout(&mut eto);
+            pool.handle_timeout(eto);
+            assert_eq!(1, pool.idle_count(server_addr));
+
+            pool.checkin(leaked);
+            assert_eq!(1, pool.idle_count(server_addr));
+
+            pool.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_max_connections_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, mut rx) = channel();
+
+            let mut server = BootstrapTcpServer::new();
+            server
+                .max_connections(1)
+                .accept_event(Box::new(move |event| {
+                    let _ = tx.send(match event {
+                        AcceptEvent::Accepted(_) => "accepted",
+                        AcceptEvent::Rejected(_) => "rejected",
+                        AcceptEvent::Failed { .. } => "failed",
+                        AcceptEvent::Paused => "paused",
+                        AcceptEvent::Resumed => "resumed",
+                    });
+                }))
+                .pipeline(Box::new(move || {
+                    let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                    pipeline.finalize()
+                }));
+            let server_addr = server.bind("127.0.0.1:0").await.unwrap();
+
+            let first = TcpStream::connect(server_addr).await.unwrap();
+            assert_eq!(Some("accepted"), rx.recv().await);
+            assert_eq!(Some("paused"), rx.recv().await);
+
+            // the second connection waits in the backlog until the first one is closed
+            let _second = TcpStream::connect(server_addr).await.unwrap();
+            drop(first);
+            assert_eq!(Some("resumed"), rx.recv().await);
+            assert_eq!(Some("accepted"), rx.recv().await);
+            assert_eq!(Some("paused"), rx.recv().await);
+
+            server.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_on_accept_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, mut rx) = channel();
+            let accepts = Cell::new(0);
+
+            // rejects the first connection only
+            let mut server = BootstrapTcpServer::new();
+            server
+                .on_accept(Box::new(move |_peer_addr, _local_addr| {
+                    accepts.set(accepts.get() + 1);
+                    if accepts.get() == 1 {
+                        AcceptDecision::Reject
+                    } else {
+                        AcceptDecision::Accept
+                    }
+                }))
+                .pipeline_with_context(Box::new(move |transport| {
+                    let _ = tx.send(transport.peer_addr);
+                    let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                    pipeline.finalize()
+                }));
+            let server_addr = server.bind("127.0.0.1:0").await.unwrap();
+
+            // a rejected connection is closed without any pipeline
+            let mut rejected = TcpStream::connect(server_addr).await.unwrap();
+            let mut received = vec![];
+            let _ = rejected.read_to_end(&mut received).await;
+            assert!(received.is_empty());
+
+            // an accepted connection gets a pipeline for its peer
+            let accepted = TcpStream::connect(server_addr).await.unwrap();
+            assert_eq!(Some(accepted.local_addr().unwrap()), rx.recv().await);
+
+            server.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_graceful_stop_with_timeout_tcp() {
+        for close_on_drain in [true, false] {
+            LocalExecutorBuilder::default().run(async {
+                let (tx, mut rx) = channel();
+
+                let mut server = BootstrapTcpServer::new();
+                server.pipeline(Box::new(move || {
+                    let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                    pipeline.add_back(GoodbyeHandler {
+                        tx: tx.clone(),
+                        close_on_drain,
+                        transport: None,
+                        transmits: VecDeque::new(),
+                    });
+                    pipeline.finalize()
+                }));
+                let server_addr = server.bind("127.0.0.1:0").await.unwrap();
+
+                let mut client = TcpStream::connect(server_addr).await.unwrap();
+                client.write_all(b"hello").await.unwrap();
+                assert_eq!(Some(()), rx.recv().await);
+
+                // a pipeline which doesn't close on drain is closed after the timeout
+                let start = Instant::now();
+                server
+                    .graceful_stop_with_timeout(Duration::from_millis(500))
+                    .await;
+                assert_eq!(
+                    !close_on_drain,
+                    start.elapsed() >= Duration::from_millis(500)
+                );
+                // the drain doesn't write a close event to the pipeline
+                assert!(rx.try_recv().is_err());
+
+                // the goodbye is flushed before the connection is closed
+                let mut received = vec![];
+                client.read_to_end(&mut received).await.unwrap();
+                assert_eq!(b"bye".to_vec(), received);
+            });
+        }
+    }
+
+    #[test]
+    fn test_multiple_listeners_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, mut rx) = channel();
+
+            let mut server = BootstrapTcpServer::new();
+            server.pipeline_with_context(Box::new(move |transport| {
+                let _ = tx.send(transport.local_addr);
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.finalize()
+            }));
+            let first_addr = server.bind("127.0.0.1:0").await.unwrap();
+            let second_addr = server.bind("127.0.0.1:0").await.unwrap();
+            assert_ne!(first_addr, second_addr);
+
+            // each connection reports the listener which accepted it
+            let _first = TcpStream::connect(first_addr).await.unwrap();
+            assert_eq!(Some(first_addr), rx.recv().await);
+            let _second = TcpStream::connect(second_addr).await.unwrap();
+            assert_eq!(Some(second_addr), rx.recv().await);
+
+            // stop closes all listeners
+            server.graceful_stop().await;
+            assert!(TcpStream::connect(first_addr).await.is_err());
+            assert!(TcpStream::connect(second_addr).await.is_err());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_listen_fds_invalid() {
+        // the environment is set up by the parent, as listen_fds must run before other threads
+        match std::env::var("RETTY_LISTEN_FDS_CHILD").as_deref() {
+            Ok("overflow") => {
+                assert_eq!(ErrorKind::InvalidInput, listen_fds().unwrap_err().kind());
+                assert!(std::env::var_os("LISTEN_FDS").is_none());
+                return;
+            }
+            Ok("closed") => {
+                // the open descriptor is neither changed nor closed
+                assert!(listen_fds().is_err());
+                assert!(std::env::var_os("LISTEN_FDS").is_none());
+                assert_eq!(0, unsafe { libc::fcntl(3, libc::F_GETFD) });
+                return;
+            }
+            _ => {}
+        }
+
+        // the end of the descriptor range overflows, or the range runs into closed descriptors
+        for (fds, case) in [
+            (i32::MAX.to_string(), "overflow"),
+            ("2".to_string(), "closed"),
+        ] {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut child = spawn_activated(
+                listener,
+                &fds,
+                "tests::test_listen_fds_invalid",
+                ("RETTY_LISTEN_FDS_CHILD", case),
+            );
+            assert!(child.wait().unwrap().success());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_activation_tcp() {
+        if std::env::var_os("RETTY_SOCKET_ACTIVATION_CHILD").is_some() {
+            // the child answers one request on the inherited listener, then exits
+            LocalExecutorBuilder::default().run(async {
+                let mut fds = listen_fds().unwrap();
+                assert_eq!(1, fds.len());
+                assert!(std::env::var_os("LISTEN_FDS").is_none());
+
+                let (tx, mut rx) = channel();
+                let listener = std::net::TcpListener::from(fds.remove(0));
+                let mut server = BootstrapTcpServer::from_std_listener(listener).unwrap();
+                server.allow_half_close(true).pipeline(Box::new(move || {
+                    let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                    pipeline.add_back(ActiveHandler { tx: tx.clone() });
+                    pipeline.add_back(RequestResponseHandler::new());
+                    pipeline.finalize()
+                }));
+                server.listen().await.unwrap();
+
+                assert_eq!(Some(true), rx.recv().await);
+                assert_eq!(Some(false), rx.recv().await);
+                server.graceful_stop().await;
+            });
+            return;
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_addr = listener.local_addr().unwrap();
+        let mut child = spawn_activated(
+            listener,
+            "1",
+            "tests::test_socket_activation_tcp",
+            ("RETTY_SOCKET_ACTIVATION_CHILD", "1"),
+        );
+
+        assert_eq!(b"response to request".to_vec(), request(server_addr));
+        assert!(child.wait().unwrap().success());
+    }
+
+    /// Runs the test in a child process with the listener passed as fd 3 like systemd, `LISTEN_FDS`
+    /// set to `listen_fds`, and `env` set to tell the child apart
+    #[cfg(unix)]
+    fn spawn_activated(
+        listener: std::net::TcpListener,
+        listen_fds: &str,
+        test: &str,
+        env: (&str, &str),
+    ) -> std::process::Child {
+        // LISTEN_PID is the pid kept by exec
+        let fd = listener.as_raw_fd();
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(r#"export LISTEN_PID=$$; exec "$0" "$@""#)
+            .arg(std::env::current_exe().unwrap())
+            .args(["--exact", test, "--nocapture", "--test-threads=1"])
+            .env("LISTEN_FDS", listen_fds)
+            .env(env.0, env.1);
+        unsafe {
+            command.pre_exec(move || {
+                let ret = if fd == 3 {
+                    libc::fcntl(fd, libc::F_SETFD, 0)
+                } else {
+                    libc::dup2(fd, 3)
+                };
+                if ret < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        command.spawn().unwrap()
+    }
+
+    /// Sends a request, half-closes and returns the response
+    #[cfg(unix)]
+    fn request(server_addr: std::net::SocketAddr) -> Vec<u8> {
+        let mut client = std::net::TcpStream::connect(server_addr).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        std::io::Write::write_all(&mut client, b"request").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let mut response = vec![];
+        std::io::Read::read_to_end(&mut client, &mut response).unwrap();
+        response
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_accept_error_backoff_tcp() {
+        if std::env::var_os("RETTY_ACCEPT_ERROR_CHILD").is_some() {
+            // the child runs out of descriptors, so that accepting fails with EMFILE
+            LocalExecutorBuilder::default().run(async {
+                let listener = std::net::TcpListener::from(listen_fds().unwrap().remove(0));
+                let (tx, mut rx) = channel();
+                let (event_tx, mut event_rx) = channel();
+                let mut server = BootstrapTcpServer::from_std_listener(listener).unwrap();
+                server
+                    .allow_half_close(true)
+                    .accept_event(Box::new(move |event| {
+                        if let AcceptEvent::Failed { error, retry_in } = event {
+                            let _ = event_tx.send((error.raw_os_error(), retry_in.is_some()));
+                        }
+                    }))
+                    .pipeline(Box::new(move || {
+                        let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                        pipeline.add_back(ActiveHandler { tx: tx.clone() });
+                        pipeline.add_back(RequestResponseHandler::new());
+                        pipeline.finalize()
+                    }));
+
+                // a few descriptors above the ones in use are left to the process
+                let probe = std::fs::File::open("/dev/null").unwrap();
+                let mut limit = unsafe { std::mem::zeroed::<libc::rlimit>() };
+                assert_eq!(0, unsafe {
+                    libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit)
+                });
+                limit.rlim_cur = probe.as_raw_fd() as libc::rlim_t + 8;
+                assert_eq!(0, unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) });
+                let mut files = vec![probe];
+                while let Ok(file) = std::fs::File::open("/dev/null") {
+                    files.push(file);
+                }
+                server.listen().await.unwrap();
+
+                // the pending connection is accepted once descriptors are free again
+                assert_eq!(Some((Some(libc::EMFILE), true)), event_rx.recv().await);
+                drop(files);
+                assert_eq!(Some(true), rx.recv().await);
+                assert_eq!(Some(false), rx.recv().await);
+
+                // the listener keeps accepting
+                assert_eq!(Some(true), rx.recv().await);
+                assert_eq!(Some(false), rx.recv().await);
+                server.graceful_stop().await;
+            });
+            return;
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_addr = listener.local_addr().unwrap();
+        let mut child = spawn_activated(
+            listener,
+            "1",
+            "tests::test_accept_error_backoff_tcp",
+            ("RETTY_ACCEPT_ERROR_CHILD", "1"),
+        );
+
+        assert_eq!(b"response to request".to_vec(), request(server_addr));
+        assert_eq!(b"response to request".to_vec(), request(server_addr));
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_ip_filter_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, mut rx) = channel();
+
+            let filter = IpFilter::new(
+                vec![IpFilterRule::deny("127.0.0.0/8".parse().unwrap())],
+                IpFilterAction::Allow,
+            );
+            let mut server = BootstrapTcpServer::new();
+            let server_filter = filter.clone();
+            server.pipeline_with_context(Box::new(move |transport| {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(IpFilterHandler::with_peer_addr(
+                    server_filter.clone(),
+                    transport.peer_addr,
+                ));
+                pipeline.add_back(ActiveHandler { tx: tx.clone() });
+                pipeline.finalize()
+            }));
+            let server_addr = server.bind("127.0.0.1:0").await.unwrap();
+
+            // a denied connection is closed without transport_active
+            let mut denied = TcpStream::connect(server_addr).await.unwrap();
+            let mut received = vec![];
+            denied.read_to_end(&mut received).await.unwrap();
+            assert!(received.is_empty());
+
+            filter.set_rules(vec![], IpFilterAction::Allow);
+            let _allowed = TcpStream::connect(server_addr).await.unwrap();
+            assert_eq!(Some(true), rx.recv().await);
+
+            server.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_traffic_shaping_read_tcp() {
+        LocalExecutorBuilder::default().run(async {
+            const READ_BYTES_PER_SEC: usize = 64 * 1024;
+
+            let mut server = BootstrapTcpServer::new();
+            server.allow_half_close(true).pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                let mut traffic_shaping =
+                    TrafficShapingHandler::new(Some(READ_BYTES_PER_SEC as u64), None);
+                // a second held back read would close the connection if reads weren't paused
+                traffic_shaping.max_pending_reads(1);
+                pipeline.add_back(traffic_shaping);
+                pipeline.add_back(RequestResponseHandler::new());
+                pipeline.finalize()
+            }));
+            let server_addr = server.bind("127.0.0.1:0").await.unwrap();
+
+            let start = Instant::now();
+            let request: Vec<u8> = (0..READ_BYTES_PER_SEC * 5 / 2)
+                .map(|i| (i % 251) as u8)
+                .collect();
+            let mut client = TcpStream::connect(server_addr).await.unwrap();
+            client.write_all(&request).await.unwrap();
+            client.shutdown(Shutdown::Write).unwrap();
+            let mut received = vec![];
+            client.read_to_end(&mut received).await.unwrap();
+
+            let mut response = b"response to ".to_vec();
+            response.extend_from_slice(&request);
+            assert_eq!(response.len(), received.len());
+            assert!(response == received);
+            assert!(start.elapsed() >= Duration::from_millis(1400));
+
+            server.graceful_stop().await;
+        });
+    }
+}
@@ -0,0 +1,556 @@
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use local_sync::mpsc::{unbounded::channel, unbounded::Tx as LocalSender};
+    use std::any::Any;
+    use std::collections::VecDeque;
+    use std::error::Error;
+    use std::io::ErrorKind;
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::time::{Duration, Instant};
+
+    use retty::bootstrap::{BootstrapUdpClient, BootstrapUdpServer, DrainEvent};
+    use retty::channel::{Context, Handler, Pipeline};
+    use retty::executor::LocalExecutorBuilder;
+    use retty::transport::{Protocol, TaggedBytesMut, TransportContext};
+
+    const MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 0, 1);
+
+    struct ReceiveHandler {
+        tx: LocalSender<BytesMut>,
+        exception_tx: Option<LocalSender<ErrorKind>>,
+        echo: bool,
+        transmits: VecDeque<TaggedBytesMut>,
+    }
+
+    impl ReceiveHandler {
+        fn new(
+            tx: LocalSender<BytesMut>,
+            exception_tx: Option<LocalSender<ErrorKind>>,
+            echo: bool,
+        ) -> Self {
+            Self {
+                tx,
+                exception_tx,
+                echo,
+                transmits: VecDeque::new(),
+            }
+        }
+    }
+
+    impl Handler for ReceiveHandler {
+        type Rin = TaggedBytesMut;
+        type Rout = Self::Rin;
+        type Win = TaggedBytesMut;
+        type Wout = Self::Win;
+
+        fn name(&self) -> &str {
+            "ReceiveHandler"
+        }
+
+        fn handle_read(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            msg: Self::Rin,
+        ) {
+            if self.echo {
+                self.transmits.push_back(TaggedBytesMut {
+                    now: Instant::now(),
+                    transport: msg.transport,
+                    message: msg.message.clone(),
+                });
+            }
+            let _ = self.tx.send(msg.message);
+        }
+
+        fn handle_exception(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            err: Box<dyn Error>,
+        ) {
+            if let (Some(tx), Some(err)) =
+                (&self.exception_tx, err.downcast_ref::<std::io::Error>())
+            {
+                let _ = tx.send(err.kind());
+            }
+        }
+
+        fn poll_write(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) -> Option<Self::Wout> {
+            if let Some(msg) = ctx.fire_poll_write() {
+                self.transmits.push_back(msg);
+            }
+            self.transmits.pop_front()
+        }
+    }
+
+    /// Reports the transport context of each inbound datagram
+    struct TransportHandler {
+        tx: LocalSender<(Instant, TransportContext)>,
+    }
+
+    impl Handler for TransportHandler {
+        type Rin = TaggedBytesMut;
+        type Rout = Self::Rin;
+        type Win = TaggedBytesMut;
+        type Wout = Self::Win;
+
+        fn name(&self) -> &str {
+            "TransportHandler"
+        }
+
+        fn handle_read(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            msg: Self::Rin,
+        ) {
+            let _ = self.tx.send((msg.now, msg.transport));
+            ctx.fire_read(msg);
+        }
+
+        fn poll_write(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) -> Option<Self::Wout> {
+            ctx.fire_poll_write()
+        }
+    }
+
+    #[test]
+    fn test_multicast_udp() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, mut rx) = channel();
+
+            let mut server = BootstrapUdpServer::new();
+            server.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(ReceiveHandler::new(tx.clone(), None, false));
+                pipeline.finalize()
+            }));
+            server
+                .join_multicast_v4(MULTICAST_ADDR, Ipv4Addr::LOCALHOST)
+                .unwrap();
+            let server_addr = server.bind("0.0.0.0:0").await.unwrap();
+
+            let mut client = BootstrapUdpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.finalize()
+            }));
+            let client_addr = client.bind("127.0.0.1:0").await.unwrap();
+            client.set_multicast_if_v4(Ipv4Addr::LOCALHOST).unwrap();
+            client.set_multicast_loop_v4(true).unwrap();
+            client.set_multicast_ttl_v4(1).unwrap();
+
+            let group_addr = SocketAddr::new(MULTICAST_ADDR.into(), server_addr.port());
+            let pipeline = client.connect(group_addr).await.unwrap();
+            pipeline.write(TaggedBytesMut {
+                now: Instant::now(),
+                transport: TransportContext {
+                    local_addr: client_addr,
+                    peer_addr: group_addr,
+                    ecn: None,
+                    protocol: Protocol::UDP,
+                    interface_index: None,
+                },
+                message: BytesMut::from("hello"),
+            });
+
+            assert_eq!(Some(BytesMut::from("hello")), rx.recv().await);
+
+            client.graceful_stop().await;
+            server.graceful_stop().await;
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_gso_batched_udp() {
+        use std::os::fd::AsRawFd;
+
+        LocalExecutorBuilder::default().run(async {
+            // with UDP_GRO, a GSO send over loopback is received as one read of all segments
+            let peer = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            let enable: libc::c_int = 1;
+            let ret = unsafe {
+                libc::setsockopt(
+                    peer.as_raw_fd(),
+                    libc::SOL_UDP,
+                    libc::UDP_GRO,
+                    &enable as *const _ as *const libc::c_void,
+                    std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+                )
+            };
+            assert_eq!(0, ret);
+            peer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let peer_addr = peer.local_addr().unwrap();
+
+            let mut client = BootstrapUdpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.finalize()
+            }));
+            client.bind("127.0.0.1:0").await.unwrap();
+            let pipeline = client.connect(peer_addr).await.unwrap();
+
+            // transmits queued before the loop wakes up are sent together
+            for message in ["aaaa", "bbbb", "cccc", "dddd"] {
+                pipeline.write(TaggedBytesMut {
+                    now: Instant::now(),
+                    transport: TransportContext::default(),
+                    message: BytesMut::from(message),
+                });
+            }
+
+            let received = smol::unblock(move || {
+                let mut buf = vec![0u8; 65536];
+                let n = peer.recv(&mut buf).unwrap();
+                buf.truncate(n);
+                buf
+            })
+            .await;
+            assert_eq!(b"aaaabbbbccccdddd".to_vec(), received);
+
+            client.graceful_stop().await;
+        });
+    }
+
+    /// Echoes each datagram, and on drain writes a goodbye to the last peer and closes
+    struct DrainHandler {
+        transport: Option<TransportContext>,
+        transmits: VecDeque<TaggedBytesMut>,
+    }
+
+    impl Handler for DrainHandler {
+        type Rin = TaggedBytesMut;
+        type Rout = Self::Rin;
+        type Win = TaggedBytesMut;
+        type Wout = Self::Win;
+
+        fn name(&self) -> &str {
+            "DrainHandler"
+        }
+
+        fn handle_read(
+            &mut self,
+            _ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            msg: Self::Rin,
+        ) {
+            self.transport = Some(msg.transport);
+            self.transmits.push_back(msg);
+        }
+
+        fn handle_event(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+            evt: Box<dyn Any>,
+        ) {
+            if evt.downcast_ref::<DrainEvent>().is_none() {
+                ctx.fire_event(evt);
+                return;
+            }
+            if let Some(transport) = self.transport {
+                self.transmits.push_back(TaggedBytesMut {
+                    now: Instant::now(),
+                    transport,
+                    message: BytesMut::from("bye"),
+                });
+            }
+            ctx.fire_close();
+        }
+
+        fn poll_write(
+            &mut self,
+            ctx: &Context<Self::Rin, Self::Rout, Self::Win, Self::Wout>,
+        ) -> Option<Self::Wout> {
+            if let Some(msg) = ctx.fire_poll_write() {
+                self.transmits.push_back(msg);
+            }
+            self.transmits.pop_front()
+        }
+    }
+
+    #[test]
+    fn test_graceful_stop_with_timeout_udp() {
+        LocalExecutorBuilder::default().run(async {
+            let (client_tx, mut client_rx) = channel();
+
+            let mut server = BootstrapUdpServer::new();
+            server.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(DrainHandler {
+                    transport: None,
+                    transmits: VecDeque::new(),
+                });
+                pipeline.finalize()
+            }));
+            let server_addr = server.bind("127.0.0.1:0").await.unwrap();
+
+            let mut client = BootstrapUdpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(ReceiveHandler::new(client_tx.clone(), None, false));
+                pipeline.finalize()
+            }));
+            client.bind("127.0.0.1:0").await.unwrap();
+            let pipeline = client.connect(server_addr).await.unwrap();
+            let write = |message: &str| {
+                pipeline.write(TaggedBytesMut {
+                    now: Instant::now(),
+                    transport: TransportContext::default(),
+                    message: BytesMut::from(message),
+                })
+            };
+
+            write("hello");
+            assert_eq!(Some(BytesMut::from("hello")), client_rx.recv().await);
+
+            // the socket is served until the handler writes a goodbye and closes on drain
+            let start = Instant::now();
+            server
+                .graceful_stop_with_timeout(Duration::from_secs(10))
+                .await;
+            assert!(start.elapsed() < Duration::from_secs(10));
+            assert_eq!(Some(BytesMut::from("bye")), client_rx.recv().await);
+
+            client.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_truncated_udp_dropped() {
+        LocalExecutorBuilder::default().run(async {
+            let (server_tx, mut server_rx) = channel();
+
+            let mut server = BootstrapUdpServer::new();
+            server.max_payload_size(16);
+            server.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(ReceiveHandler::new(server_tx.clone(), None, false));
+                pipeline.finalize()
+            }));
+            let server_addr = server.bind("127.0.0.1:0").await.unwrap();
+
+            // larger than a read even with GRO, so it is cut off and must not reach the pipeline
+            let client = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            client.send_to(&[0u8; 8192], server_addr).unwrap();
+            client.send_to(b"hello", server_addr).unwrap();
+            assert_eq!(Some(BytesMut::from("hello")), server_rx.recv().await);
+
+            server.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_connected_udp() {
+        LocalExecutorBuilder::default().run(async {
+            let (server_tx, mut server_rx) = channel();
+            let (client_tx, mut client_rx) = channel();
+
+            let mut server = BootstrapUdpServer::new();
+            server.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(ReceiveHandler::new(server_tx.clone(), None, true));
+                pipeline.finalize()
+            }));
+            let server_addr = server.bind("127.0.0.1:0").await.unwrap();
+
+            let mut client = BootstrapUdpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(ReceiveHandler::new(client_tx.clone(), None, false));
+                pipeline.finalize()
+            }));
+            let client_addr = client.bind("127.0.0.1:0").await.unwrap();
+            let pipeline = client.connect(server_addr).await.unwrap();
+
+            // datagrams from other peers never reach the pipeline
+            let stranger = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            stranger.send_to(b"stranger", client_addr).unwrap();
+
+            // outbound destination defaults to the connected peer
+            pipeline.write(TaggedBytesMut {
+                now: Instant::now(),
+                transport: TransportContext::default(),
+                message: BytesMut::from("hello"),
+            });
+            assert_eq!(Some(BytesMut::from("hello")), server_rx.recv().await);
+            assert_eq!(Some(BytesMut::from("hello")), client_rx.recv().await);
+
+            client.graceful_stop().await;
+            server.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_connected_udp_port_unreachable() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, _rx) = channel();
+            let (exception_tx, mut exception_rx) = channel();
+
+            // a port with nobody listening on it
+            let peer_addr = {
+                let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+                socket.local_addr().unwrap()
+            };
+
+            let mut client = BootstrapUdpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(ReceiveHandler::new(
+                    tx.clone(),
+                    Some(exception_tx.clone()),
+                    false,
+                ));
+                pipeline.finalize()
+            }));
+            client.bind("127.0.0.1:0").await.unwrap();
+            let pipeline = client.connect(peer_addr).await.unwrap();
+
+            pipeline.write(TaggedBytesMut {
+                now: Instant::now(),
+                transport: TransportContext::default(),
+                message: BytesMut::from("hello"),
+            });
+            assert_eq!(
+                Some(ErrorKind::ConnectionRefused),
+                exception_rx.recv().await
+            );
+
+            client.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_connect_udp_errors() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, _rx) = channel();
+            let peer_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 9));
+
+            let mut client = BootstrapUdpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(ReceiveHandler::new(tx.clone(), None, false));
+                pipeline.finalize()
+            }));
+
+            // nothing bound yet
+            let err = client.connect(peer_addr).await.err().unwrap();
+            assert_eq!(ErrorKind::NotConnected, err.kind());
+
+            // the bound socket is served only once
+            client.bind("127.0.0.1:0").await.unwrap();
+            assert!(client.connect(peer_addr).await.is_ok());
+            let err = client.connect(peer_addr).await.err().unwrap();
+            assert_eq!(ErrorKind::AlreadyExists, err.kind());
+
+            client.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_udp_recv_destination_and_timestamp() {
+        LocalExecutorBuilder::default().run(async {
+            let (server_tx, _server_rx) = channel();
+            let (transport_tx, mut transport_rx) = channel();
+            let (client_tx, mut client_rx) = channel();
+
+            let mut server = BootstrapUdpServer::new();
+            server.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(TransportHandler {
+                    tx: transport_tx.clone(),
+                });
+                pipeline.add_back(ReceiveHandler::new(server_tx.clone(), None, true));
+                pipeline.finalize()
+            }));
+            server.set_recv_timestamps(true).unwrap();
+            let server_addr = server.bind("0.0.0.0:0").await.unwrap();
+
+            let mut client = BootstrapUdpClient::new();
+            client.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(ReceiveHandler::new(client_tx.clone(), None, false));
+                pipeline.finalize()
+            }));
+            client.bind("127.0.0.1:0").await.unwrap();
+            let peer_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), server_addr.port());
+            let pipeline = client.connect(peer_addr).await.unwrap();
+
+            let sent = Instant::now();
+            pipeline.write(TaggedBytesMut {
+                now: sent,
+                transport: TransportContext::default(),
+                message: BytesMut::from("hello"),
+            });
+
+            // the wildcard bind address is replaced with the actual destination
+            let (now, transport) = transport_rx.recv().await.unwrap();
+            assert_eq!(peer_addr, transport.local_addr);
+            // the loopback interface it arrived on
+            #[cfg(target_os = "linux")]
+            assert!(transport.interface_index.is_some_and(|index| index > 0));
+            assert!(now <= Instant::now());
+            assert!(sent.saturating_duration_since(now) < Duration::from_millis(100));
+
+            // the echo leaves from the destination address, so the connected client accepts it
+            assert_eq!(Some(BytesMut::from("hello")), client_rx.recv().await);
+
+            client.graceful_stop().await;
+            server.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_multiple_listeners_udp() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, mut rx) = channel();
+
+            let mut server = BootstrapUdpServer::new();
+            server.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(TransportHandler { tx: tx.clone() });
+                pipeline.finalize()
+            }));
+            let first_addr = server.bind("127.0.0.1:0").await.unwrap();
+            let second_addr = server.bind("127.0.0.1:0").await.unwrap();
+            assert_ne!(first_addr, second_addr);
+
+            // each datagram reports the socket which received it
+            let client = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            for server_addr in [first_addr, second_addr] {
+                client.send_to(b"hello", server_addr).unwrap();
+                let (_, transport) = rx.recv().await.unwrap();
+                assert_eq!(server_addr, transport.local_addr);
+            }
+
+            server.graceful_stop().await;
+        });
+    }
+
+    #[test]
+    fn test_from_std_socket_udp() {
+        LocalExecutorBuilder::default().run(async {
+            let (tx, mut rx) = channel();
+
+            let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            let mut server = BootstrapUdpServer::from_std_socket(socket).unwrap();
+            server.pipeline(Box::new(move || {
+                let pipeline: Pipeline<TaggedBytesMut, TaggedBytesMut> = Pipeline::new();
+                pipeline.add_back(TransportHandler { tx: tx.clone() });
+                pipeline.finalize()
+            }));
+            let server_addr = server.listen().await.unwrap();
+            assert!(server.listen().await.is_err());
+
+            let client = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            client.send_to(b"hello", server_addr).unwrap();
+            let (_, transport) = rx.recv().await.unwrap();
+            assert_eq!(server_addr, transport.local_addr);
+
+            server.graceful_stop().await;
+        });
+    }
+}